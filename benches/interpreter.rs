@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// Callbacks used to copy every variable on each call, so these got slower the more was defined.
+// `big` is never used by the callbacks and shouldn't make a difference
+const CALLS: [(&str, &str); 3] = [
+    ("map", "map(range(0, 10000), |x| x * 2)"),
+    ("filter", "filter(range(0, 10000), |x| x % 3 == 0)"),
+    ("fold", "fold(range(0, 10000), |acc, x| acc + x, 0)"),
+];
+
+fn callbacks(c: &mut Criterion) {
+    for (name, call) in CALLS {
+        let mut group = c.benchmark_group(name);
+        for size in [1, 1000, 10_000] {
+            let source = format!("let big = range(0, {}); {}", size, call);
+            group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+                b.iter(|| qcalc::run(source, |_| {}).unwrap())
+            });
+        }
+        group.finish();
+    }
+}
+
+fn recursion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fib");
+    for backend in ["tree", "vm"] {
+        let source = format!(
+            "config backend = \"{}\"; fn fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2); fib(15)",
+            backend
+        );
+        group.bench_with_input(
+            BenchmarkId::from_parameter(backend),
+            &source,
+            |b, source| b.iter(|| qcalc::run(source, |_| {}).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, callbacks, recursion);
+criterion_main!(benches);
@@ -12,6 +12,7 @@ use ratatui::{
 use tui_textarea::{Input, TextArea};
 
 use crate::{
+    format::Format,
     interpreter::{Interpreter, Stmt, Value},
     parse::{self, Parser},
     token::Tokenizer,
@@ -26,7 +27,11 @@ pub enum Popup {
 pub struct App<'ta> {
     pub input: TextArea<'ta>,
     pub output: Option<Value>,
+    // How the output is shown and copied
+    pub format: Format,
     pub err: Option<String>,
+    // Shown below the output, eg. for a match arm that's never reached
+    pub warnings: Vec<String>,
     pub interpreter: Interpreter,
     pub expr_history: Vec<Stmt>,
     pub expr_selector: usize,
@@ -35,6 +40,8 @@ pub struct App<'ta> {
     pub clipboard: Clipboard,
     should_save: bool,
     rc_file: PathBuf,
+    // Set when lines of the rc file couldn't be read, so it isn't overwritten without them
+    rc_failed: bool,
 }
 
 impl<'ta> App<'ta> {
@@ -49,7 +56,9 @@ impl<'ta> App<'ta> {
         let mut app = Self {
             input: textarea(None, None, None),
             output: None,
+            format: Format::default(),
             err: None,
+            warnings: vec![],
             clipboard,
             interpreter: Interpreter::new(),
             expr_history: Vec::new(),
@@ -58,6 +67,7 @@ impl<'ta> App<'ta> {
             popup: None,
             rc_file,
             should_save,
+            rc_failed: false,
         };
         app.run_commands(file);
         app
@@ -69,25 +79,31 @@ impl<'ta> App<'ta> {
         let mut buf = String::new();
         file.read_to_string(&mut buf)
             .expect("Failed to read from RC file");
-        buf.lines().for_each(|line| {
-            let mut tokenizer = Tokenizer::new(line.chars().peekable()).peekable();
-            if let Some(token) = tokenizer.next() {
-                let _ = Parser::new(tokenizer, token)
-                    .parse()
-                    .map(|res| self.interpreter.interpret(res));
-            }
-        });
+        let errors = run_rc(&mut self.interpreter, &buf);
+        if !errors.is_empty() {
+            self.rc_failed = true;
+            self.set_err(format!(
+                "{}\nThe rc file won't be saved until these lines are fixed",
+                errors.join("\n")
+            ));
+        }
     }
 
     fn update_rc(&mut self) {
+        if self.rc_failed {
+            return;
+        }
         let commands = self
             .interpreter
             .env()
             .iter()
-            .fold(Vec::new(), |mut acc, (string, val)| {
-                acc.push(val.to_input(string));
-                acc
-            })
+            .fold(
+                self.interpreter.settings().to_inputs(),
+                |mut acc, (string, val)| {
+                    acc.push(val.to_input(string));
+                    acc
+                },
+            )
             .join("\n");
         let file = OpenOptions::new()
             .read(true)
@@ -124,13 +140,16 @@ impl<'ta> App<'ta> {
     }
 
     pub fn eval(&mut self) {
-        let input = &self.input.lines()[0];
-        let mut tokenizer = Tokenizer::new(input.chars().peekable()).peekable();
-        if tokenizer.peek().is_none() {
+        let input = self.input.lines()[0].clone();
+        if input.trim().is_empty() {
             return;
         }
-        let current = tokenizer.next().unwrap();
-        match Parser::new(tokenizer, current).parse() {
+        let parsed = Parser::new(Tokenizer::new(&input)).and_then(|mut parser| {
+            let stmt = parser.parse();
+            self.warnings = parser.warnings(&input);
+            stmt
+        });
+        match parsed {
             Ok(stmt) => {
                 if !self.expr_history.contains(&stmt) {
                     self.expr_history.push(stmt.clone());
@@ -147,10 +166,10 @@ impl<'ta> App<'ta> {
                             self.update_rc();
                         }
                     }
-                    Err(err) => self.set_err(err.to_string()),
+                    Err(err) => self.set_err(err.report(&input)),
                 }
             }
-            Err(err) => self.set_err(err.to_string()),
+            Err(err) => self.set_err(err.report(&input)),
         };
     }
 
@@ -235,6 +254,21 @@ fn textarea<'a>(
     textarea
 }
 
+// Runs each line of an rc file on its own, so that one that doesn't parse, eg. after a name
+// became a keyword, doesn't lose the others. Gives the errors of the lines that didn't
+fn run_rc(interpreter: &mut Interpreter, rc: &str) -> Vec<String> {
+    let mut errors = vec![];
+    for (i, line) in rc.lines().enumerate() {
+        match Parser::new(Tokenizer::new(line)).and_then(|mut parser| parser.statements()) {
+            Ok(stmts) => stmts.into_iter().for_each(|(stmt, _)| {
+                let _ = interpreter.interpret(stmt);
+            }),
+            Err(err) => errors.push(format!("rc line {}: {}", i + 1, err.report(line))),
+        }
+    }
+    errors
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,6 +296,16 @@ mod tests {
         assert_eq!(*&app.output, Some(expected));
     }
 
+    #[test]
+    fn rc_lines_run_on_their_own() {
+        let mut interpreter = Interpreter::new();
+        let errors = run_rc(&mut interpreter, "let a = 1\nlet to = 3\nlet b = a + 1");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("rc line 2: "), "{}", errors[0]);
+        assert_eq!(interpreter.env().get("a"), Some(&Value::Int(1)));
+        assert_eq!(interpreter.env().get("b"), Some(&Value::Int(2)));
+    }
+
     #[test]
     fn create_and_call_function() {
         let mut app = new_app();
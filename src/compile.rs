@@ -0,0 +1,433 @@
+use num_complex::Complex64;
+
+use crate::{
+    datetime::Literal,
+    interpreter::{pattern, Stmt, Value},
+    parse::{Definition, Expr, Func, Part, Pattern},
+    token::Token,
+};
+
+// Instructions for the vm. Operands are indexes into the tables of the chunk, or the number of
+// values an instruction takes off the stack
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Const(usize),
+    // Float literals become decimals when the decimal setting is on, so that's left to the vm
+    Float(f64),
+    Load(usize),
+    // Defines a name as the value on top of the stack, which is left there
+    Define(usize),
+    Pop,
+    Binary(Token),
+    Unary(Token),
+    Quantity(usize),
+    Convert(usize),
+    List(usize),
+    Tuple(usize),
+    // Pops a value for each of the field names, and for Update the record before them
+    Record(usize),
+    Update(usize),
+    Field(usize),
+    // Pops the index and what's indexed
+    Index,
+    // Pops the parts of the slice that were given, then what's sliced
+    Slice([bool; 3]),
+    // Pops a value for each placeholder of the pattern in names
+    Interpolate(usize, usize),
+    // Pushes whether the value on top of the stack fits a pattern, defining what it binds
+    Match(usize),
+    // No arm of a match fit the value on top of the stack
+    Unmatched,
+    // Defines what a pattern from let binds, which the value on top of the stack has to fit
+    Unpack(usize),
+    Closure(usize),
+    Builtin(Func, usize),
+    // Checks that the value on top of the stack can be called with this many arguments, before
+    // they are evaluated
+    Callee(usize),
+    Call(usize),
+    // Replaces the running function instead of returning to it
+    TailCall(usize),
+    Return,
+    Jump(usize),
+    // Pops a condition and jumps if it's false
+    JumpUnless(usize),
+    // Blocks and lets get a scope which is dropped at the end
+    PushScope,
+    PopScope,
+    Undef(usize),
+    // Changes a setting to the value on top of the stack, which is left there
+    Config(usize),
+    Fns(usize),
+    Simplify(usize),
+    Unit,
+    // Rounds decimals and brings fixed width integers back into range like any other result
+    Normalize,
+}
+
+// The compiled code for a statement or function body
+#[derive(Debug, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    pub units: Vec<Vec<(String, i32)>>,
+    pub functions: Vec<(Vec<Pattern>, Expr)>,
+    pub groups: Vec<Vec<Definition>>,
+    pub undefs: Vec<Vec<String>>,
+    pub fields: Vec<Vec<String>>,
+    pub patterns: Vec<Pattern>,
+    // Expressions to show simplified
+    pub exprs: Vec<Expr>,
+}
+
+pub fn statement(stmt: &Stmt) -> Chunk {
+    let mut compiler = Compiler::default();
+    compiler.stmt(stmt);
+    compiler.finish()
+}
+
+pub fn expression(expr: &Expr) -> Chunk {
+    let mut compiler = Compiler::default();
+    compiler.expr(expr, false);
+    compiler.finish()
+}
+
+// Calls at the end of the body are compiled as tail calls
+pub fn function(body: &Expr) -> Chunk {
+    let mut compiler = Compiler::default();
+    compiler.expr(body, true);
+    compiler.finish()
+}
+
+#[derive(Default)]
+struct Compiler {
+    chunk: Chunk,
+}
+
+impl Compiler {
+    fn finish(mut self) -> Chunk {
+        self.emit(Op::Return);
+        self.chunk
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.code.push(op);
+        self.chunk.code.len() - 1
+    }
+
+    // Points the jump at the next instruction
+    fn patch(&mut self, jump: usize) {
+        let target = self.chunk.code.len();
+        match &mut self.chunk.code[jump] {
+            Op::Jump(to) | Op::JumpUnless(to) => *to = target,
+            op => unreachable!("Patching {:?}", op),
+        }
+    }
+
+    fn constant(&mut self, value: Value) {
+        self.chunk.constants.push(value);
+        self.emit(Op::Const(self.chunk.constants.len() - 1));
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        match self
+            .chunk
+            .names
+            .iter()
+            .position(|existing| existing == name)
+        {
+            Some(index) => index,
+            None => {
+                self.chunk.names.push(name.to_string());
+                self.chunk.names.len() - 1
+            }
+        }
+    }
+
+    // Leaves the values of the fields on the stack, returning where their names are
+    fn fields(&mut self, fields: &[(String, Expr)]) -> usize {
+        fields.iter().for_each(|(_, expr)| self.expr(expr, false));
+        self.chunk
+            .fields
+            .push(fields.iter().map(|(name, _)| name.clone()).collect());
+        self.chunk.fields.len() - 1
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.expr(expr, false);
+                let ans = self.name("ans");
+                self.emit(Op::Define(ans));
+            }
+            Stmt::Assign(name, expr) => {
+                self.expr(expr, false);
+                let name = self.name(name);
+                self.emit(Op::Define(name));
+            }
+            Stmt::Unpack(pattern, expr) => {
+                self.expr(expr, false);
+                self.chunk.patterns.push(pattern.clone());
+                self.emit(Op::Unpack(self.chunk.patterns.len() - 1));
+            }
+            Stmt::Undef(names) => {
+                self.chunk.undefs.push(names.clone());
+                self.emit(Op::Undef(self.chunk.undefs.len() - 1));
+            }
+            Stmt::Config(name, expr) => {
+                self.expr(expr, false);
+                let name = self.name(name);
+                self.emit(Op::Config(name));
+            }
+            Stmt::Seq(stmts) => self.stmts(stmts),
+            Stmt::Fn(definitions) => {
+                self.chunk.groups.push(definitions.clone());
+                self.emit(Op::Fns(self.chunk.groups.len() - 1));
+            }
+            Stmt::Simplify(expr) => {
+                self.chunk.exprs.push(expr.clone());
+                self.emit(Op::Simplify(self.chunk.exprs.len() - 1));
+            }
+        }
+    }
+
+    // Leaves the value of the last statement
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        if stmts.is_empty() {
+            self.emit(Op::Unit);
+        }
+        for (i, stmt) in stmts.iter().enumerate() {
+            if i > 0 {
+                self.emit(Op::Pop);
+            }
+            self.stmt(stmt);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, tail: bool) {
+        match expr {
+            Expr::Float(float) => {
+                self.emit(Op::Float(*float));
+            }
+            Expr::Int(int) => self.constant(Value::Int(*int)),
+            Expr::BigInt(int) => self.constant(Value::from(int.clone())),
+            Expr::Fixed(int, ty) => self.constant(Value::Fixed(int.clone(), *ty)),
+            Expr::Imaginary(im) => self.constant(Value::from(Complex64::new(0.0, *im))),
+            Expr::Decimal(decimal) => self.constant(Value::Decimal(*decimal)),
+            Expr::Time(Literal::Date(date)) => self.constant(Value::Date(*date)),
+            Expr::Time(Literal::DateTime(datetime)) => self.constant(Value::DateTime(*datetime)),
+            Expr::Time(Literal::Duration(duration)) => self.constant(Value::Duration(*duration)),
+            Expr::String(string) => self.constant(Value::String(string.clone())),
+            Expr::Bool(bool) => self.constant(Value::Bool(*bool)),
+            Expr::Nil => self.constant(Value::Nil),
+            Expr::Nan => self.constant(Value::NaN),
+            Expr::Var(name) => {
+                let name = self.name(name);
+                self.emit(Op::Load(name));
+            }
+            Expr::Quantity(expr, unit) => {
+                self.expr(expr, false);
+                self.chunk.units.push(unit.clone());
+                self.emit(Op::Quantity(self.chunk.units.len() - 1));
+            }
+            Expr::Convert(expr, unit) => {
+                self.expr(expr, false);
+                self.chunk.units.push(unit.clone());
+                self.emit(Op::Convert(self.chunk.units.len() - 1));
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expr(left, false);
+                self.expr(right, false);
+                self.emit(Op::Binary(operator.clone()));
+            }
+            Expr::Unary(expr, operator) => {
+                self.expr(expr, false);
+                self.emit(Op::Unary(operator.clone()));
+            }
+            Expr::Grouping(expr) => {
+                self.expr(expr, tail);
+                self.emit(Op::Normalize);
+            }
+            Expr::List(elems) => {
+                elems.iter().for_each(|elem| self.expr(elem, false));
+                self.emit(Op::List(elems.len()));
+            }
+            Expr::Tuple(elems) => {
+                elems.iter().for_each(|elem| self.expr(elem, false));
+                self.emit(Op::Tuple(elems.len()));
+            }
+            Expr::Record(fields) => {
+                let fields = self.fields(fields);
+                self.emit(Op::Record(fields));
+            }
+            Expr::Update(record, fields) => {
+                self.expr(record, false);
+                let fields = self.fields(fields);
+                self.emit(Op::Update(fields));
+            }
+            Expr::Field(record, name) => {
+                self.expr(record, false);
+                let name = self.name(name);
+                self.emit(Op::Field(name));
+            }
+            Expr::Index(expr, index) => {
+                self.expr(expr, false);
+                self.expr(index, false);
+                self.emit(Op::Index);
+            }
+            Expr::Slice(expr, bounds) => {
+                self.expr(expr, false);
+                bounds
+                    .iter()
+                    .flatten()
+                    .for_each(|bound| self.expr(bound, false));
+                self.emit(Op::Slice(bounds.each_ref().map(Option::is_some)));
+            }
+            Expr::Interpolated(parts) => {
+                let exprs = parts.iter().filter_map(Part::expr).collect::<Vec<_>>();
+                exprs.iter().for_each(|expr| self.expr(expr, false));
+                let pattern = self.name(&pattern(parts));
+                self.emit(Op::Interpolate(pattern, exprs.len()));
+            }
+            Expr::Fun(params, body) => {
+                self.chunk
+                    .functions
+                    .push((params.clone(), body.as_ref().clone()));
+                self.emit(Op::Closure(self.chunk.functions.len() - 1));
+            }
+            Expr::Func(func, args) => {
+                args.iter().for_each(|arg| self.expr(arg, false));
+                self.emit(Op::Builtin(func.clone(), args.len()));
+            }
+            Expr::Call(callee, args) => {
+                self.expr(callee, false);
+                self.emit(Op::Callee(args.len()));
+                args.iter().for_each(|arg| self.expr(arg, false));
+                if tail {
+                    self.emit(Op::TailCall(args.len()));
+                } else {
+                    self.emit(Op::Call(args.len()));
+                    self.emit(Op::Normalize);
+                }
+            }
+            Expr::If(cond, then, else_expr) => {
+                self.expr(cond, false);
+                let to_else = self.emit(Op::JumpUnless(0));
+                self.expr(then, tail);
+                let to_end = self.emit(Op::Jump(0));
+                self.patch(to_else);
+                self.expr(else_expr, tail);
+                self.patch(to_end);
+                self.emit(Op::Normalize);
+            }
+            Expr::Block(stmts) => {
+                self.emit(Op::PushScope);
+                self.stmts(stmts);
+                self.emit(Op::PopScope);
+                self.emit(Op::Normalize);
+            }
+            // Each arm gets a scope for what its pattern binds, the value is kept on the stack
+            // until one fits
+            Expr::Match(subject, arms) => {
+                self.expr(subject, false);
+                let mut ends = vec![];
+                for arm in arms {
+                    self.emit(Op::PushScope);
+                    self.chunk.patterns.push(arm.pattern.clone());
+                    self.emit(Op::Match(self.chunk.patterns.len() - 1));
+                    let mut skips = vec![self.emit(Op::JumpUnless(0))];
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard, false);
+                        skips.push(self.emit(Op::JumpUnless(0)));
+                    }
+                    self.emit(Op::Pop);
+                    self.expr(&arm.body, tail);
+                    self.emit(Op::PopScope);
+                    ends.push(self.emit(Op::Jump(0)));
+                    skips.into_iter().for_each(|skip| self.patch(skip));
+                    self.emit(Op::PopScope);
+                }
+                self.emit(Op::Unmatched);
+                ends.into_iter().for_each(|end| self.patch(end));
+                self.emit(Op::Normalize);
+            }
+            Expr::Let(bindings, body) => {
+                self.emit(Op::PushScope);
+                for (name, expr) in bindings {
+                    self.expr(expr, false);
+                    let name = self.name(name);
+                    self.emit(Op::Define(name));
+                    self.emit(Op::Pop);
+                }
+                self.expr(body, tail);
+                self.emit(Op::PopScope);
+                self.emit(Op::Normalize);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse::Parser, Tokenizer};
+
+    fn compile(input: &str) -> Chunk {
+        let stmt = Parser::new(Tokenizer::new(input)).unwrap().parse().unwrap();
+        statement(&stmt)
+    }
+
+    #[test]
+    fn compile_expressions() {
+        let chunk = compile("if x then 1 else y + 2");
+        assert_eq!(
+            chunk.code,
+            vec![
+                Op::Load(0),
+                Op::JumpUnless(4),
+                Op::Const(0),
+                Op::Jump(7),
+                Op::Load(1),
+                Op::Const(1),
+                Op::Binary(Token::Plus),
+                Op::Normalize,
+                Op::Define(2),
+                Op::Return,
+            ]
+        );
+        assert_eq!(chunk.names, vec!["x", "y", "ans"]);
+        assert_eq!(chunk.constants, vec![Value::Int(1), Value::Int(2)]);
+    }
+
+    #[test]
+    fn compile_tail_calls() {
+        let body = Expr::If(
+            Box::new(Expr::Var("n".to_string())),
+            Box::new(Expr::Call(
+                Box::new(Expr::Var("f".to_string())),
+                vec![Expr::Int(1)],
+            )),
+            Box::new(Expr::Call(Box::new(Expr::Var("g".to_string())), vec![])),
+        );
+        assert_eq!(
+            function(&body).code,
+            vec![
+                Op::Load(0),
+                Op::JumpUnless(7),
+                Op::Load(1),
+                Op::Callee(1),
+                Op::Const(0),
+                Op::TailCall(1),
+                Op::Jump(10),
+                Op::Load(2),
+                Op::Callee(0),
+                Op::TailCall(0),
+                Op::Normalize,
+                Op::Return,
+            ]
+        );
+        // Not in tail position
+        assert!(expression(&body).code.contains(&Op::Call(1)));
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt::Write;
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, Weekday,
+};
+
+// Date, time and duration literals are written after an @, eg. @2024-01-15, @2024-01-15T09:30+02:00
+// or @P1DT12H. Times without an offset are in UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Literal {
+    Date(NaiveDate),
+    DateTime(DateTime<FixedOffset>),
+    Duration(TimeDelta),
+}
+
+const DATETIME_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"];
+
+pub fn parse(literal: &str) -> Option<Literal> {
+    if let Some(duration) = literal.strip_prefix('P') {
+        return parse_duration(duration).map(Literal::Duration);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(literal, "%Y-%m-%d") {
+        return Some(Literal::Date(date));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(literal) {
+        return Some(Literal::DateTime(datetime));
+    }
+    // RFC 3339 requires seconds, allow leaving them out
+    if let Ok(datetime) = DateTime::parse_from_str(literal, "%Y-%m-%dT%H:%M%#z") {
+        return Some(Literal::DateTime(datetime));
+    }
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(literal, format).ok())
+        .map(|datetime| Literal::DateTime(datetime.and_utc().fixed_offset()))
+}
+
+// ISO 8601 durations without the leading P, eg. 1W2DT3H4M5.5S. Years and months are left out as
+// their length depends on the date they're added to
+fn parse_duration(duration: &str) -> Option<TimeDelta> {
+    let (days, time) = duration.split_once('T').unwrap_or((duration, ""));
+    if days.is_empty() && time.is_empty() {
+        return None;
+    }
+    let mut secs = 0.0;
+    for (part, units) in [
+        (days, &[('W', 604800.0), ('D', 86400.0)][..]),
+        (time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)]),
+    ] {
+        let mut number = String::new();
+        let mut units = units.iter();
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' {
+                number.push(c);
+                continue;
+            }
+            // Designators have to be in order and each can appear once
+            let (_, size) = units.find(|(designator, _)| *designator == c)?;
+            secs += number.parse::<f64>().ok()? * size;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    from_secs(secs)
+}
+
+pub fn secs(duration: TimeDelta) -> f64 {
+    duration.num_seconds() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+pub fn from_secs(secs: f64) -> Option<TimeDelta> {
+    if !secs.is_finite() || secs.abs() >= i64::MAX as f64 / 1000.0 {
+        return None;
+    }
+    let whole = secs.floor();
+    TimeDelta::new(
+        whole as i64,
+        ((secs - whole) * 1e9).round().min(999_999_999.0) as u32,
+    )
+}
+
+pub fn format_datetime(datetime: &DateTime<FixedOffset>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::AutoSi, false)
+}
+
+// Human readable, eg. 1d 2h 30m
+pub fn format_duration(duration: TimeDelta) -> String {
+    if duration.is_zero() {
+        return "0s".to_string();
+    }
+    let sign = if duration < TimeDelta::zero() {
+        "-"
+    } else {
+        ""
+    };
+    let duration = duration.abs();
+    let parts = [
+        (duration.num_days(), "d"),
+        (duration.num_hours() % 24, "h"),
+        (duration.num_minutes() % 60, "m"),
+    ];
+    let mut formatted = parts
+        .iter()
+        .filter(|(amount, _)| *amount != 0)
+        .map(|(amount, unit)| format!("{}{}", amount, unit))
+        .collect::<Vec<_>>();
+    let secs = secs(duration) % 60.0;
+    if secs != 0.0 {
+        formatted.push(format!("{}s", secs));
+    }
+    format!("{}{}", sign, formatted.join(" "))
+}
+
+// As it would be written, eg. @P1DT2H0S or -@P0DT30M0S
+pub fn duration_input(duration: TimeDelta) -> String {
+    let sign = if duration < TimeDelta::zero() {
+        "-"
+    } else {
+        ""
+    };
+    let duration = duration.abs();
+    let mut iso = format!("@P{}DT", duration.num_days());
+    if duration.num_hours() % 24 != 0 {
+        iso.push_str(&format!("{}H", duration.num_hours() % 24));
+    }
+    if duration.num_minutes() % 60 != 0 {
+        iso.push_str(&format!("{}M", duration.num_minutes() % 60));
+    }
+    format!("{}{}{}S", sign, iso, secs(duration) % 60.0)
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::Date(date) => write!(f, "@{}", date),
+            Literal::DateTime(datetime) => write!(f, "@{}", format_datetime(datetime)),
+            Literal::Duration(duration) => write!(f, "{}", duration_input(*duration)),
+        }
+    }
+}
+
+// Every 7 days hold 5 business days so whole weeks are jumped over, leaving 1 to 5 days to
+// step through. Counts past the range of dates fail on the jump instead of looping
+pub fn add_business_days(date: NaiveDate, days: i64) -> Option<NaiveDate> {
+    if days == 0 {
+        return Some(date);
+    }
+    let count = days.checked_abs()?;
+    let weeks = (count - 1) / 5;
+    let jump = TimeDelta::try_days(weeks.checked_mul(7)? * days.signum())?;
+    let step = TimeDelta::days(days.signum());
+    let mut date = date.checked_add_signed(jump)?;
+    let mut remaining = count - weeks * 5;
+    while remaining > 0 {
+        date = date.checked_add_signed(step)?;
+        if !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            remaining -= 1;
+        }
+    }
+    Some(date)
+}
+
+// Fails rather than panicking on invalid patterns or missing fields, eg. %H for a date
+pub fn strftime(literal: Literal, pattern: &str) -> Option<String> {
+    let items = StrftimeItems::new(pattern);
+    if items.clone().any(|item| item == Item::Error) {
+        return None;
+    }
+    let mut string = String::new();
+    match literal {
+        Literal::Date(date) => write!(string, "{}", date.format_with_items(items)),
+        Literal::DateTime(datetime) => write!(string, "{}", datetime.format_with_items(items)),
+        Literal::Duration(_) => return None,
+    }
+    .ok()?;
+    Some(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_literals() {
+        assert_eq!(
+            parse("2024-01-15"),
+            Some(Literal::Date(NaiveDate::from_ymd_opt(2024, 1, 15).unwrap()))
+        );
+        assert_eq!(
+            parse("2024-01-15T09:30+02:00").map(|literal| match literal {
+                Literal::DateTime(datetime) => format_datetime(&datetime),
+                _ => panic!("Expected a date time"),
+            }),
+            Some("2024-01-15T09:30:00+02:00".to_string())
+        );
+        assert_eq!(
+            parse("2024-01-15T09:30"),
+            parse("2024-01-15T09:30:00+00:00")
+        );
+        assert_eq!(
+            parse("P1DT2H30M"),
+            Some(Literal::Duration(
+                TimeDelta::days(1) + TimeDelta::hours(2) + TimeDelta::minutes(30)
+            ))
+        );
+        assert_eq!(
+            parse("PT1.5S"),
+            Some(Literal::Duration(TimeDelta::milliseconds(1500)))
+        );
+        assert_eq!(parse("P"), None);
+        assert_eq!(parse("PT1D"), None);
+        assert_eq!(parse("2024-13-01"), None);
+    }
+
+    #[test]
+    fn format_durations() {
+        let duration = TimeDelta::days(1) + TimeDelta::hours(2) + TimeDelta::seconds(5);
+        assert_eq!(format_duration(duration), "1d 2h 5s");
+        assert_eq!(format_duration(-TimeDelta::minutes(90)), "-1h 30m");
+        assert_eq!(duration_input(duration), "@P1DT2H5S");
+        assert_eq!(duration_input(-duration), "-@P1DT2H5S");
+        assert_eq!(
+            parse(&duration_input(duration)[1..]),
+            Some(Literal::Duration(duration))
+        );
+    }
+
+    #[test]
+    fn format_patterns() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        assert_eq!(
+            strftime(Literal::Date(date), "%d/%m/%Y %A"),
+            Some("15/01/2024 Monday".to_string())
+        );
+        assert_eq!(strftime(Literal::Date(date), "%H:%M"), None);
+        assert_eq!(strftime(Literal::Date(date), "%Q"), None);
+    }
+
+    #[test]
+    fn business_days() {
+        // A Friday
+        let date = NaiveDate::from_ymd_opt(2024, 1, 12).unwrap();
+        assert_eq!(
+            add_business_days(date, 1),
+            NaiveDate::from_ymd_opt(2024, 1, 15)
+        );
+        assert_eq!(
+            add_business_days(date, -5),
+            NaiveDate::from_ymd_opt(2024, 1, 5)
+        );
+        assert_eq!(add_business_days(date, 0), Some(date));
+        assert_eq!(
+            add_business_days(date, 100),
+            NaiveDate::from_ymd_opt(2024, 5, 31)
+        );
+        // From a Saturday
+        let date = NaiveDate::from_ymd_opt(2024, 1, 13).unwrap();
+        assert_eq!(
+            add_business_days(date, 5),
+            NaiveDate::from_ymd_opt(2024, 1, 19)
+        );
+        assert_eq!(
+            add_business_days(date, -10),
+            NaiveDate::from_ymd_opt(2024, 1, 1)
+        );
+        assert_eq!(add_business_days(date, 100000000000), None);
+        assert_eq!(add_business_days(date, i64::MIN), None);
+    }
+}
@@ -0,0 +1,227 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use crate::settings::Overflow;
+
+// Fixed width integers such as 0xffu8, for modelling registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+}
+
+impl IntType {
+    const ALL: [IntType; 8] = [
+        IntType::U8,
+        IntType::U16,
+        IntType::U32,
+        IntType::U64,
+        IntType::I8,
+        IntType::I16,
+        IntType::I32,
+        IntType::I64,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntType::U8 => "u8",
+            IntType::U16 => "u16",
+            IntType::U32 => "u32",
+            IntType::U64 => "u64",
+            IntType::I8 => "i8",
+            IntType::I16 => "i16",
+            IntType::I32 => "i32",
+            IntType::I64 => "i64",
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            IntType::U8 | IntType::I8 => 8,
+            IntType::U16 | IntType::I16 => 16,
+            IntType::U32 | IntType::I32 => 32,
+            IntType::U64 | IntType::I64 => 64,
+        }
+    }
+
+    fn signed(&self) -> bool {
+        matches!(
+            self,
+            IntType::I8 | IntType::I16 | IntType::I32 | IntType::I64
+        )
+    }
+
+    pub fn min(&self) -> BigInt {
+        if self.signed() {
+            -(BigInt::one() << (self.bits() - 1))
+        } else {
+            BigInt::zero()
+        }
+    }
+
+    pub fn max(&self) -> BigInt {
+        if self.signed() {
+            (BigInt::one() << (self.bits() - 1)) - 1
+        } else {
+            (BigInt::one() << self.bits()) - 1
+        }
+    }
+
+    pub fn contains(&self, int: &BigInt) -> bool {
+        self.min() <= *int && *int <= self.max()
+    }
+
+    // Two's complement truncation, like `as` casts in Rust
+    pub fn wrap(&self, int: &BigInt) -> BigInt {
+        let bits = int.mod_floor(&(BigInt::one() << self.bits()));
+        if bits > self.max() {
+            bits - (BigInt::one() << self.bits())
+        } else {
+            bits
+        }
+    }
+
+    // Brings the result of an operation back into range. None if it overflowed in checked mode
+    pub fn fit(&self, int: BigInt, overflow: Overflow) -> Option<BigInt> {
+        if self.contains(&int) {
+            return Some(int);
+        }
+        match overflow {
+            Overflow::Wrapping => Some(self.wrap(&int)),
+            Overflow::Checked => None,
+            Overflow::Saturating if int.is_negative() => Some(self.min()),
+            Overflow::Saturating => Some(self.max()),
+        }
+    }
+
+    // The bit pattern as an unsigned number
+    pub fn unsigned(&self, int: &BigInt) -> u64 {
+        int.mod_floor(&(BigInt::one() << self.bits()))
+            .to_u64()
+            .unwrap_or_default()
+    }
+
+    fn with_bits(&self, bits: u64) -> BigInt {
+        self.wrap(&BigInt::from(bits))
+    }
+
+    pub fn popcount(&self, int: &BigInt) -> u32 {
+        self.unsigned(int).count_ones()
+    }
+
+    pub fn leading_zeros(&self, int: &BigInt) -> u32 {
+        self.unsigned(int).leading_zeros() - (64 - self.bits())
+    }
+
+    pub fn rotate_left(&self, int: &BigInt, n: i64) -> BigInt {
+        let n = n.rem_euclid(self.bits() as i64) as u32;
+        let bits = self.unsigned(int);
+        let mask = u64::MAX >> (64 - self.bits());
+        let rotated = if n == 0 {
+            bits
+        } else {
+            ((bits << n) | (bits >> (self.bits() - n))) & mask
+        };
+        self.with_bits(rotated)
+    }
+
+    // Huge powers only keep their low bits, moved out of range so that they still overflow
+    pub fn pow(&self, int: &BigInt, exp: u32) -> BigInt {
+        if int.magnitude() <= &One::one() || exp < 2 * self.bits() {
+            return num_traits::Pow::pow(int, exp);
+        }
+        let modulus = BigInt::one() << self.bits();
+        let low = int.modpow(&BigInt::from(exp), &modulus).mod_floor(&modulus);
+        if int.is_negative() && exp.is_odd() {
+            low - (modulus << 1)
+        } else {
+            low + modulus
+        }
+    }
+
+    // Bitwise not within the width, eg. !0u8 == 255u8
+    pub fn not(&self, int: &BigInt) -> BigInt {
+        if self.signed() {
+            -int - 1
+        } else {
+            self.max() - int
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_overflow() {
+        let int = BigInt::from(300);
+        assert_eq!(
+            IntType::U8.fit(int.clone(), Overflow::Wrapping),
+            Some(BigInt::from(44))
+        );
+        assert_eq!(IntType::U8.fit(int.clone(), Overflow::Checked), None);
+        assert_eq!(
+            IntType::U8.fit(int.clone(), Overflow::Saturating),
+            Some(BigInt::from(255))
+        );
+        assert_eq!(
+            IntType::I8.fit(BigInt::from(128), Overflow::Wrapping),
+            Some(BigInt::from(-128))
+        );
+        assert_eq!(
+            IntType::I16.fit(BigInt::from(-40000), Overflow::Saturating),
+            Some(BigInt::from(i16::MIN))
+        );
+    }
+
+    #[test]
+    fn large_powers() {
+        let three = BigInt::from(3);
+        let pow = IntType::U8.pow(&three, 1000);
+        assert_eq!(
+            IntType::U8.wrap(&pow),
+            IntType::U8.wrap(&num_traits::Pow::pow(&three, 1000u32))
+        );
+        assert_eq!(
+            IntType::U8.fit(pow, Overflow::Saturating),
+            Some(BigInt::from(255))
+        );
+        let pow = IntType::I8.pow(&-three, 1001);
+        assert_eq!(
+            IntType::I8.fit(pow, Overflow::Saturating),
+            Some(BigInt::from(-128))
+        );
+    }
+
+    #[test]
+    fn bit_functions() {
+        assert_eq!(IntType::U8.not(&BigInt::from(45)), BigInt::from(210));
+        assert_eq!(IntType::I8.not(&BigInt::from(45)), BigInt::from(-46));
+        assert_eq!(IntType::I8.popcount(&BigInt::from(-1)), 8);
+        assert_eq!(IntType::U16.leading_zeros(&BigInt::from(1)), 15);
+        assert_eq!(
+            IntType::U8.rotate_left(&BigInt::from(0b1000_0001), 1),
+            BigInt::from(0b0000_0011)
+        );
+        assert_eq!(
+            IntType::U8.rotate_left(&BigInt::from(0b1000_0001), -1),
+            BigInt::from(0b1100_0000)
+        );
+        assert_eq!(
+            IntType::U64.rotate_left(&BigInt::from(u64::MAX), 3),
+            BigInt::from(u64::MAX)
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use std::{fmt::Display, str::FromStr};
+
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive};
+use rust_decimal::Decimal;
+
+use crate::interpreter::Value;
+
+const DEFAULT_DIGITS: u16 = 2;
+
+// How results are shown. Only changes the output, values are kept at full precision
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+    #[default]
+    Decimal,
+    Hex,
+    Binary,
+    Octal,
+    Scientific,
+    Engineering,
+    // Number of digits after the decimal point, up to u16::MAX like format spec precisions
+    Fixed(u16),
+    Thousands,
+}
+
+impl Format {
+    const ALL: [Format; 8] = [
+        Format::Decimal,
+        Format::Hex,
+        Format::Binary,
+        Format::Octal,
+        Format::Scientific,
+        Format::Engineering,
+        Format::Fixed(DEFAULT_DIGITS),
+        Format::Thousands,
+    ];
+
+    // The format after this one, wrapping around to decimal
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|format| format.name() == self.name())
+            .unwrap_or_default();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Decimal => "decimal",
+            Format::Hex => "hex",
+            Format::Binary => "binary",
+            Format::Octal => "octal",
+            Format::Scientific => "scientific",
+            Format::Engineering => "engineering",
+            Format::Fixed(_) => "fixed",
+            Format::Thousands => "thousands",
+        }
+    }
+
+    pub fn value(&self, value: &Value) -> String {
+        match (self, value) {
+            (Format::Decimal, _) => value.to_string(),
+            (_, Value::Int(int)) => self.int(&BigInt::from(*int)),
+            (_, Value::BigInt(int)) => self.int(int),
+            (Format::Hex | Format::Binary | Format::Octal, Value::Fixed(int, ty)) => {
+                // The bit pattern, padded to the width of the type
+                let bits = ty.unsigned(int);
+                match self {
+                    Format::Hex => format!(
+                        "0x{:0width$x}{}",
+                        bits,
+                        ty.name(),
+                        width = ty.bits() as usize / 4
+                    ),
+                    Format::Binary => format!(
+                        "0b{:0width$b}{}",
+                        bits,
+                        ty.name(),
+                        width = ty.bits() as usize
+                    ),
+                    _ => format!("0o{:o}{}", bits, ty.name()),
+                }
+            }
+            (Format::Thousands, Value::Fixed(int, ty)) => format!("{}{}", self.int(int), ty.name()),
+            (_, Value::Float(float)) => self.float(*float),
+            (_, Value::Decimal(decimal)) => self.decimal(decimal),
+            (Format::Fixed(_) | Format::Scientific | Format::Engineering, Value::Rational(_)) => {
+                value
+                    .approximation()
+                    .map(|float| self.float(float))
+                    .unwrap_or_else(|| value.to_string())
+            }
+            (_, Value::Rational(ratio)) => {
+                format!("{}/{}", self.int(ratio.numer()), self.int(ratio.denom()))
+            }
+            (_, Value::Complex(complex)) => match (complex.re, complex.im) {
+                (0.0, im) => format!("{}i", self.float(im)),
+                (re, im) if im < 0.0 => format!("{}-{}i", self.float(re), self.float(-im)),
+                (re, im) => format!("{}+{}i", self.float(re), self.float(im)),
+            },
+            (_, Value::Quantity(value, unit)) => format!("{} {}", self.float(*value), unit),
+            (_, Value::List(elems)) => format!("[{}]", self.join(elems)),
+            (_, Value::Tuple(elems)) => format!("{{{}}}", self.join(elems)),
+            (_, Value::Record(fields)) => format!(
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, self.value(value)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            _ => value.to_string(),
+        }
+    }
+
+    fn join(&self, elems: &[Value]) -> String {
+        elems
+            .iter()
+            .map(|elem| self.value(elem))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // Integers stay exact, fixed point only applies to fractional numbers
+    fn int(&self, int: &BigInt) -> String {
+        let sign = if int.is_negative() { "-" } else { "" };
+        let magnitude = int.magnitude();
+        match self {
+            Format::Hex => format!("{}0x{:x}", sign, magnitude),
+            Format::Binary => format!("{}0b{:b}", sign, magnitude),
+            Format::Octal => format!("{}0o{:o}", sign, magnitude),
+            Format::Scientific | Format::Engineering => {
+                let digits = magnitude.to_string();
+                exponent(
+                    sign,
+                    &digits,
+                    digits.len() as i64 - 1,
+                    *self == Format::Engineering,
+                )
+            }
+            Format::Thousands => format!("{}{}", sign, group(&magnitude.to_string())),
+            Format::Decimal | Format::Fixed(_) => int.to_string(),
+        }
+    }
+
+    pub fn float(&self, float: f64) -> String {
+        if !float.is_finite() {
+            return float.to_string();
+        }
+        match self {
+            // Whole floats are shown like integers, anything else can't be written in the radix
+            Format::Hex | Format::Binary | Format::Octal
+                if float.fract() == 0.0 && float.abs() < i64::MAX as f64 =>
+            {
+                self.int(&BigInt::from(float as i64))
+            }
+            Format::Scientific | Format::Engineering if float != 0.0 => {
+                let sign = if float < 0.0 { "-" } else { "" };
+                let scientific = format!("{:e}", float.abs());
+                let (mantissa, exp) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+                exponent(
+                    sign,
+                    &mantissa.replace('.', ""),
+                    exp.parse().unwrap_or_default(),
+                    *self == Format::Engineering,
+                )
+            }
+            Format::Scientific | Format::Engineering => "0e0".to_string(),
+            Format::Fixed(digits) => format!("{:.*}", usize::from(*digits), float),
+            Format::Thousands => thousands(&float.to_string()),
+            _ => float.to_string(),
+        }
+    }
+
+    fn decimal(&self, decimal: &Decimal) -> String {
+        match self {
+            Format::Scientific | Format::Engineering if !decimal.is_zero() => {
+                let sign = if decimal.is_sign_negative() { "-" } else { "" };
+                let digits = decimal.mantissa().unsigned_abs().to_string();
+                let exp = digits.len() as i64 - 1 - decimal.scale() as i64;
+                exponent(sign, &digits, exp, *self == Format::Engineering)
+            }
+            Format::Fixed(digits) => format!("{:.*}", usize::from(*digits), decimal),
+            Format::Thousands => thousands(&decimal.to_string()),
+            Format::Hex | Format::Binary | Format::Octal if decimal.fract().is_zero() => decimal
+                .to_i64()
+                .map(|int| self.int(&BigInt::from(int)))
+                .unwrap_or_else(|| decimal.to_string()),
+            _ => self.float(decimal.to_f64().unwrap_or(f64::NAN)),
+        }
+    }
+}
+
+// Places the point after the first digit, or after up to three so that the exponent is a multiple
+// of three for engineering notation. digits are the significant digits and exp the power of ten of
+// the first one, eg. 1234.5 == ("12345", 3)
+fn exponent(sign: &str, digits: &str, exp: i64, engineering: bool) -> String {
+    let digits = digits.trim_end_matches('0');
+    if digits.is_empty() {
+        return "0e0".to_string();
+    }
+    let lead = if engineering {
+        exp.rem_euclid(3) as usize + 1
+    } else {
+        1
+    };
+    let digits = format!("{:0<lead$}", digits);
+    let (int, fract) = digits.split_at(lead);
+    let point = if fract.is_empty() { "" } else { "." };
+    format!(
+        "{}{}{}{}e{}",
+        sign,
+        int,
+        point,
+        fract,
+        exp - lead as i64 + 1
+    )
+}
+
+// Separates groups of three digits with commas, eg. 1234567 == 1,234,567
+fn group(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+// Groups the integer part of a formatted number, eg. -1234.5678 == -1,234.5678
+fn thousands(number: &str) -> String {
+    let (sign, number) = match number.strip_prefix('-') {
+        Some(number) => ("-", number),
+        None => ("", number),
+    };
+    match number.split_once('.') {
+        Some((int, fract)) => format!("{}{}.{}", sign, group(int), fract),
+        None => format!("{}{}", sign, group(number)),
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Fixed(digits) => write!(f, "{}:{}", self.name(), digits),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+// Names as given to --format, eg. hex or fixed:4
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, digits) = s.split_once(':').unwrap_or((s, ""));
+        let format = Self::ALL
+            .into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown format {}, expected one of {}",
+                    s,
+                    Self::ALL.map(|format| format.name()).join(", ")
+                )
+            })?;
+        match (format, digits) {
+            (_, "") => Ok(format),
+            (Format::Fixed(_), digits) => digits
+                .parse()
+                .map(Format::Fixed)
+                .map_err(|_| format!("Invalid number of digits {}", digits)),
+            _ => Err(format!(
+                "The {} format does not take a number of digits",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_rational::BigRational;
+
+    use super::*;
+    use crate::fixed::IntType;
+
+    #[test]
+    fn radix_formats() {
+        assert_eq!(Format::Hex.value(&Value::Int(255)), "0xff");
+        assert_eq!(Format::Binary.value(&Value::Int(-5)), "-0b101");
+        assert_eq!(Format::Octal.value(&Value::Float(8.0)), "0o10");
+        assert_eq!(Format::Hex.value(&Value::Float(1.5)), "1.5");
+        assert_eq!(
+            Format::Hex.value(&Value::Fixed(BigInt::from(-1), IntType::I16)),
+            "0xffffi16"
+        );
+        assert_eq!(
+            Format::Binary.value(&Value::Fixed(BigInt::from(5), IntType::U8)),
+            "0b00000101u8"
+        );
+        assert_eq!(
+            Format::Hex.value(&Value::List(vec![Value::Int(10), Value::Bool(true)])),
+            "[0xa, true]"
+        );
+    }
+
+    #[test]
+    fn exponent_formats() {
+        assert_eq!(Format::Scientific.value(&Value::Float(1234.5)), "1.2345e3");
+        assert_eq!(
+            Format::Engineering.value(&Value::Float(12345.6)),
+            "12.3456e3"
+        );
+        assert_eq!(
+            Format::Engineering.value(&Value::Float(-0.0123)),
+            "-12.3e-3"
+        );
+        assert_eq!(Format::Engineering.value(&Value::Int(100000)), "100e3");
+        assert_eq!(Format::Scientific.value(&Value::Float(0.0)), "0e0");
+        assert_eq!(
+            Format::Scientific.value(&Value::Decimal(Decimal::new(-15, 3))),
+            "-1.5e-2"
+        );
+    }
+
+    #[test]
+    fn fixed_and_thousands() {
+        assert_eq!(Format::Fixed(2).value(&Value::Float(1.23456)), "1.23");
+        assert_eq!(Format::Fixed(3).value(&Value::Int(7)), "7");
+        assert_eq!(
+            Format::Fixed(4).value(&Value::Rational(BigRational::new(
+                BigInt::from(1),
+                BigInt::from(3)
+            ))),
+            "0.3333"
+        );
+        assert_eq!(Format::Thousands.value(&Value::Int(-1234567)), "-1,234,567");
+        assert_eq!(Format::Thousands.value(&Value::Float(1234.5)), "1,234.5");
+        assert_eq!(Format::Thousands.value(&Value::Int(123)), "123");
+    }
+
+    #[test]
+    fn parse_formats() {
+        assert_eq!("hex".parse(), Ok(Format::Hex));
+        assert_eq!("fixed".parse(), Ok(Format::Fixed(DEFAULT_DIGITS)));
+        assert_eq!("fixed:4".parse(), Ok(Format::Fixed(4)));
+        assert!("fixed:x".parse::<Format>().is_err());
+        assert_eq!("fixed:65535".parse(), Ok(Format::Fixed(u16::MAX)));
+        assert_eq!(
+            "fixed:70000".parse::<Format>(),
+            Err("Invalid number of digits 70000".to_string())
+        );
+        assert!("hex:2".parse::<Format>().is_err());
+        assert!("roman".parse::<Format>().is_err());
+        assert_eq!(Format::Thousands.next(), Format::Decimal);
+        assert_eq!(Format::Fixed(5).next(), Format::Thousands);
+    }
+}
@@ -1,27 +1,70 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     error::Error,
     f64::consts::{E, PI},
     fmt::Display,
     ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
+    rc::Rc,
 };
 
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, SubsecRound, TimeDelta};
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, Pow, Signed, ToPrimitive, Zero};
+use rust_decimal::Decimal;
+
 use crate::{
+    datetime::{self, Literal},
+    fixed::IntType,
     inner_write,
-    parse::{Expr, Func},
-    token::Token,
+    parse::{self, join_patterns, suggest, Arm, Definition, Expr, Func, Part, Pattern, FNS},
+    scope::Scope,
+    settings::{Backend, Settings},
+    simplify, strings,
+    token::{escape, Token},
+    types::{self, Signature, Type},
+    units::Unit,
+    vm::{self, Code},
 };
 
-#[derive(Debug)]
+// Defined in a new interpreter, and inlined by `simplify` unless they've been redefined
+const CONSTANTS: [(&str, f64); 2] = [("pi", PI), ("e", E)];
+// Anything larger takes long enough to freeze the TUI
+const MAX_FACTORIAL: i64 = 10_000;
+// Same for printing integers from powers and shifts with more bits than this
+const MAX_BITS: u64 = 1 << 18;
+// Stack left when a user function is called before a new segment is allocated, and the size of
+// the segment. Generous since debug builds use a lot of stack for every nested expression
+const RED_ZONE: usize = 512 * 1024;
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+// Tail calls take no stack but can loop forever, so a call can be replaced this many times
+// for every level of max_depth. A million by default
+const TAIL_CALLS_PER_DEPTH: usize = 100;
+
+#[derive(Debug, Clone)]
 pub struct Interpreter {
-    env: HashMap<String, Value>,
+    env: Rc<Scope>,
+    settings: Settings,
+    // Number of user function calls being evaluated, limited by the max_depth setting
+    depth: usize,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
     Expr(Expr),
     Assign(String, Expr),
+    // Takes a list or tuple apart into names, eg. let {x, y} = quadr(1, 1, -2)
+    Unpack(Pattern, Expr),
     Undef(Vec<String>),
+    Config(String, Expr),
+    // Statements separated by ; run one after the other
+    Seq(Vec<Stmt>),
+    // Functions which can call themselves and each other
+    Fn(Vec<Definition>),
+    // Shows an expression the way it will run, without running it
+    Simplify(Expr),
 }
 impl Stmt {
     pub(crate) fn format(&self) -> String {
@@ -29,6 +72,22 @@ impl Stmt {
             Self::Undef(variables) => format!("undef({})", variables.join(", ")),
             Self::Expr(expr) => expr.format(),
             Self::Assign(name, expr) => format!("let {} = {}", name, expr.format()),
+            Self::Unpack(pattern, expr) => format!("let {} = {}", pattern.format(), expr.format()),
+            Self::Config(name, expr) => format!("config {} = {}", name, expr.format()),
+            Self::Seq(stmts) => stmts
+                .iter()
+                .map(|stmt| stmt.format())
+                .collect::<Vec<_>>()
+                .join("; "),
+            Self::Simplify(expr) => format!("simplify({})", expr.format()),
+            Self::Fn(definitions) => format!(
+                "fn {}",
+                definitions
+                    .iter()
+                    .map(|definition| definition.format())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
         }
     }
 }
@@ -38,10 +97,27 @@ pub enum Value {
     Fn(Function),
     Float(f64),
     Int(i64),
+    // Only used for integers that don't fit into an i64, see `From<BigInt>`
+    BigInt(BigInt),
+    // Fits its type after every expression, see the end of `Interpreter::interpret_expr`
+    Fixed(BigInt, IntType),
+    // Exact result of integer division, never has a denominator of 1, see `From<BigRational>`
+    Rational(BigRational),
+    // Always has a non-zero imaginary part, see `From<Complex64>`
+    Complex(Complex64),
+    Decimal(Decimal),
+    // Never dimensionless, see `Value::quantity`
+    Quantity(f64, Unit),
+    Date(NaiveDate),
+    DateTime(DateTime<FixedOffset>),
+    Duration(TimeDelta),
     String(String),
     Bool(bool),
     List(Vec<Value>),
     Tuple(Vec<Value>),
+    // Kept sorted by name, so records with the same fields are equal whatever order they were
+    // written in
+    Record(BTreeMap<String, Value>),
     Unit,
     Nil,
     NaN,
@@ -52,48 +128,92 @@ impl Value {
         match self {
             Self::Float(float) => format!("let {} = {}", name, float),
             Self::Int(int) => format!("let {} = {}", name, int),
-            Self::String(string) => format!("let {} = \"{}\"", name, string),
+            Self::BigInt(int) => format!("let {} = {}", name, int),
+            // Negative literals would be out of range before being negated
+            Self::Fixed(int, ty) if int.is_negative() => {
+                format!("let {} = cast({}, \"{}\")", name, int, ty.name())
+            }
+            Self::Fixed(_, _) => format!("let {} = {}", name, self),
+            Self::Rational(ratio) => format!("let {} = {}", name, ratio),
+            Self::Complex(_) | Self::Quantity(_, _) => format!("let {} = {}", name, self),
+            Self::Date(date) => format!("let {} = {}", name, Literal::Date(*date)),
+            Self::DateTime(datetime) => format!("let {} = {}", name, Literal::DateTime(*datetime)),
+            Self::Duration(duration) => format!("let {} = {}", name, Literal::Duration(*duration)),
+            Self::Decimal(decimal) => format!("let {} = {}d", name, decimal),
+            Self::String(string) => format!("let {} = \"{}\"", name, escape(string)),
             Self::Unit => "()".to_string(),
             Self::Bool(bool) => if *bool { "true" } else { "false" }.to_string(),
+            Self::Fn(func) if !func.group.is_empty() => Stmt::Fn(
+                func.group
+                    .iter()
+                    .map(|(name, member)| member.definition(name))
+                    .collect(),
+            )
+            .format(),
             Self::Fn(func) => format!(
                 "let {} = |{}| {}",
                 name,
-                func.parameters.join(", "),
+                join_patterns(&func.parameters),
                 func.body.format()
             ),
-            Self::List(elems) => format!(
-                "let {} = [{}]",
-                name,
-                elems
-                    .iter()
-                    // This is quite dangerous and error-prone. Considering changing how this works
-                    .map(|e| match e {
-                        Value::String(string) => format!("\"{}\"", string),
-                        v => v.to_string(),
-                    })
-                    .collect::<Vec<_>>()
-                    .join(", ")
+            Self::List(_) | Self::Tuple(_) | Self::Record(_) => {
+                format!("let {} = {}", name, self.literal())
+            }
+            Self::Nil => "nil".to_string(),
+            Self::NaN => "NaN".to_string(),
+        }
+    }
+
+    // An expression which gives back the value, for saving lists, tuples and records
+    fn literal(&self) -> String {
+        let join = |elems: &[Value]| {
+            elems
+                .iter()
+                .map(|elem| elem.literal())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::Fixed(int, ty) if int.is_negative() => {
+                format!("cast({}, \"{}\")", int, ty.name())
+            }
+            Self::Decimal(decimal) => format!("{}d", decimal),
+            Self::Date(date) => Literal::Date(*date).to_string(),
+            Self::DateTime(datetime) => Literal::DateTime(*datetime).to_string(),
+            Self::Duration(duration) => Literal::Duration(*duration).to_string(),
+            Self::String(string) => format!("\"{}\"", escape(string)),
+            Self::Fn(func) => format!(
+                "|{}| {}",
+                join_patterns(&func.parameters),
+                func.body.format()
             ),
-            Self::Tuple(elems) => format!(
-                "let {} = {{{}}}",
-                name,
-                elems
+            Self::List(elems) => format!("[{}]", join(elems)),
+            Self::Tuple(elems) => format!("{{{}}}", join(elems)),
+            Self::Record(fields) => format!(
+                "{{{}}}",
+                fields
                     .iter()
-                    .map(|e| match e {
-                        Value::String(string) => format!("\"{}\"", string),
-                        v => v.to_string(),
-                    })
+                    .map(|(name, value)| format!("{}: {}", name, value.literal()))
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
-            Self::Nil => "nil".to_string(),
-            Self::NaN => "NaN".to_string(),
+            value => value.to_string(),
         }
     }
 
     fn abs(&self) -> Result<Self, InterpretError> {
         Ok(match self {
-            Value::Int(int) => Value::Int(int.abs()),
+            Value::Int(int) => int
+                .checked_abs()
+                .map(Value::Int)
+                .unwrap_or_else(|| Value::from(BigInt::from(*int).abs())),
+            Value::BigInt(int) => Value::from(int.abs()),
+            Value::Fixed(int, ty) => Value::Fixed(int.abs(), *ty),
+            Value::Rational(ratio) => Value::from(ratio.abs()),
+            Value::Complex(complex) => Value::Float(complex.norm()),
+            Value::Decimal(decimal) => Value::Decimal(decimal.abs()),
+            Value::Quantity(value, unit) => Value::Quantity(value.abs(), unit.clone()),
+            Value::Duration(duration) => Value::Duration(duration.abs()),
             Value::Float(float) => Value::Float(float.abs()),
             _ => {
                 return Err(InterpretError::InvalidArgument(
@@ -104,11 +224,70 @@ impl Value {
     }
 
     fn pow(&self, rhs: Self) -> Result<Self, InterpretError> {
+        if self.is_numeric() && rhs.is_numeric() {
+            let base = self.to_float().unwrap_or(0.0);
+            let exp = rhs.to_float().unwrap_or(0.0);
+            // Negative bases with fractional exponents have a complex principal value
+            if matches!(self, Value::Complex(_))
+                || matches!(rhs, Value::Complex(_))
+                || (base < 0.0 && exp.fract() != 0.0)
+            {
+                let base = self.to_complex()?;
+                return Ok(Value::from(match rhs {
+                    Value::Int(exp) if i32::try_from(exp).is_ok() => base.powi(exp as i32),
+                    rhs => base.powc(rhs.to_complex()?),
+                }));
+            }
+        }
         let res = match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Value::Int(lhs.pow(rhs as u32)),
+            (Value::Decimal(lhs), Value::Int(rhs)) => decimal_pow(*lhs, rhs)?,
+            (Value::Fixed(lhs, ty), rhs @ (Value::Int(_) | Value::Fixed(_, _))) => {
+                let exp = rhs.to_int()?;
+                if exp < 0 {
+                    return Err(InterpretError::RuntimeError(format!(
+                        "Cannot raise {} to a negative power",
+                        ty.name()
+                    )));
+                }
+                Value::Fixed(ty.pow(lhs, pow_exponent(exp)?), *ty)
+            }
+            (Value::Quantity(value, unit), Value::Int(rhs)) => {
+                let exp = i32::try_from(rhs).map_err(|_| {
+                    InterpretError::RuntimeError("Unit power too large".to_string())
+                })?;
+                Value::quantity(value.powi(exp), unit.powi(exp)?)
+            }
+            (Value::Int(lhs), Value::Int(rhs)) if rhs >= 0 => {
+                let exp = pow_exponent(rhs)?;
+                match lhs.checked_pow(exp) {
+                    Some(int) => Value::Int(int),
+                    None => big_pow(&BigInt::from(*lhs), exp)?,
+                }
+            }
+            (Value::BigInt(lhs), Value::Int(rhs)) if rhs >= 0 => big_pow(lhs, pow_exponent(rhs)?)?,
+            // Negative integer exponents stay exact by raising the reciprocal
+            (lhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)), Value::Int(rhs)) => {
+                let base = lhs.to_rational()?;
+                let exp = pow_exponent(rhs)?;
+                limit_bits(base.numer(), exp)?;
+                limit_bits(base.denom(), exp)?;
+                if rhs >= 0 {
+                    Value::from(Pow::pow(base, exp))
+                } else if base.is_zero() {
+                    Value::NaN
+                } else {
+                    Value::from(Pow::pow(base.recip(), exp))
+                }
+            }
             (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs.powf(rhs)),
             (Value::Int(lhs), Value::Float(rhs)) => Value::Float((*lhs as f64).powf(rhs)),
             (Value::Float(lhs), Value::Int(rhs)) => Value::Float(lhs.powi(rhs as i32)),
+            (lhs @ (Value::BigInt(_) | Value::Rational(_)), Value::Float(rhs)) => {
+                Value::Float(lhs.to_float()?.powf(rhs))
+            }
+            (lhs, rhs @ (Value::BigInt(_) | Value::Rational(_))) => {
+                Value::Float(lhs.to_float()?.powf(rhs.to_float()?))
+            }
             _ => {
                 return Err(InterpretError::RuntimeError(
                     "Cannot pow non numeric types".to_string(),
@@ -118,9 +297,17 @@ impl Value {
         Ok(res)
     }
 
-    fn truthy(&self) -> bool {
+    pub(crate) fn truthy(&self) -> bool {
         match self {
             Self::Int(int) => *int > 0,
+            Self::BigInt(int) => int.is_positive(),
+            Self::Fixed(int, _) => int.is_positive(),
+            Self::Rational(ratio) => ratio.is_positive(),
+            Self::Complex(complex) => !complex.is_zero(),
+            Self::Decimal(decimal) => decimal.is_sign_positive() && !decimal.is_zero(),
+            Self::Quantity(value, _) => *value > 0.0,
+            Self::Date(_) | Self::DateTime(_) => true,
+            Self::Duration(duration) => !duration.is_zero(),
             Self::Float(float) => *float > 0.0,
             Self::Bool(bool) => *bool,
             Self::String(string) => !string.is_empty(),
@@ -129,15 +316,22 @@ impl Value {
             Self::Fn(_) => true,
             Self::List(elems) => !elems.is_empty(),
             Self::Tuple(elems) => !elems.is_empty(),
+            Self::Record(fields) => !fields.is_empty(),
             Self::NaN => false,
         }
     }
 
-    fn to_float(&self) -> Result<f64, InterpretError> {
+    pub(crate) fn to_float(&self) -> Result<f64, InterpretError> {
         if let Value::Float(float) = self.clone() {
             Ok(float)
         } else if let Value::Int(int) = self {
             Ok(*int as f64)
+        } else if let Value::BigInt(int) | Value::Fixed(int, _) = self {
+            Ok(big_to_f64(int))
+        } else if let Value::Rational(ratio) = self {
+            Ok(ratio.to_f64().unwrap_or(f64::NAN))
+        } else if let Value::Decimal(decimal) = self {
+            Ok(decimal.to_f64().unwrap_or(f64::NAN))
         } else if let Value::NaN = self {
             Ok(f64::NAN)
         } else {
@@ -159,9 +353,31 @@ impl Value {
         }
     }
 
+    fn to_str(&self) -> Result<&str, InterpretError> {
+        if let Value::String(string) = self {
+            Ok(string)
+        } else {
+            Err(InterpretError::InvalidArgument(format!(
+                "Expected string, got: {}",
+                self
+            )))
+        }
+    }
+
+    fn to_record(&self) -> Result<BTreeMap<String, Self>, InterpretError> {
+        if let Value::Record(fields) = self.clone() {
+            Ok(fields)
+        } else {
+            Err(InterpretError::InvalidArgument(format!(
+                "Expected record, got: {}",
+                self
+            )))
+        }
+    }
+
     fn to_callable(&self) -> Result<Function, InterpretError> {
-        if let Value::Fn(function) = self.clone() {
-            Ok(function)
+        if let Value::Fn(function) = self {
+            Ok(function.clone())
         } else {
             Err(InterpretError::InvalidArgument(format!(
                 "Expected function, got: {}",
@@ -170,9 +386,18 @@ impl Value {
         }
     }
 
-    fn to_int(&self) -> Result<i64, InterpretError> {
+    pub(crate) fn to_int(&self) -> Result<i64, InterpretError> {
         if let Value::Int(int) = self.clone() {
             Ok(int)
+        } else if let Value::Fixed(int, _) = self {
+            int.to_i64().ok_or_else(|| {
+                InterpretError::InvalidArgument(format!("Integer too large: {}", int))
+            })
+        } else if let Value::BigInt(int) = self {
+            Err(InterpretError::InvalidArgument(format!(
+                "Integer too large: {}",
+                int
+            )))
         } else {
             Err(InterpretError::InvalidArgument(format!(
                 "Expected int, got: {}",
@@ -180,38 +405,749 @@ impl Value {
             )))
         }
     }
+
+    fn to_bigint(&self) -> Result<BigInt, InterpretError> {
+        match self {
+            Value::Int(int) => Ok(BigInt::from(*int)),
+            Value::BigInt(int) | Value::Fixed(int, _) => Ok(int.clone()),
+            _ => Err(InterpretError::InvalidArgument(format!(
+                "Expected int, got: {}",
+                self
+            ))),
+        }
+    }
+
+    fn to_complex(&self) -> Result<Complex64, InterpretError> {
+        match self {
+            Value::Complex(complex) => Ok(*complex),
+            _ => Ok(Complex64::new(self.to_float()?, 0.0)),
+        }
+    }
+
+    fn to_decimal(&self) -> Result<Decimal, InterpretError> {
+        let big_to_decimal = |int: &BigInt| {
+            int.to_string().parse::<Decimal>().map_err(|_| {
+                InterpretError::RuntimeError(format!("{} is too large for a decimal", int))
+            })
+        };
+        match self {
+            Value::Decimal(decimal) => Ok(*decimal),
+            Value::Int(int) => Ok(Decimal::from(*int)),
+            Value::BigInt(int) => big_to_decimal(int),
+            Value::Rational(ratio) => big_to_decimal(ratio.numer())?
+                .checked_div(big_to_decimal(ratio.denom())?)
+                .ok_or_else(|| {
+                    InterpretError::RuntimeError(format!("{} is too large for a decimal", ratio))
+                }),
+            Value::Float(float) => Decimal::from_f64(*float).ok_or_else(|| {
+                InterpretError::RuntimeError(format!(
+                    "{} cannot be represented as a decimal",
+                    float
+                ))
+            }),
+            _ => Err(InterpretError::InvalidArgument(format!(
+                "Expected decimal, got: {}",
+                self
+            ))),
+        }
+    }
+
+    // Time quantities such as `3 day` work anywhere a duration does
+    fn to_duration(&self) -> Result<TimeDelta, InterpretError> {
+        match self {
+            Value::Duration(duration) => Ok(*duration),
+            Value::Quantity(value, unit) => {
+                datetime::from_secs(convert(*value, unit, &Unit::second())?).ok_or_else(|| {
+                    InterpretError::RuntimeError(format!("Duration out of range {}", self))
+                })
+            }
+            _ => Err(InterpretError::InvalidArgument(format!(
+                "Expected duration, got: {}",
+                self
+            ))),
+        }
+    }
+
+    // Dates are midnight UTC
+    fn to_datetime(&self) -> Result<DateTime<FixedOffset>, InterpretError> {
+        match self {
+            Value::DateTime(datetime) => Ok(*datetime),
+            Value::Date(date) => Ok(date.and_time(Default::default()).and_utc().fixed_offset()),
+            _ => Err(InterpretError::InvalidArgument(format!(
+                "Expected date, got: {}",
+                self
+            ))),
+        }
+    }
+
+    fn to_rational(&self) -> Result<BigRational, InterpretError> {
+        match self {
+            Value::Rational(ratio) => Ok(ratio.clone()),
+            _ => Ok(BigRational::from_integer(self.to_bigint()?)),
+        }
+    }
+
+    // Numbers which can be converted to a decimal
+    fn is_real(&self) -> bool {
+        matches!(
+            self,
+            Value::Int(_)
+                | Value::BigInt(_)
+                | Value::Rational(_)
+                | Value::Float(_)
+                | Value::Decimal(_)
+        )
+    }
+
+    pub(crate) fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Value::Int(_)
+                | Value::BigInt(_)
+                | Value::Fixed(_, _)
+                | Value::Rational(_)
+                | Value::Float(_)
+                | Value::Complex(_)
+                | Value::Decimal(_)
+                | Value::NaN
+        )
+    }
+
+    // Dimensionless results such as `2 km / 500 m` are plain numbers
+    pub(crate) fn quantity(value: f64, unit: Unit) -> Self {
+        if unit.is_dimensionless() {
+            Value::Float(value * unit.factor)
+        } else {
+            Value::Quantity(value, unit)
+        }
+    }
+
+    // Decimal rendering shown next to values which don't display as decimals themselves
+    pub fn approximation(&self) -> Option<f64> {
+        match self {
+            Value::Rational(_) => self.to_float().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(int: BigInt) -> Self {
+        match int.to_i64() {
+            Some(int) => Value::Int(int),
+            None => Value::BigInt(int),
+        }
+    }
+}
+
+impl From<BigRational> for Value {
+    fn from(ratio: BigRational) -> Self {
+        if ratio.is_integer() {
+            Value::from(ratio.to_integer())
+        } else {
+            Value::Rational(ratio)
+        }
+    }
+}
+
+impl From<Complex64> for Value {
+    fn from(complex: Complex64) -> Self {
+        if complex.is_nan() || complex.is_infinite() {
+            Value::NaN
+        } else if complex.im == 0.0 {
+            Value::Float(complex.re)
+        } else {
+            Value::Complex(complex)
+        }
+    }
+}
+
+fn complex_op(
+    lhs: Value,
+    rhs: Value,
+    op: fn(Complex64, Complex64) -> Complex64,
+) -> Result<Value, InterpretError> {
+    Ok(Value::from(op(lhs.to_complex()?, rhs.to_complex()?)))
+}
+
+// Built-ins evaluated on the complex plane, either because the argument is complex or because
+// the result for a real argument lies outside of the reals. None means the real version applies
+fn complex_func(func: &Func, args: &[Value]) -> Result<Option<Value>, InterpretError> {
+    if let (Func::Log, [base, arg]) = (func, args) {
+        let complex = [base, arg]
+            .iter()
+            .any(|v| matches!(v, Value::Complex(_)) || v.to_float().is_ok_and(|f| f < 0.0));
+        return Ok(if complex {
+            Some(Value::from(
+                arg.to_complex()?.ln() / base.to_complex()?.ln(),
+            ))
+        } else {
+            None
+        });
+    }
+    let [arg] = args else {
+        return Ok(None);
+    };
+    let real = match arg {
+        Value::Complex(_) => None,
+        arg if arg.is_numeric() => Some(arg.to_float()?),
+        _ => return Ok(None),
+    };
+    let outside_domain = |domain: fn(f64) -> bool| real.is_some_and(|r| !r.is_nan() && !domain(r));
+    let z = arg.to_complex()?;
+    let res = match func {
+        Func::Sqrt if outside_domain(|r| r >= 0.0) => z.sqrt(),
+        Func::Ln if outside_domain(|r| r >= 0.0) => z.ln(),
+        Func::Asin if outside_domain(|r| r.abs() <= 1.0) => z.asin(),
+        Func::Acos if outside_domain(|r| r.abs() <= 1.0) => z.acos(),
+        Func::Acosh if outside_domain(|r| r >= 1.0) => z.acosh(),
+        Func::Atanh if outside_domain(|r| r.abs() <= 1.0) => z.atanh(),
+        _ if real.is_some() => return Ok(None),
+        Func::Sqrt => z.sqrt(),
+        Func::Ln => z.ln(),
+        Func::Exp => z.exp(),
+        Func::Exp2 => z.expf(2.0),
+        Func::Sin => z.sin(),
+        Func::Sinh => z.sinh(),
+        Func::Asin => z.asin(),
+        Func::Asinh => z.asinh(),
+        Func::Cos => z.cos(),
+        Func::Cosh => z.cosh(),
+        Func::Acos => z.acos(),
+        Func::Acosh => z.acosh(),
+        Func::Tan => z.tan(),
+        Func::Tanh => z.tanh(),
+        Func::Atan => z.atan(),
+        Func::Atanh => z.atanh(),
+        Func::Sq => z * z,
+        Func::Cube => z * z * z,
+        Func::Cbrt => z.cbrt(),
+        Func::Recip => z.inv(),
+        _ => return Ok(None),
+    };
+    Ok(Some(Value::from(res)))
+}
+
+// Decimals are contagious over every other real number, so `0.1d + 0.2` stays a decimal
+fn decimal_op(
+    lhs: Value,
+    rhs: Value,
+    op: fn(Decimal, Decimal) -> Option<Decimal>,
+) -> Result<Value, InterpretError> {
+    let rhs = rhs.to_decimal()?;
+    if rhs.is_zero() && op(Decimal::ONE, rhs).is_none() {
+        // Division by zero, same as for the other number types
+        return Ok(Value::NaN);
+    }
+    op(lhs.to_decimal()?, rhs)
+        .map(Value::Decimal)
+        .ok_or_else(|| InterpretError::RuntimeError("Decimal overflow".to_string()))
+}
+
+fn decimal_pow(base: Decimal, exp: i64) -> Result<Value, InterpretError> {
+    let overflow = || InterpretError::RuntimeError("Decimal overflow".to_string());
+    // By squaring, so huge exponents take a few steps. The base is only squared when more
+    // of the exponent is left, which means it overflowing makes the result overflow too
+    let zero = base.is_zero();
+    let (mut res, mut base, mut rest) = (Decimal::ONE, base, exp.unsigned_abs());
+    while rest > 0 {
+        if rest & 1 == 1 {
+            res = res.checked_mul(base).ok_or_else(overflow)?;
+        }
+        rest >>= 1;
+        if rest > 0 {
+            base = base.checked_mul(base).ok_or_else(overflow)?;
+        }
+    }
+    if exp < 0 {
+        // Otherwise a power too small to be told from zero has a reciprocal too large to hold
+        if zero {
+            return Ok(Value::NaN);
+        }
+        res = Decimal::ONE.checked_div(res).ok_or_else(overflow)?;
+    }
+    Ok(Value::Decimal(res))
+}
+
+// Built-ins which have an exact decimal version
+fn decimal_func(func: &Func, arg: &Value, settings: &Settings) -> Option<Value> {
+    let Value::Decimal(decimal) = arg else {
+        return None;
+    };
+    Some(match func {
+        Func::Round => {
+            Value::Decimal(decimal.round_dp_with_strategy(0, settings.rounding.strategy()))
+        }
+        Func::Ceil => Value::Decimal(decimal.ceil()),
+        Func::Floor => Value::Decimal(decimal.floor()),
+        Func::Fract => Value::Decimal(decimal.fract()),
+        Func::Sq => Value::Decimal(decimal.checked_mul(*decimal)?),
+        Func::Cube => Value::Decimal(decimal.checked_mul(*decimal)?.checked_mul(*decimal)?),
+        Func::Recip if decimal.is_zero() => Value::NaN,
+        Func::Recip => Value::Decimal(Decimal::ONE.checked_div(*decimal)?),
+        _ => return None,
+    })
+}
+
+// Values in one unit expressed in another unit with the same dimensions
+fn convert(value: f64, from: &Unit, to: &Unit) -> Result<f64, InterpretError> {
+    if from.dims == to.dims {
+        Ok(value * from.factor / to.factor)
+    } else {
+        Err(InterpretError::RuntimeError(format!(
+            "Incompatible units, cannot convert {} to {}",
+            from, to
+        )))
+    }
+}
+
+// The result of a binary operator applied to evaluated operands
+pub(crate) fn operate(
+    left: Value,
+    operator: &Token,
+    right: Value,
+) -> Result<Value, InterpretError> {
+    let val = match operator {
+        Token::Plus => (left + right)?,
+        Token::Minus => (left - right)?,
+        Token::Mult => (left * right)?,
+        Token::Div => (left / right)?,
+        Token::Mod => (left % right)?,
+        Token::BitAnd => (left & right)?,
+        Token::Pipe => (left | right)?,
+        Token::BitXor => (left ^ right)?,
+        Token::Shl => (left << right)?,
+        Token::Shr => (left >> right)?,
+        Token::Pow => left.pow(right)?,
+        Token::And => Value::Bool(left.truthy() && right.truthy()),
+        Token::Or => Value::Bool(left.truthy() || right.truthy()),
+        Token::Eq => Value::Bool(left == right),
+        Token::Ne => Value::Bool(left != right),
+        Token::Gte => Value::Bool(left >= right),
+        Token::Gt => Value::Bool(left > right),
+        Token::Lte => Value::Bool(left <= right),
+        Token::Lt => Value::Bool(left < right),
+        _ => unreachable!(),
+    };
+    Ok(val)
+}
+
+// The value of a field, from r.name
+pub(crate) fn field(record: Value, name: &str) -> Result<Value, InterpretError> {
+    let Value::Record(mut fields) = record else {
+        return Err(InterpretError::InvalidArgument(format!(
+            "Cannot get field {} of {}, it isn't a record",
+            name, record
+        )));
+    };
+    fields.remove(name).ok_or_else(|| {
+        let msg = format!("Record has no field {}", name);
+        match suggest(name, fields.keys().map(String::as_str)) {
+            Some(similar) => {
+                InterpretError::RuntimeError(format!("{}, did you mean `{}`?", msg, similar))
+            }
+            None => InterpretError::RuntimeError(msg),
+        }
+    })
+}
+
+// A copy of the record with the fields replaced, or added if it didn't have them
+pub(crate) fn update(
+    record: Value,
+    changes: Vec<(String, Value)>,
+) -> Result<Value, InterpretError> {
+    let mut fields = record.to_record()?;
+    fields.extend(changes);
+    Ok(Value::Record(fields))
+}
+
+// An element of a list or tuple, or a character of a string, from xs[i]
+pub(crate) fn index(value: Value, index: &Value) -> Result<Value, InterpretError> {
+    match value {
+        Value::List(mut elems) | Value::Tuple(mut elems) => {
+            let position = position(index, elems.len())?;
+            Ok(elems.swap_remove(position))
+        }
+        Value::String(string) => {
+            let chars = string.chars().collect::<Vec<_>>();
+            Ok(Value::String(
+                chars[position(index, chars.len())?].to_string(),
+            ))
+        }
+        value => Err(InterpretError::InvalidArgument(format!(
+            "Cannot index {}, it isn't a list, tuple or string",
+            value
+        ))),
+    }
+}
+
+// Where an index is in a sequence of len elements, counting from the end when it's negative
+fn position(index: &Value, len: usize) -> Result<usize, InterpretError> {
+    let index = index.to_int()?;
+    let position = if index < 0 { index + len as i64 } else { index };
+    usize::try_from(position)
+        .ok()
+        .filter(|position| *position < len)
+        .ok_or_else(|| {
+            InterpretError::RuntimeError(format!("Index {} out of range for length {}", index, len))
+        })
+}
+
+// Every step-th element from start up to but not including end, from xs[start:end:step]
+pub(crate) fn slice(value: Value, bounds: [Option<Value>; 3]) -> Result<Value, InterpretError> {
+    let [start, end, step] = bounds.map(|bound| bound.map(|bound| bound.to_int()).transpose());
+    let (start, end, step) = (start?, end?, step?.unwrap_or(1));
+    if step == 0 {
+        return Err(InterpretError::InvalidArgument(
+            "Slice step cannot be 0".to_string(),
+        ));
+    }
+    let pick = |elems: Vec<Value>| {
+        let positions = slice_positions(start, end, step, elems.len());
+        positions.into_iter().map(|i| elems[i].clone()).collect()
+    };
+    Ok(match value {
+        Value::List(elems) => Value::List(pick(elems)),
+        Value::Tuple(elems) => Value::Tuple(pick(elems)),
+        Value::String(string) => {
+            let chars = string.chars().collect::<Vec<_>>();
+            let positions = slice_positions(start, end, step, chars.len());
+            Value::String(positions.into_iter().map(|i| chars[i]).collect())
+        }
+        value => {
+            return Err(InterpretError::InvalidArgument(format!(
+                "Cannot slice {}, it isn't a list, tuple or string",
+                value
+            )))
+        }
+    })
+}
+
+// When no arm of a match fits, which can only be told when it runs
+pub(crate) fn unmatched(value: &Value) -> InterpretError {
+    InterpretError::RuntimeError(format!("No match arm fits {}", value.literal()))
+}
+
+// The format pattern of an interpolated string, with a placeholder for each expression
+pub(crate) fn pattern(parts: &[Part]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Text(text) => text.replace('{', "{{").replace('}', "}}"),
+            Part::Expr(_, None) => "{}".to_string(),
+            Part::Expr(_, Some(spec)) => format!("{{:{}}}", spec),
+        })
+        .collect()
+}
+
+// The positions a slice picks out of len elements. Like in Python, bounds past either end are
+// moved to it, and a negative step goes backwards from the end
+fn slice_positions(start: Option<i64>, end: Option<i64>, step: i64, len: usize) -> Vec<usize> {
+    let len = len as i64;
+    let clamp = |bound: i64, low: i64, high: i64| {
+        if bound < 0 { bound + len } else { bound }.clamp(low, high)
+    };
+    if step > 0 {
+        let start = start.map_or(0, |start| clamp(start, 0, len));
+        let end = end.map_or(len, |end| clamp(end, 0, len));
+        (start..end)
+            .step_by(step as usize)
+            .map(|i| i as usize)
+            .collect()
+    } else {
+        // -1 is before the first element
+        let start = start.map_or(len - 1, |start| clamp(start, -1, len - 1));
+        let end = end.map_or(-1, |end| clamp(end, -1, len - 1));
+        std::iter::successors(Some(start), |i| i.checked_add(step))
+            .take_while(|i| *i > end)
+            .map(|i| i as usize)
+            .collect()
+    }
+}
+
+pub(crate) fn convert_to(value: Value, target: Unit) -> Result<Value, InterpretError> {
+    match value {
+        Value::Quantity(value, unit) => {
+            Ok(Value::Quantity(convert(value, &unit, &target)?, target))
+        }
+        Value::Duration(duration) => Ok(Value::Quantity(
+            convert(datetime::secs(duration), &Unit::second(), &target)?,
+            target,
+        )),
+        value => Err(InterpretError::RuntimeError(format!(
+            "Cannot convert {} to {}",
+            value, target
+        ))),
+    }
+}
+
+// Dates stay dates when moved by whole days
+fn shift(date: Value, by: TimeDelta) -> Result<Value, InterpretError> {
+    let out_of_range = || InterpretError::RuntimeError("Date out of range".to_string());
+    match date {
+        Value::Date(date) if by.subsec_nanos() == 0 && by.num_seconds() % 86400 == 0 => Ok(
+            Value::Date(date.checked_add_signed(by).ok_or_else(out_of_range)?),
+        ),
+        date => Ok(Value::DateTime(
+            date.to_datetime()?
+                .checked_add_signed(by)
+                .ok_or_else(out_of_range)?,
+        )),
+    }
+}
+
+// Scaling past the range of a duration is NaN, like float overflow
+fn scale(duration: TimeDelta, factor: f64) -> Value {
+    datetime::from_secs(datetime::secs(duration) * factor)
+        .map(Value::Duration)
+        .unwrap_or(Value::NaN)
+}
+
+// Quantities can only be ordered against others with the same dimensions
+fn comparable(list: &[Value]) -> Result<(), InterpretError> {
+    let mut quantities = list
+        .iter()
+        .filter(|elem| matches!(elem, Value::Quantity(_, _)));
+    let Some(Value::Quantity(_, first)) = quantities.next() else {
+        return Ok(());
+    };
+    for elem in list {
+        match elem {
+            Value::Quantity(_, unit) if unit.dims == first.dims => {}
+            Value::Quantity(_, unit) => {
+                return Err(InterpretError::RuntimeError(format!(
+                    "Incompatible units, cannot compare {} to {}",
+                    unit, first
+                )))
+            }
+            elem => {
+                return Err(InterpretError::RuntimeError(format!(
+                    "Cannot compare {} to a quantity in {}",
+                    elem, first
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+// Built-ins which keep the unit of a quantity
+fn quantity_func(func: &Func, arguments: &[Value]) -> Result<Option<Value>, InterpretError> {
+    let Some(Value::Quantity(value, unit)) = arguments.first() else {
+        return Ok(None);
+    };
+    Ok(Some(match func {
+        Func::Sq => Value::quantity(value.powi(2), unit.powi(2)?),
+        Func::Cube => Value::quantity(value.powi(3), unit.powi(3)?),
+        Func::Recip => Value::quantity(value.recip(), unit.powi(-1)?),
+        Func::Sqrt | Func::Cbrt => {
+            let n = if *func == Func::Sqrt { 2 } else { 3 };
+            let root = unit.root(n).ok_or_else(|| {
+                InterpretError::RuntimeError(format!(
+                    "Cannot take {} of {}, the powers of its units must divide by {}",
+                    func, unit, n
+                ))
+            })?;
+            Value::quantity(value.powf(1.0 / f64::from(n)), root)
+        }
+        Func::Round => Value::Quantity(value.round(), unit.clone()),
+        Func::Ceil => Value::Quantity(value.ceil(), unit.clone()),
+        Func::Floor => Value::Quantity(value.floor(), unit.clone()),
+        _ => return Ok(None),
+    }))
+}
+
+// Plain integers take the type of the fixed width side, eg. 0xffu8 + 1 == 0u8
+fn fixed_op(
+    lhs: Value,
+    rhs: Value,
+    op: fn(BigInt, BigInt) -> BigInt,
+) -> Result<Value, InterpretError> {
+    let ty = match (&lhs, &rhs) {
+        (Value::Fixed(_, lhs), Value::Fixed(_, rhs)) if lhs != rhs => {
+            return Err(InterpretError::RuntimeError(format!(
+                "Mismatched integer types {} and {}",
+                lhs.name(),
+                rhs.name()
+            )))
+        }
+        (Value::Fixed(_, ty), _) | (_, Value::Fixed(_, ty)) => *ty,
+        _ => {
+            return Err(InterpretError::RuntimeError(
+                "Expected a fixed width integer".to_string(),
+            ))
+        }
+    };
+    Ok(Value::Fixed(op(lhs.to_bigint()?, rhs.to_bigint()?), ty))
+}
+
+// Shifting further than the width has the same effect as shifting by the width
+fn fixed_shift(
+    int: BigInt,
+    ty: IntType,
+    rhs: &Value,
+    op: fn(BigInt, u32) -> BigInt,
+) -> Result<Value, InterpretError> {
+    Ok(Value::Fixed(op(int, shift_amount(rhs)?.min(ty.bits())), ty))
+}
+
+// Exact arithmetic for integers and rationals
+fn rational_op(
+    lhs: Value,
+    rhs: Value,
+    op: fn(BigRational, BigRational) -> BigRational,
+) -> Result<Value, InterpretError> {
+    Ok(Value::from(op(lhs.to_rational()?, rhs.to_rational()?)))
+}
+
+// Built-ins which stay exact for integers and rationals rather than falling to float
+fn exact_func(func: &Func, arg: &Value) -> Option<Value> {
+    if !matches!(arg, Value::Int(_) | Value::BigInt(_) | Value::Rational(_)) {
+        return None;
+    }
+    let ratio = arg.to_rational().ok()?;
+    Some(match func {
+        Func::Sq => Value::from(&ratio * &ratio),
+        Func::Cube => Value::from(&ratio * &ratio * &ratio),
+        Func::Round => Value::from(ratio.round()),
+        Func::Ceil => Value::from(ratio.ceil()),
+        Func::Floor => Value::from(ratio.floor()),
+        Func::Fract => Value::from(ratio.fract()),
+        Func::Recip if ratio.is_zero() => Value::NaN,
+        Func::Recip => Value::from(ratio.recip()),
+        _ => return None,
+    })
+}
+
+fn big_to_f64(int: &BigInt) -> f64 {
+    int.to_f64().unwrap_or(f64::NAN)
+}
+
+fn pow_exponent(exp: i64) -> Result<u32, InterpretError> {
+    u32::try_from(exp.unsigned_abs())
+        .map_err(|_| InterpretError::RuntimeError(format!("Exponent {} is too large", exp)))
+}
+
+fn big_pow(base: &BigInt, exp: u32) -> Result<Value, InterpretError> {
+    limit_bits(base, exp)?;
+    Ok(Value::from(base.pow(exp)))
+}
+
+// Estimated from below with the size of the base, so powers of two are exact and nothing is
+// more than twice the limit. Only printing the result is slow, not computing it
+fn limit_bits(base: &BigInt, exp: u32) -> Result<(), InterpretError> {
+    if base.bits().saturating_sub(1).saturating_mul(u64::from(exp)) >= MAX_BITS {
+        return Err(too_many_bits());
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone, PartialEq)]
+fn too_many_bits() -> InterpretError {
+    InterpretError::RuntimeError(format!(
+        "Result too large, integers are limited to {} bits",
+        MAX_BITS
+    ))
+}
+
+fn shift_amount(rhs: &Value) -> Result<u32, InterpretError> {
+    let amount = rhs.to_int()?;
+    if amount < 0 {
+        return Err(InterpretError::InvalidArgument(format!(
+            "Cannot shift by a negative amount {}",
+            amount
+        )));
+    }
+    u32::try_from(amount)
+        .map_err(|_| InterpretError::RuntimeError(format!("Shift amount {} is too large", amount)))
+}
+
+// Applies an integer operation on the i64 fast path, promoting to a BigInt on overflow
+fn int_op(
+    lhs: i64,
+    rhs: i64,
+    checked: fn(i64, i64) -> Option<i64>,
+    big: fn(BigInt, BigInt) -> BigInt,
+) -> Value {
+    checked(lhs, rhs)
+        .map(Value::Int)
+        .unwrap_or_else(|| Value::from(big(BigInt::from(lhs), BigInt::from(rhs))))
+}
+
+#[derive(Debug, Clone)]
 pub struct Function {
-    closure: HashMap<String, Value>,
-    parameters: Vec<String>,
+    // Only the variables the body uses, captured when the function was defined
+    closure: Rc<Scope>,
+    parameters: Rc<[Pattern]>,
     arity: usize,
-    body: Expr,
+    body: Rc<Expr>,
+    // Named functions defined together, which are in scope when the body runs so that they
+    // can be called recursively. Empty for lambdas
+    group: Rc<[(String, Function)]>,
+    // Bytecode for the body, compiled the first time the vm calls it and shared by clones
+    code: Code,
+    signature: Signature,
+}
+
+// The signature is inferred from the rest, so it's left out
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.closure == other.closure
+            && self.parameters == other.parameters
+            && self.arity == other.arity
+            && self.body == other.body
+            && self.group == other.group
+            && self.code == other.code
+    }
+}
+
+// The result of an expression in tail position. Calls to user functions are handed back to
+// the caller instead of being made, so tail recursion runs in a loop rather than on the stack
+enum Tail {
+    Value(Value),
+    Call(Function, Vec<Value>),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum InterpretError {
-    UnknownVariable(String),
+    // The name, and a similar one that is defined
+    UnknownVariable(String, Option<String>),
     Uncallable(Value),
     UnInvokedFunction(String),
     WrongArity(Value, usize, usize),
     InvalidArgument(String),
     RuntimeError(String),
+    // Found before running, with the expression that's wrong
+    TypeError(String, Expr),
 }
 
 impl Function {
-    fn new(parameters: Vec<String>, body: Expr, closure: HashMap<String, Value>) -> Self {
+    fn new(parameters: Vec<Pattern>, body: Expr, closure: HashMap<String, Value>) -> Self {
         Self {
             arity: parameters.len(),
-            parameters,
-            body,
-            closure,
+            parameters: parameters.into(),
+            body: Rc::new(body),
+            closure: Rc::new(Scope::new(closure)),
+            group: Rc::new([]),
+            code: Code::default(),
+            signature: Signature::default(),
         }
     }
 
-    fn call(&self, args: Vec<Value>) -> Result<Value, InterpretError> {
-        let mut interpreter = Interpreter::with_env(self.closure.clone());
+    // The scope the body runs in, with the arguments and the other functions of the group
+    pub(crate) fn enter(
+        &self,
+        args: Vec<Value>,
+        caller: &Interpreter,
+    ) -> Result<Interpreter, InterpretError> {
+        let mut interpreter = caller.scope(Scope::child(self.closure.clone()));
+        interpreter.depth += 1;
+        for (name, member) in self.group.iter() {
+            let member = Function {
+                group: self.group.clone(),
+                ..member.clone()
+            };
+            interpreter.define(name.clone(), Value::Fn(member));
+        }
         let len = args.len();
         for (i, arg) in args.into_iter().enumerate() {
             let param = self
@@ -221,57 +1157,484 @@ impl Function {
                     "Expected {} arguments",
                     len,
                 )))?;
-            if param == "_" {
-                continue;
+            match param {
+                Pattern::Bind(name) => interpreter.define(name.clone(), arg),
+                Pattern::Wildcard => {}
+                pattern => {
+                    for (name, value) in interpreter.unpack(pattern, &arg)? {
+                        interpreter.define(name, value);
+                    }
+                }
             }
-            interpreter.define(param.clone(), arg)
         }
-        interpreter.interpret_expr(&self.body)
+        Ok(interpreter)
     }
-}
 
-impl Interpreter {
-    pub fn new() -> Self {
-        Self {
-            env: Self::default_env(),
+    // The function being called with this many arguments, checked before they are evaluated
+    pub(crate) fn callee(value: Value, args: usize) -> Result<Function, InterpretError> {
+        let Value::Fn(func) = value else {
+            return Err(InterpretError::Uncallable(value));
+        };
+        let arity = func.arity;
+        if args != arity {
+            Err(InterpretError::WrongArity(Value::Fn(func), args, arity))
+        } else {
+            Ok(func)
         }
     }
 
-    pub fn interpret(&mut self, stmt: Stmt) -> Result<Value, InterpretError> {
-        match stmt {
-            Stmt::Assign(name, expr) => {
-                let val = self.interpret_expr(&expr)?;
-                self.env.insert(name, val.clone()); // Some way to remove this clone?
-                Ok(val)
-            }
-            Stmt::Expr(expr) => {
-                let ans = self.interpret_expr(&expr)?;
-                self.env.insert("ans".to_string(), ans.clone()); // Some way to remove this clone?
-                Ok(ans)
-            }
-            Stmt::Undef(names) => {
-                names.iter().for_each(|name| {
-                    self.env.remove(name);
-                });
-                Ok(Value::Unit)
-            }
-        }
+    pub(crate) fn body(&self) -> &Expr {
+        &self.body
     }
 
-    fn default_env() -> HashMap<String, Value> {
-        HashMap::from_iter([
-            ("pi".to_string(), Value::Float(PI)),
-            ("e".to_string(), Value::Float(E)),
-        ])
+    pub(crate) fn code(&self) -> &Code {
+        &self.code
+    }
+
+    // eg. (num, [num]) -> [num], shown next to saved functions
+    pub(crate) fn signature(&self) -> Type {
+        self.signature.get(|| {
+            let group = self
+                .group
+                .iter()
+                .map(|(name, member)| member.definition(name))
+                .collect::<Vec<_>>();
+            types::signature(&self.parameters, &self.body, &self.closure, &group)
+        })
+    }
+
+    fn definition(&self, name: &str) -> Definition {
+        Definition {
+            name: name.to_string(),
+            parameters: self.parameters.to_vec(),
+            body: self.body.as_ref().clone(),
+        }
+    }
+
+    fn call(&self, args: Vec<Value>, caller: &Interpreter) -> Result<Value, InterpretError> {
+        caller.check_depth()?;
+        // Non-tail recursion nests calls, so more stack is allocated when it runs low
+        if caller.settings.backend == Backend::Vm {
+            return stacker::maybe_grow(RED_ZONE, STACK_SIZE, || vm::call(self, args, caller));
+        }
+        let mut function = self.clone();
+        let mut args = args;
+        let mut tail_calls = 0;
+        // Tail calls replace the current call instead of nesting inside it
+        loop {
+            let interpreter = function.enter(args, caller)?;
+            let tail =
+                stacker::maybe_grow(RED_ZONE, STACK_SIZE, || interpreter.tail(&function.body));
+            match tail? {
+                Tail::Value(value) => return Ok(value),
+                Tail::Call(next, next_args) => {
+                    tail_calls += 1;
+                    caller.check_tail_calls(tail_calls)?;
+                    function = next;
+                    args = next_args;
+                }
+            }
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_env(Self::default_env())
+    }
+
+    pub fn interpret(&mut self, stmt: Stmt) -> Result<Value, InterpretError> {
+        // Each statement is simplified just before it runs, so it sees what the ones before it
+        // defined and configured
+        if let Stmt::Seq(stmts) = stmt {
+            return stmts
+                .into_iter()
+                .try_fold(Value::Unit, |_, stmt| self.interpret(stmt));
+        }
+        if self.settings.typecheck {
+            types::check(&stmt, &self.env)?;
+        }
+        let stmt = simplify::stmt(stmt, self);
+        self.execute(stmt)
+    }
+
+    fn execute(&mut self, stmt: Stmt) -> Result<Value, InterpretError> {
+        if self.settings.backend == Backend::Vm {
+            return vm::run(self, &stmt);
+        }
+        match stmt {
+            Stmt::Assign(name, expr) => {
+                let val = self.interpret_expr(&expr)?;
+                self.define(name, val.clone()); // Some way to remove this clone?
+                Ok(val)
+            }
+            Stmt::Unpack(pattern, expr) => {
+                let val = self.interpret_expr(&expr)?;
+                for (name, value) in self.unpack(&pattern, &val)? {
+                    self.define(name, value);
+                }
+                Ok(val)
+            }
+            Stmt::Expr(expr) => {
+                let ans = self.interpret_expr(&expr)?;
+                self.define("ans".to_string(), ans.clone()); // Some way to remove this clone?
+                Ok(ans)
+            }
+            Stmt::Undef(names) => {
+                self.undef(&names);
+                Ok(Value::Unit)
+            }
+            Stmt::Config(name, expr) => {
+                let val = self.interpret_expr(&expr)?;
+                self.configure(&name, &val)?;
+                Ok(val)
+            }
+            Stmt::Seq(stmts) => stmts
+                .into_iter()
+                .try_fold(Value::Unit, |_, stmt| self.execute(stmt)),
+            Stmt::Fn(definitions) => Ok(self.define_fns(definitions)),
+            Stmt::Simplify(expr) => Ok(self.simplify(expr)),
+        }
+    }
+
+    pub(crate) fn undef(&mut self, names: &[String]) {
+        let env = Rc::make_mut(&mut self.env);
+        names.iter().for_each(|name| env.remove(name));
+    }
+
+    pub(crate) fn define_fns(&mut self, definitions: Vec<Definition>) -> Value {
+        // The functions only see the variables from before they were defined, and
+        // each other when called
+        let names = definitions
+            .iter()
+            .map(|definition| definition.name.clone())
+            .collect::<Vec<_>>();
+        let closure = self.env.capture(
+            definitions.iter().map(|definition| &definition.body),
+            &names,
+        );
+        let group = definitions
+            .into_iter()
+            .map(|definition| {
+                let function =
+                    Function::new(definition.parameters, definition.body, closure.clone());
+                (definition.name, function)
+            })
+            .collect::<Rc<[_]>>();
+        let mut res = Value::Unit;
+        for (name, member) in group.iter() {
+            res = Value::Fn(Function {
+                group: group.clone(),
+                ..member.clone()
+            });
+            self.define(name.clone(), res.clone());
+        }
+        res
+    }
+
+    fn default_env() -> HashMap<String, Value> {
+        CONSTANTS
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), Value::Float(value)))
+            .collect()
+    }
+
+    // The value of a built-in constant, as long as it still has it
+    pub(crate) fn constant(&self, name: &str) -> Option<Value> {
+        let (_, value) = CONSTANTS
+            .into_iter()
+            .find(|(constant, _)| *constant == name)?;
+        let value = Value::Float(value);
+        (self.env.get(name) == Some(&value)).then_some(value)
+    }
+
+    pub(crate) fn simplify(&self, expr: Expr) -> Value {
+        Value::String(simplify::expr(expr, self).format())
     }
 
     pub fn with_env(env: HashMap<String, Value>) -> Self {
-        Self { env }
+        Self {
+            env: Rc::new(Scope::new(env)),
+            settings: Settings::default(),
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn check_depth(&self) -> Result<(), InterpretError> {
+        let max_depth = self.settings.max_depth;
+        if self.depth >= max_depth {
+            return Err(InterpretError::RuntimeError(format!(
+                "Maximum recursion depth of {} exceeded",
+                max_depth
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_tail_calls(&self, tail_calls: usize) -> Result<(), InterpretError> {
+        let max_tail_calls = self.settings.max_depth.saturating_mul(TAIL_CALLS_PER_DEPTH);
+        if tail_calls > max_tail_calls {
+            return Err(InterpretError::RuntimeError(format!(
+                "Maximum of {} tail calls exceeded",
+                max_tail_calls
+            )));
+        }
+        Ok(())
+    }
+
+    // An interpreter for a block or let, whose definitions are gone once it ends
+    pub(crate) fn child(&self) -> Self {
+        self.scope(Scope::child(self.env.clone()))
+    }
+
+    // An interpreter for a nested scope, keeping the settings and call depth
+    fn scope(&self, env: Scope) -> Self {
+        Self {
+            env: Rc::new(env),
+            settings: self.settings,
+            depth: self.depth,
+        }
+    }
+
+    // Like interpret_expr, but a call to a user function is handed back rather than made when
+    // it's the last thing the expression does
+    fn tail(&self, expr: &Expr) -> Result<Tail, InterpretError> {
+        match expr {
+            Expr::If(cond, then, else_expr) => {
+                if self.interpret_expr(cond)?.truthy() {
+                    self.tail(then)
+                } else {
+                    self.tail(else_expr)
+                }
+            }
+            Expr::Grouping(expr) => self.tail(expr),
+            Expr::Let(bindings, body) => self.bindings(bindings)?.tail(body),
+            Expr::Match(subject, arms) => {
+                let (interpreter, body) = self.arm(subject, arms)?;
+                interpreter.tail(body)
+            }
+            Expr::Call(name, args) => {
+                let func = Function::callee(self.interpret_expr(name)?, args.len())?;
+                let mut vals = vec![];
+                for arg in args.iter() {
+                    vals.push(self.interpret_expr(arg)?);
+                }
+                Ok(Tail::Call(func, vals))
+            }
+            expr => self.interpret_expr(expr).map(Tail::Value),
+        }
+    }
+
+    // The body of the first arm that matches, and a scope with what its pattern bound
+    fn arm<'a>(
+        &self,
+        subject: &Expr,
+        arms: &'a [Arm],
+    ) -> Result<(Interpreter, &'a Expr), InterpretError> {
+        let value = self.interpret_expr(subject)?;
+        for arm in arms {
+            let Some(bindings) = self.matches(&arm.pattern, &value)? else {
+                continue;
+            };
+            let mut interpreter = self.child();
+            for (name, value) in bindings {
+                interpreter.define(name, value);
+            }
+            let guard = match &arm.guard {
+                Some(guard) => interpreter.interpret_expr(guard)?.truthy(),
+                None => true,
+            };
+            if guard {
+                return Ok((interpreter, &arm.body));
+            }
+        }
+        Err(unmatched(&value))
+    }
+
+    // What the pattern binds if the value fits it
+    pub(crate) fn matches(
+        &self,
+        pattern: &Pattern,
+        value: &Value,
+    ) -> Result<Option<Vec<(String, Value)>>, InterpretError> {
+        self.fits(pattern, value, false)
+    }
+
+    fn fits(
+        &self,
+        pattern: &Pattern,
+        value: &Value,
+        unpacking: bool,
+    ) -> Result<Option<Vec<(String, Value)>>, InterpretError> {
+        let mut bindings = vec![];
+        Ok(self
+            .bind(pattern, value, unpacking, &mut bindings)?
+            .then_some(bindings))
+    }
+
+    fn bind(
+        &self,
+        pattern: &Pattern,
+        value: &Value,
+        unpacking: bool,
+        bindings: &mut Vec<(String, Value)>,
+    ) -> Result<bool, InterpretError> {
+        Ok(match (pattern, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Bind(name), value) => {
+                bindings.push((name.clone(), value.clone()));
+                true
+            }
+            (Pattern::Literal(expr), value) => *value == self.literal(expr)?,
+            (Pattern::List(patterns), Value::List(elems)) => {
+                self.elements(patterns, elems, Value::List, unpacking, bindings)?
+            }
+            // Lists can be unpacked like tuples too, since functions like quadr give them back
+            (Pattern::Tuple(patterns), Value::List(elems)) if unpacking => {
+                self.elements(patterns, elems, Value::List, unpacking, bindings)?
+            }
+            (Pattern::Tuple(patterns), Value::Tuple(elems)) => {
+                self.elements(patterns, elems, Value::Tuple, unpacking, bindings)?
+            }
+            _ => false,
+        })
+    }
+
+    // The patterns before a ..rest take elements from the start and the ones after it from the
+    // end, the rest is collected into the same kind of sequence
+    fn elements(
+        &self,
+        patterns: &[Pattern],
+        elems: &[Value],
+        sequence: fn(Vec<Value>) -> Value,
+        unpacking: bool,
+        bindings: &mut Vec<(String, Value)>,
+    ) -> Result<bool, InterpretError> {
+        let rest = patterns
+            .iter()
+            .position(|pattern| matches!(pattern, Pattern::Rest(_)));
+        let (before, after) = match rest {
+            Some(rest) => (&patterns[..rest], &patterns[rest + 1..]),
+            None if patterns.len() == elems.len() => (patterns, &[][..]),
+            None => return Ok(false),
+        };
+        if elems.len() < before.len() + after.len() {
+            return Ok(false);
+        }
+        let end = elems.len() - after.len();
+        let pairs = before
+            .iter()
+            .zip(elems)
+            .chain(after.iter().zip(&elems[end..]));
+        for (pattern, elem) in pairs {
+            if !self.bind(pattern, elem, unpacking, bindings)? {
+                return Ok(false);
+            }
+        }
+        if let Some(Pattern::Rest(Some(name))) = rest.map(|rest| &patterns[rest]) {
+            bindings.push((name.clone(), sequence(elems[before.len()..end].to_vec())));
+        }
+        Ok(true)
+    }
+
+    // What a pattern from let or a parameter binds, which has to fit the value
+    pub(crate) fn unpack(
+        &self,
+        pattern: &Pattern,
+        value: &Value,
+    ) -> Result<Vec<(String, Value)>, InterpretError> {
+        match self.fits(pattern, value, true)? {
+            Some(bindings) => Ok(bindings),
+            None => Err(self.mismatch(pattern, value)?),
+        }
+    }
+
+    // Why a value doesn't fit a pattern, from the innermost part that doesn't
+    fn mismatch(&self, pattern: &Pattern, value: &Value) -> Result<InterpretError, InterpretError> {
+        let error = |reason: String| {
+            InterpretError::InvalidArgument(format!(
+                "Cannot unpack {} into {}, {}",
+                value.literal(),
+                pattern.format(),
+                reason
+            ))
+        };
+        let (patterns, elems, kind) = match (pattern, value) {
+            (Pattern::List(patterns) | Pattern::Tuple(patterns), Value::List(elems)) => {
+                (patterns, elems, "list")
+            }
+            (Pattern::Tuple(patterns), Value::Tuple(elems)) => (patterns, elems, "tuple"),
+            (Pattern::List(_), _) => return Ok(error("expected a list".to_string())),
+            (Pattern::Tuple(_), _) => return Ok(error("expected a tuple or list".to_string())),
+            _ => return Ok(error("they don't match".to_string())),
+        };
+        let rest = patterns
+            .iter()
+            .position(|pattern| matches!(pattern, Pattern::Rest(_)));
+        let fixed = patterns.len() - usize::from(rest.is_some());
+        if elems.len() < fixed || (rest.is_none() && elems.len() > fixed) {
+            let least = if rest.is_some() { "at least " } else { "" };
+            return Ok(error(format!(
+                "the {} has {} elements instead of {}{}",
+                kind,
+                elems.len(),
+                least,
+                fixed
+            )));
+        }
+        // The lengths fit, so it's one of the elements that doesn't
+        let (before, after) = patterns.split_at(rest.unwrap_or(patterns.len()));
+        let after = after.get(1..).unwrap_or_default();
+        let pairs = before
+            .iter()
+            .zip(elems)
+            .chain(after.iter().zip(&elems[elems.len() - after.len()..]));
+        for (pattern, elem) in pairs {
+            if self.fits(pattern, elem, true)?.is_none() {
+                return self.mismatch(pattern, elem);
+            }
+        }
+        Ok(error("they don't match".to_string()))
+    }
+
+    // The value of a literal pattern, without compiling it when the vm is running
+    fn literal(&self, expr: &Expr) -> Result<Value, InterpretError> {
+        match expr {
+            Expr::Unary(expr, Token::Minus) => self.literal(expr)?.neg(),
+            Expr::Float(float) => self.float(*float),
+            Expr::Int(int) => Ok(Value::Int(*int)),
+            Expr::String(string) => Ok(Value::String(string.clone())),
+            Expr::Bool(bool) => Ok(Value::Bool(*bool)),
+            Expr::Nil => Ok(Value::Nil),
+            expr => self.interpret_expr(expr),
+        }
+    }
+
+    // A scope with each binding defined in turn, so that they can use the ones before them
+    fn bindings(&self, bindings: &[(String, Expr)]) -> Result<Interpreter, InterpretError> {
+        let mut interpreter = self.child();
+        for (name, expr) in bindings {
+            let value = interpreter.interpret_expr(expr)?;
+            interpreter.define(name.clone(), value);
+        }
+        Ok(interpreter)
     }
 
     pub fn interpret_expr(&self, expr: &Expr) -> Result<Value, InterpretError> {
+        if self.settings.backend == Backend::Vm {
+            return vm::eval(self, expr);
+        }
         match expr {
-            Expr::Float(float) => Ok(Value::Float(*float)),
+            Expr::Float(float) => self.float(*float),
+            Expr::Decimal(decimal) => Ok(Value::Decimal(*decimal)),
+            Expr::Time(Literal::Date(date)) => Ok(Value::Date(*date)),
+            Expr::Time(Literal::DateTime(datetime)) => Ok(Value::DateTime(*datetime)),
+            Expr::Time(Literal::Duration(duration)) => Ok(Value::Duration(*duration)),
+            Expr::Quantity(expr, unit) => Ok(Value::quantity(
+                self.interpret_expr(expr)?.to_float()?,
+                self.unit(unit)?,
+            )),
+            Expr::Convert(expr, unit) => self.convert(expr, unit),
             Expr::List(elems) => {
                 let mut elements = vec![];
                 for elem in elems.iter() {
@@ -286,9 +1649,47 @@ impl Interpreter {
                 }
                 Ok(Value::Tuple(elements))
             }
+            Expr::Record(fields) => Ok(Value::Record(self.fields(fields)?.into_iter().collect())),
+            Expr::Update(record, fields) => {
+                update(self.interpret_expr(record)?, self.fields(fields)?)
+            }
+            Expr::Field(record, name) => field(self.interpret_expr(record)?, name),
+            Expr::Index(expr, i) => index(self.interpret_expr(expr)?, &self.interpret_expr(i)?),
+            Expr::Slice(expr, bounds) => {
+                let value = self.interpret_expr(expr)?;
+                let mut values = [None, None, None];
+                for (value, bound) in values.iter_mut().zip(bounds) {
+                    if let Some(bound) = bound {
+                        *value = Some(self.interpret_expr(bound)?);
+                    }
+                }
+                slice(value, values)
+            }
+            Expr::Interpolated(parts) => {
+                let mut values = vec![];
+                for expr in parts.iter().filter_map(Part::expr) {
+                    values.push(self.interpret_expr(expr)?);
+                }
+                strings::format(&pattern(parts), &values).map(Value::String)
+            }
             Expr::Nan => Ok(Value::NaN),
             Expr::Nil => Ok(Value::Nil),
+            Expr::Let(bindings, body) => self.bindings(bindings)?.interpret_expr(body),
+            Expr::Match(subject, arms) => {
+                let (interpreter, body) = self.arm(subject, arms)?;
+                interpreter.interpret_expr(body)
+            }
+            Expr::Block(stmts) => {
+                // Anything defined in the block is gone once it ends
+                let mut interpreter = self.child();
+                stmts
+                    .iter()
+                    .try_fold(Value::Unit, |_, stmt| interpreter.execute(stmt.clone()))
+            }
             Expr::Int(int) => Ok(Value::Int(*int)),
+            Expr::BigInt(int) => Ok(Value::from(int.clone())),
+            Expr::Fixed(int, ty) => Ok(Value::Fixed(int.clone(), *ty)),
+            Expr::Imaginary(im) => Ok(Value::from(Complex64::new(0.0, *im))),
             Expr::String(string) => Ok(Value::String(string.clone())),
             Expr::Bool(bool) => Ok(Value::Bool(*bool)),
             Expr::If(cond, then, else_expr) => {
@@ -298,357 +1699,723 @@ impl Interpreter {
                     self.interpret_expr(else_expr)
                 }
             }
-            Expr::Binary(left, operator, right) => {
-                let left = self.interpret_expr(left)?;
-                let right = self.interpret_expr(right)?;
-                let val = match operator {
-                    Token::Plus => (left + right)?,
-                    Token::Minus => (left - right)?,
-                    Token::Mult => (left * right)?,
-                    Token::Div => (left / right)?,
-                    Token::Mod => (left % right)?,
-                    Token::BitAnd => (left & right)?,
-                    Token::Pipe => (left | right)?,
-                    Token::BitXor => (left ^ right)?,
-                    Token::Shl => (left << right)?,
-                    Token::Shr => (left >> right)?,
-                    Token::Pow => left.pow(right)?,
-                    Token::And => Value::Bool(left.truthy() && right.truthy()),
-                    Token::Or => Value::Bool(left.truthy() || right.truthy()),
-                    Token::Eq => Value::Bool(left == right),
-                    Token::Ne => Value::Bool(left != right),
-                    Token::Gte => Value::Bool(left >= right),
-                    Token::Gt => Value::Bool(left > right),
-                    Token::Lte => Value::Bool(left <= right),
-                    Token::Lt => Value::Bool(left < right),
-                    _ => unreachable!(),
-                };
-                Ok(val)
-            }
-            Expr::Fun(params, body) => Ok(Value::Fn(Function::new(
-                params.to_owned(),
-                *body.to_owned(),
-                self.env.clone(),
-            ))),
+            Expr::Binary(left, operator, right) => self.binary(left, operator, right),
+            Expr::Fun(params, body) => Ok(Value::Fn(self.closure(params, body))),
             Expr::Grouping(expr) => self.interpret_expr(expr),
             Expr::Unary(expr, operator) => match operator {
                 Token::Not => self.interpret_expr(expr)?.not(),
                 Token::Minus => Ok(self.interpret_expr(expr)?.neg()?),
                 _ => unreachable!(),
             },
-            Expr::Call(name, args) => {
-                let function = self.interpret_expr(name)?;
-                if let Value::Fn(func) = function {
-                    let arity = func.arity;
-                    if args.len() != arity {
-                        Err(InterpretError::WrongArity(
-                            Value::Fn(func),
-                            args.len(),
-                            arity,
-                        ))
-                    } else {
-                        let mut vals = vec![];
-                        for arg in args.iter() {
-                            vals.push(self.interpret_expr(arg)?);
-                        }
-                        func.call(vals)
-                    }
-                } else {
-                    Err(InterpretError::Uncallable(function))
-                }
-            }
-            Expr::Var(var) => {
-                if let Some(val) = self.env.get(var) {
-                    Ok(val.to_owned())
-                } else {
-                    Err(InterpretError::UnknownVariable(var.clone()))
-                }
-            }
-            Expr::Func(func, args) => {
-                let mut arguments = vec![];
-                for arg in args.iter() {
-                    arguments.push(self.interpret_expr(arg)?);
-                }
-                let arity = func.arity();
-                if arguments.len() != arity {
-                    return Err(InterpretError::WrongArity(
-                        Value::String(func.to_string()),
-                        arguments.len(),
-                        arity,
-                    ));
-                }
-                let val = match func {
-                    Func::Abs => return self.interpret_expr(&args[0])?.abs(),
-                    Func::Sin => arguments[0].to_float()?.sin(),
-                    Func::Sinh => arguments[0].to_float()?.sinh(),
-                    Func::Asin => arguments[0].to_float()?.asin(),
-                    Func::Asinh => arguments[0].to_float()?.asinh(),
-                    Func::Cos => arguments[0].to_float()?.cos(),
-                    Func::Cosh => arguments[0].to_float()?.cosh(),
-                    Func::Acos => arguments[0].to_float()?.acos(),
-                    Func::Acosh => arguments[0].to_float()?.acosh(),
-                    Func::Tan => arguments[0].to_float()?.tan(),
-                    Func::Tanh => arguments[0].to_float()?.tanh(),
-                    Func::Atan => arguments[0].to_float()?.atan(),
-                    Func::Atanh => arguments[0].to_float()?.tanh(),
-                    Func::Ln => arguments[0].to_float()?.ln(),
-                    Func::Log => arguments[1].to_float()?.log(arguments[0].to_float()?),
-                    Func::Degs => arguments[0].to_float()?.to_degrees(),
-                    Func::Rads => arguments[0].to_float()?.to_radians(),
-                    Func::Sq => arguments[0].to_float()?.powi(2),
-                    Func::Sqrt => arguments[0].to_float()?.sqrt(),
-                    Func::Cube => arguments[0].to_float()?.powi(3),
-                    Func::Cbrt => arguments[0].to_float()?.cbrt(),
-                    Func::Round => arguments[0].to_float()?.round(),
-                    Func::Ceil => arguments[0].to_float()?.ceil(),
-                    Func::Floor => arguments[0].to_float()?.floor(),
-                    Func::Exp => arguments[0].to_float()?.exp(),
-                    Func::Exp2 => arguments[0].to_float()?.exp2(),
-                    Func::Fract => arguments[0].to_float()?.fract(),
-                    Func::Recip => arguments[0].to_float()?.recip(),
-                    Func::Quadr => {
-                        let a = arguments[0].to_float()?;
-                        let b = arguments[1].to_float()?;
-                        let c = arguments[2].to_float()?;
-                        let n = (b.powi(2) - 4.0 * a * c).powf(0.5);
-                        let pos = (-b + n) / (2.0 * a);
-                        let neg = (-b - n) / (2.0 * a);
-                        return Ok(Value::List(vec![Value::Float(pos), Value::Float(neg)]));
-                    }
-                    Func::Map => {
-                        let list = arguments[0].to_list()?;
-                        let callable = arguments[1].to_callable()?;
-                        let mut result = vec![];
-                        for elem in list.into_iter() {
-                            result.push(callable.call(vec![elem])?);
-                        }
-                        return Ok(Value::List(result));
-                    }
-                    Func::Sum => {
-                        let list = arguments[0].to_list()?;
-                        let mut result = 0_f64;
-                        for elem in list.into_iter() {
-                            result += elem.to_float()?;
-                        }
-                        result
-                    }
-                    Func::Fold => {
-                        let list = arguments[0].to_list()?;
-                        let callable = arguments[1].to_callable()?;
-                        let mut acc = arguments[2].clone();
-                        for elem in list.into_iter() {
-                            acc = callable.call(vec![acc, elem])?;
-                        }
-                        return Ok(acc);
-                    }
-                    Func::Filter => {
-                        let list = arguments[0].to_list()?;
-                        let callable = arguments[1].to_callable()?;
-                        let mut result = vec![];
-                        for elem in list.into_iter() {
-                            if callable.call(vec![elem.clone()])?.truthy() {
-                                result.push(elem);
-                            }
-                        }
-                        return Ok(Value::List(result));
-                    }
-                    Func::Even => {
-                        return Ok(Value::Bool(arguments[0].to_int()? % 2 == 0));
-                    }
-                    Func::Odd => {
-                        return Ok(Value::Bool(arguments[0].to_int()? % 2 != 0));
-                    }
-                    Func::Fact => {
-                        let arg = arguments[0].to_int()?;
-                        if arg > 20 {
-                            return Err(InterpretError::InvalidArgument(format!(
-                                "Value too large {}",
-                                arg
-                            )));
-                        } else {
-                            return Ok(Value::Int(get_fact(arg)));
-                        }
-                    }
-                    Func::Range => {
-                        let start = arguments[0].to_int()?;
-                        let end = arguments[1].to_int()?;
-                        if start < 0 || end <= start {
-                            return Err(InterpretError::InvalidArgument(
-                                "Invalid range".to_string(),
-                            ));
-                        } else {
-                            let vec = (start..=end).map(Value::Int).collect::<Vec<_>>();
-                            return Ok(Value::List(vec));
-                        }
-                    }
-                    Func::Elem => {
-                        let elements = match &arguments[0] {
-                            Value::List(elems) => elems.to_owned(),
-                            Value::Tuple(elems) => elems.to_owned(),
-                            _ => {
-                                return Err(InterpretError::InvalidArgument(
-                                    "Cannot call elem on this data type".to_string(),
-                                ))
-                            }
-                        };
-                        let index = arguments[1].to_int()?;
-                        if index < 0 {
-                            return Err(InterpretError::InvalidArgument(
-                                "Indexes start at 0".to_string(),
-                            ));
-                        }
-
-                        let element =
-                            elements
-                                .get(index as usize)
-                                .ok_or(InterpretError::RuntimeError(format!(
-                                    "Index {} out of range 0..{}",
-                                    index,
-                                    elements.len() - 1
-                                )))?;
-
-                        return Ok(element.to_owned());
-                    }
-                    Func::Min => {
-                        let list = arguments[0].to_list()?;
-                        return Ok(list.into_iter().min().unwrap_or(Value::Nil));
-                    }
-                    Func::Max => {
-                        let list = arguments[0].to_list()?;
-                        return Ok(list.into_iter().max().unwrap_or(Value::Nil));
-                    }
-                };
-                Ok(Value::Float(val))
-            }
+            Expr::Call(_, _) => match self.tail(expr)? {
+                Tail::Value(value) => Ok(value),
+                Tail::Call(func, args) => func.call(args, self),
+            },
+            Expr::Var(var) => self.lookup(var),
+            // Returned early as built-ins mostly normalize their own results
+            Expr::Func(func, args) => return self.builtin(func, args),
         }
-        .map(|n| {
-            if let Value::Float(n) = n {
-                if !n.is_nan() && !n.is_infinite() {
-                    if n.is_subnormal() {
-                        Value::Float(n.round())
-                    } else {
-                        Value::Float(n)
-                    }
-                } else {
-                    Value::NaN
-                }
-            } else {
-                n
-            }
-        })
+        .and_then(|n| self.normalize(n))
     }
 
-    pub fn reset_vars(&mut self) {
-        self.env = Self::default_env()
+    fn fields(&self, fields: &[(String, Expr)]) -> Result<Vec<(String, Value)>, InterpretError> {
+        fields
+            .iter()
+            .map(|(name, expr)| Ok((name.clone(), self.interpret_expr(expr)?)))
+            .collect()
     }
 
-    pub fn define(&mut self, var: String, val: Value) {
-        self.env.insert(var, val);
+    fn binary(&self, left: &Expr, operator: &Token, right: &Expr) -> Result<Value, InterpretError> {
+        let left = self.interpret_expr(left)?;
+        let right = self.interpret_expr(right)?;
+        operate(left, operator, right)
     }
 
-    pub fn env(&self) -> &HashMap<String, Value> {
-        &self.env
-    }
-}
-
-fn get_fact(arg: i64) -> i64 {
-    match arg {
-        1 => 1,
-        2 => 2,
-        3 => 6,
-        4 => 24,
-        5 => 120,
-        6 => 720,
-        7 => 5040,
-        8 => 40320,
-        9 => 362880,
-        10 => 3628800,
-        11 => 39916800,
-        12 => 479001600,
-        13 => 6227020800,
-        14 => 87178291200,
-        15 => 1307674368000,
-        16 => 20922789888000,
-        17 => 355687428096000,
-        18 => 6402373705728000,
-        19 => 121645100408832000,
-        20 => 2432902008176640000,
-        _ => unreachable!(),
+    // Float literals are read as decimals when the decimal setting is on
+    pub(crate) fn float(&self, float: f64) -> Result<Value, InterpretError> {
+        if !self.settings.decimal {
+            return Ok(Value::Float(float));
+        }
+        Decimal::from_f64(float).map(Value::Decimal).ok_or_else(|| {
+            InterpretError::RuntimeError(format!("{} cannot be represented as a decimal", float))
+        })
     }
-}
-
-impl Error for InterpretError {}
 
-impl Display for InterpretError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::UnknownVariable(v) => format!("Unknown variable {}", v),
-                Self::Uncallable(f) => format!("Unknown function {}", f),
-                Self::UnInvokedFunction(f) => format!("Uninvoked function {}", f),
-                Self::InvalidArgument(m) => m.clone(),
-                Self::RuntimeError(m) => m.clone(),
-                Self::WrongArity(name, actual, expected) => format!(
-                    "Function {} takes {} arguments but {} were provided",
-                    name, expected, actual
-                ),
-            },
+    pub(crate) fn closure(&self, params: &[Pattern], body: &Expr) -> Function {
+        let bound = params
+            .iter()
+            .flat_map(Pattern::names)
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        Function::new(
+            params.to_owned(),
+            body.to_owned(),
+            self.env.capture([body], &bound),
         )
     }
-}
 
-impl Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Float(float) => inner_write(float, f),
-            Self::Int(int) => inner_write(int, f),
-            Self::Fn(func) => inner_write(func, f),
-            Self::String(string) => inner_write(string, f),
-            Self::Unit => inner_write("()", f),
-            Self::Nil => inner_write("nil", f),
-            Self::NaN => inner_write("NaN", f),
-            Self::Bool(bool) => inner_write(bool, f),
-            Self::List(elems) => inner_write(
-                format!(
-                    "[{}]",
-                    elems
-                        .iter()
-                        .map(|e| e.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ),
-                f,
-            ),
-            Self::Tuple(elems) => inner_write(
-                format!(
-                    "{{{}}}",
-                    elems
-                        .iter()
-                        .map(|e| e.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ),
-                f,
-            ),
+    pub(crate) fn lookup(&self, var: &str) -> Result<Value, InterpretError> {
+        if let Some(val) = self.env.get(var) {
+            Ok(val.to_owned())
+        } else if let Some(func) = parse::builtin(var) {
+            // A built-in named without arguments, when nothing else has the name
+            let (params, body) = parse::function_value(func);
+            Ok(Value::Fn(self.closure(&params, &body)))
+        } else {
+            let names = FNS.into_iter().chain(self.env.names());
+            Err(InterpretError::UnknownVariable(
+                var.to_string(),
+                suggest(var, names).map(str::to_string),
+            ))
         }
     }
-}
 
-impl Add for Value {
-    type Output = Result<Value, InterpretError>;
+    fn convert(&self, expr: &Expr, unit: &[(String, i32)]) -> Result<Value, InterpretError> {
+        let target = self.unit(unit)?;
+        convert_to(self.interpret_expr(expr)?, target)
+    }
 
-    fn add(self, rhs: Self) -> Self::Output {
-        let res = match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Value::Int(lhs + rhs),
-            (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs + rhs),
-            (Value::Int(lhs), Value::Float(rhs)) => Value::Float(lhs as f64 + rhs),
-            (Value::Float(lhs), Value::Int(rhs)) => Value::Float(lhs + rhs as f64),
-            (Value::List(mut lhs), Value::List(mut rhs)) => {
-                lhs.append(&mut rhs);
-                Value::List(lhs)
-            }
+    // Decimals are rounded to the precision setting, fixed width integers brought back into
+    // range and infinite floats and quantities become NaN
+    pub(crate) fn normalize(&self, n: Value) -> Result<Value, InterpretError> {
+        Ok(if let Value::Decimal(decimal) = n {
+            Value::Decimal(
+                decimal.round_dp_with_strategy(
+                    self.settings.precision,
+                    self.settings.rounding.strategy(),
+                ),
+            )
+        } else if let Value::Fixed(int, ty) = n {
+            let fit = ty.fit(int.clone(), self.settings.overflow).ok_or_else(|| {
+                InterpretError::RuntimeError(format!(
+                    "Integer overflow, {} does not fit in {}",
+                    int,
+                    ty.name()
+                ))
+            })?;
+            Value::Fixed(fit, ty)
+        } else if let Value::Float(n) = n {
+            if !n.is_nan() && !n.is_infinite() {
+                if n.is_subnormal() {
+                    Value::Float(n.round())
+                } else {
+                    Value::Float(n)
+                }
+            } else {
+                Value::NaN
+            }
+        } else if let Value::Quantity(value, _) = n {
+            if value.is_finite() {
+                n
+            } else {
+                Value::NaN
+            }
+        } else {
+            n
+        })
+    }
+
+    fn builtin(&self, func: &Func, args: &[Expr]) -> Result<Value, InterpretError> {
+        let mut arguments = vec![];
+        for arg in args.iter() {
+            arguments.push(self.interpret_expr(arg)?);
+        }
+        self.call_builtin(func, arguments)
+    }
+
+    pub(crate) fn call_builtin(
+        &self,
+        func: &Func,
+        arguments: Vec<Value>,
+    ) -> Result<Value, InterpretError> {
+        let arity = func.arity();
+        if arguments.len() != arity {
+            return Err(InterpretError::WrongArity(
+                Value::String(func.to_string()),
+                arguments.len(),
+                arity,
+            ));
+        }
+        if let Some(val) = arguments.first().and_then(|arg| exact_func(func, arg)) {
+            return Ok(val);
+        }
+        if let Some(val) = arguments
+            .first()
+            .and_then(|arg| decimal_func(func, arg, &self.settings))
+        {
+            return Ok(val);
+        }
+        if let Some(val) = quantity_func(func, &arguments)? {
+            return Ok(val);
+        }
+        if let Some(val) = complex_func(func, &arguments)? {
+            return Ok(val);
+        }
+        let val = match func {
+            Func::Abs => return arguments[0].abs(),
+            Func::Sin => arguments[0].to_float()?.sin(),
+            Func::Sinh => arguments[0].to_float()?.sinh(),
+            Func::Asin => arguments[0].to_float()?.asin(),
+            Func::Asinh => arguments[0].to_float()?.asinh(),
+            Func::Cos => arguments[0].to_float()?.cos(),
+            Func::Cosh => arguments[0].to_float()?.cosh(),
+            Func::Acos => arguments[0].to_float()?.acos(),
+            Func::Acosh => arguments[0].to_float()?.acosh(),
+            Func::Tan => arguments[0].to_float()?.tan(),
+            Func::Tanh => arguments[0].to_float()?.tanh(),
+            Func::Atan => arguments[0].to_float()?.atan(),
+            Func::Atanh => arguments[0].to_float()?.atanh(),
+            Func::Ln => arguments[0].to_float()?.ln(),
+            Func::Log => arguments[1].to_float()?.log(arguments[0].to_float()?),
+            Func::Degs => arguments[0].to_float()?.to_degrees(),
+            Func::Rads => arguments[0].to_float()?.to_radians(),
+            Func::Sq => arguments[0].to_float()?.powi(2),
+            Func::Sqrt => arguments[0].to_float()?.sqrt(),
+            Func::Cube => arguments[0].to_float()?.powi(3),
+            Func::Cbrt => arguments[0].to_float()?.cbrt(),
+            Func::Round => arguments[0].to_float()?.round(),
+            Func::Ceil => arguments[0].to_float()?.ceil(),
+            Func::Floor => arguments[0].to_float()?.floor(),
+            Func::Exp => arguments[0].to_float()?.exp(),
+            Func::Exp2 => arguments[0].to_float()?.exp2(),
+            Func::Fract => arguments[0].to_float()?.fract(),
+            Func::Recip => arguments[0].to_float()?.recip(),
+            Func::Float => arguments[0].to_float()?,
+            Func::Quadr => {
+                let a = arguments[0].to_complex()?;
+                let b = arguments[1].to_complex()?;
+                let c = arguments[2].to_complex()?;
+                let n = (b.powi(2) - 4.0 * a * c).sqrt();
+                let pos = (-b + n) / (2.0 * a);
+                let neg = (-b - n) / (2.0 * a);
+                return Ok(Value::List(vec![Value::from(pos), Value::from(neg)]));
+            }
+            Func::Re => arguments[0].to_complex()?.re,
+            Func::Im => arguments[0].to_complex()?.im,
+            Func::Arg => arguments[0].to_complex()?.arg(),
+            Func::Conj => return Ok(Value::from(arguments[0].to_complex()?.conj())),
+            Func::Map => {
+                let list = arguments[0].to_list()?;
+                let callable = arguments[1].to_callable()?;
+                let mut result = vec![];
+                for elem in list.into_iter() {
+                    result.push(callable.call(vec![elem], self)?);
+                }
+                return Ok(Value::List(result));
+            }
+            Func::Sum => {
+                let list = arguments[0].to_list()?;
+                // Quantities are added to each other, which fails if their units don't match
+                let mut result = None;
+                for elem in list.into_iter() {
+                    if !elem.is_numeric() && !matches!(elem, Value::Quantity(_, _)) {
+                        return Err(InterpretError::InvalidArgument(format!(
+                            "Expected float, got: {}",
+                            elem
+                        )));
+                    }
+                    result = Some(match result {
+                        Some(result) => (result + elem)?,
+                        None => elem,
+                    });
+                }
+                return Ok(result.unwrap_or(Value::Int(0)));
+            }
+            Func::Fold => {
+                let list = arguments[0].to_list()?;
+                let callable = arguments[1].to_callable()?;
+                let mut acc = arguments[2].clone();
+                for elem in list.into_iter() {
+                    acc = callable.call(vec![acc, elem], self)?;
+                }
+                return Ok(acc);
+            }
+            Func::Filter => {
+                let list = arguments[0].to_list()?;
+                let callable = arguments[1].to_callable()?;
+                let mut result = vec![];
+                for elem in list.into_iter() {
+                    if callable.call(vec![elem.clone()], self)?.truthy() {
+                        result.push(elem);
+                    }
+                }
+                return Ok(Value::List(result));
+            }
+            Func::Even => {
+                return Ok(Value::Bool(arguments[0].to_bigint()?.is_even()));
+            }
+            Func::Odd => {
+                return Ok(Value::Bool(arguments[0].to_bigint()?.is_odd()));
+            }
+            Func::Fact => {
+                let arg = arguments[0].to_int()?;
+                if arg < 0 {
+                    return Err(InterpretError::InvalidArgument(format!(
+                        "Cannot take factorial of negative number {}",
+                        arg
+                    )));
+                } else if arg > MAX_FACTORIAL {
+                    return Err(InterpretError::InvalidArgument(format!(
+                        "Value too large {}",
+                        arg
+                    )));
+                } else {
+                    let fact = (2..=arg).fold(BigInt::from(1), |acc, n| acc * n);
+                    return Ok(Value::from(fact));
+                }
+            }
+            Func::Range => {
+                let start = arguments[0].to_int()?;
+                let end = arguments[1].to_int()?;
+                if start < 0 || end <= start {
+                    return Err(InterpretError::InvalidArgument("Invalid range".to_string()));
+                } else {
+                    let vec = (start..=end).map(Value::Int).collect::<Vec<_>>();
+                    return Ok(Value::List(vec));
+                }
+            }
+            // Same as xs[i]
+            Func::Elem => return index(arguments[0].clone(), &arguments[1]),
+            // A copy with one element replaced
+            Func::Set => {
+                let value = arguments[2].clone();
+                return match arguments[0].clone() {
+                    Value::List(mut elems) => {
+                        let position = position(&arguments[1], elems.len())?;
+                        elems[position] = value;
+                        Ok(Value::List(elems))
+                    }
+                    Value::Tuple(mut elems) => {
+                        let position = position(&arguments[1], elems.len())?;
+                        elems[position] = value;
+                        Ok(Value::Tuple(elems))
+                    }
+                    arg => Err(InterpretError::InvalidArgument(format!(
+                        "Cannot set an element of {}, it isn't a list or tuple",
+                        arg
+                    ))),
+                };
+            }
+            Func::Min => {
+                let list = arguments[0].to_list()?;
+                comparable(&list)?;
+                return Ok(list.into_iter().min().unwrap_or(Value::Nil));
+            }
+            Func::Now => {
+                return Ok(Value::DateTime(
+                    Local::now().fixed_offset().trunc_subsecs(0),
+                ))
+            }
+            Func::Today => return Ok(Value::Date(Local::now().date_naive())),
+            Func::Weekday => {
+                let date = arguments[0].to_datetime()?;
+                return Ok(Value::String(date.format("%A").to_string()));
+            }
+            Func::AddBusinessDays => {
+                let days = arguments[1].to_int()?;
+                let date = arguments[0].to_datetime()?.date_naive();
+                let shifted = datetime::add_business_days(date, days)
+                    .ok_or_else(|| InterpretError::RuntimeError("Date out of range".to_string()))?;
+                return shift(arguments[0].clone(), shifted - date);
+            }
+            Func::Strftime => {
+                let literal = match &arguments[0] {
+                    Value::Date(date) => Literal::Date(*date),
+                    value => Literal::DateTime(value.to_datetime()?),
+                };
+                let Value::String(pattern) = &arguments[1] else {
+                    return Err(InterpretError::InvalidArgument(format!(
+                        "Expected format string, got: {}",
+                        arguments[1]
+                    )));
+                };
+                return datetime::strftime(literal, pattern)
+                    .map(Value::String)
+                    .ok_or_else(|| {
+                        InterpretError::InvalidArgument(format!(
+                            "Invalid format \"{}\" for {}",
+                            pattern, arguments[0]
+                        ))
+                    });
+            }
+            Func::Cast => {
+                let Value::String(name) = &arguments[1] else {
+                    return Err(InterpretError::InvalidArgument(format!(
+                        "Expected type name, got: {}",
+                        arguments[1]
+                    )));
+                };
+                let int = match &arguments[0] {
+                    Value::Float(float) => BigInt::from_f64(float.trunc()).ok_or_else(|| {
+                        InterpretError::InvalidArgument(format!("Cannot cast {}", float))
+                    })?,
+                    arg => arg.to_bigint()?,
+                };
+                if name == "int" {
+                    return Ok(Value::from(int));
+                }
+                let ty = IntType::from_name(name).ok_or_else(|| {
+                    InterpretError::InvalidArgument(format!(
+                        "Unknown integer type {}, expected int, u8, u16, u32, u64, i8, i16, i32 or i64",
+                        name
+                    ))
+                })?;
+                return Ok(Value::Fixed(ty.wrap(&int), ty));
+            }
+            Func::Rotl | Func::Rotr => {
+                let n = arguments[1].to_int()?;
+                let n = if *func == Func::Rotl { n } else { -n };
+                return match &arguments[0] {
+                    Value::Fixed(int, ty) => Ok(Value::Fixed(ty.rotate_left(int, n), *ty)),
+                    arg => Ok(Value::Int(
+                        arg.to_int()?.rotate_left(n.rem_euclid(64) as u32),
+                    )),
+                };
+            }
+            Func::Popcount => {
+                return match &arguments[0] {
+                    Value::Fixed(int, ty) => Ok(Value::Int(ty.popcount(int) as i64)),
+                    Value::BigInt(int) if int.is_positive() => {
+                        Ok(Value::Int(int.magnitude().count_ones() as i64))
+                    }
+                    arg => Ok(Value::Int(arg.to_int()?.count_ones() as i64)),
+                };
+            }
+            Func::Clz => {
+                return match &arguments[0] {
+                    Value::Fixed(int, ty) => Ok(Value::Int(ty.leading_zeros(int) as i64)),
+                    arg => Ok(Value::Int(arg.to_int()?.leading_zeros() as i64)),
+                };
+            }
+            Func::Tz => {
+                let datetime = arguments[0].to_datetime()?;
+                let offset = match &arguments[1] {
+                    Value::String(utc) if utc == "Z" || utc == "UTC" => FixedOffset::east_opt(0),
+                    Value::String(offset) => offset.parse::<FixedOffset>().ok(),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    InterpretError::InvalidArgument(format!(
+                        "Expected offset such as \"+05:30\", got: {}",
+                        arguments[1]
+                    ))
+                })?;
+                return Ok(Value::DateTime(datetime.with_timezone(&offset)));
+            }
+            Func::Keys => {
+                let fields = arguments[0].to_record()?;
+                return Ok(Value::List(fields.into_keys().map(Value::String).collect()));
+            }
+            Func::Values => {
+                let fields = arguments[0].to_record()?;
+                return Ok(Value::List(fields.into_values().collect()));
+            }
+            // Pairs of name and value
+            Func::Entries => {
+                let fields = arguments[0].to_record()?;
+                return Ok(Value::List(
+                    fields
+                        .into_iter()
+                        .map(|(name, value)| Value::Tuple(vec![Value::String(name), value]))
+                        .collect(),
+                ));
+            }
+            Func::Max => {
+                let list = arguments[0].to_list()?;
+                comparable(&list)?;
+                return Ok(list.into_iter().max().unwrap_or(Value::Nil));
+            }
+            Func::Len => {
+                let len = match &arguments[0] {
+                    Value::String(string) => string.chars().count(),
+                    Value::List(elems) | Value::Tuple(elems) => elems.len(),
+                    Value::Record(fields) => fields.len(),
+                    arg => {
+                        return Err(InterpretError::InvalidArgument(format!(
+                            "Expected string, list, tuple or record, got: {}",
+                            arg
+                        )))
+                    }
+                };
+                return Ok(Value::Int(len as i64));
+            }
+            Func::Upper => return Ok(Value::String(arguments[0].to_str()?.to_uppercase())),
+            Func::Lower => return Ok(Value::String(arguments[0].to_str()?.to_lowercase())),
+            Func::Trim => return Ok(Value::String(arguments[0].to_str()?.trim().to_string())),
+            Func::Split => {
+                let string = arguments[0].to_str()?;
+                let separator = arguments[1].to_str()?;
+                if separator.is_empty() {
+                    return Err(InterpretError::InvalidArgument(
+                        "Cannot split on an empty string, use chars instead".to_string(),
+                    ));
+                }
+                let parts = string.split(separator);
+                return Ok(Value::List(
+                    parts.map(|part| Value::String(part.to_string())).collect(),
+                ));
+            }
+            // Strings are joined as they are, anything else as it's displayed
+            Func::Join => {
+                let list = arguments[0].to_list()?;
+                let separator = arguments[1].to_str()?;
+                let parts = list
+                    .iter()
+                    .map(|elem| match elem {
+                        Value::String(string) => string.clone(),
+                        elem => elem.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                return Ok(Value::String(parts.join(separator)));
+            }
+            Func::Replace => {
+                let string = arguments[0].to_str()?;
+                let from = arguments[1].to_str()?;
+                let to = arguments[2].to_str()?;
+                return Ok(Value::String(string.replace(from, to)));
+            }
+            Func::Contains => {
+                let contains = match (&arguments[0], &arguments[1]) {
+                    (Value::String(string), needle) => string.contains(needle.to_str()?),
+                    (Value::List(elems) | Value::Tuple(elems), needle) => elems.contains(needle),
+                    (Value::Record(fields), name) => fields.contains_key(name.to_str()?),
+                    (arg, _) => {
+                        return Err(InterpretError::InvalidArgument(format!(
+                            "Expected string, list, tuple or record, got: {}",
+                            arg
+                        )))
+                    }
+                };
+                return Ok(Value::Bool(contains));
+            }
+            Func::StartsWith => {
+                let string = arguments[0].to_str()?;
+                return Ok(Value::Bool(string.starts_with(arguments[1].to_str()?)));
+            }
+            // Counted in chars, with a negative start counting from the end
+            Func::Substr => {
+                let chars = arguments[0].to_str()?.chars().collect::<Vec<_>>();
+                let start = arguments[1].to_int()?;
+                let len = arguments[2].to_int()?;
+                if len < 0 {
+                    return Err(InterpretError::InvalidArgument(format!(
+                        "Cannot take a substring of negative length {}",
+                        len
+                    )));
+                }
+                let count = chars.len() as i64;
+                let start = if start < 0 { start + count } else { start }.clamp(0, count);
+                let end = start.saturating_add(len).min(count);
+                let substr = &chars[start as usize..end as usize];
+                return Ok(Value::String(substr.iter().collect()));
+            }
+            Func::Chars => {
+                let chars = arguments[0].to_str()?.chars();
+                return Ok(Value::List(
+                    chars.map(|c| Value::String(c.to_string())).collect(),
+                ));
+            }
+            Func::ToStr => {
+                return Ok(match &arguments[0] {
+                    Value::String(string) => Value::String(string.clone()),
+                    arg => Value::String(arg.to_string()),
+                })
+            }
+            Func::ParseNum => {
+                let string = arguments[0].to_str()?;
+                return strings::parse_number(string).ok_or_else(|| {
+                    InterpretError::InvalidArgument(format!(
+                        "Cannot parse \"{}\" as a number",
+                        string
+                    ))
+                });
+            }
+            // A tuple fills one placeholder per element, eg. format("{} of {}", {1, 3})
+            Func::Format => {
+                let pattern = arguments[0].to_str()?;
+                let values = match &arguments[1] {
+                    Value::Tuple(elems) => elems.as_slice(),
+                    value => std::slice::from_ref(value),
+                };
+                return strings::format(pattern, values).map(Value::String);
+            }
+        };
+        self.normalize(Value::Float(val))
+    }
+
+    // Variables holding quantities can be used as units, eg. let furlong = 201.168 m
+    pub(crate) fn unit(&self, symbols: &[(String, i32)]) -> Result<Unit, InterpretError> {
+        symbols.iter().try_fold(Unit::one(), |acc, (name, power)| {
+            let unit = match self.env.get(name) {
+                Some(Value::Quantity(value, unit)) => {
+                    Unit::named(name, value * unit.factor, unit.dims)
+                }
+                _ => Unit::builtin(name).ok_or_else(|| {
+                    InterpretError::RuntimeError(format!("Unknown unit {}", name))
+                })?,
+            };
+            acc.mul(&unit.powi(*power)?)
+        })
+    }
+
+    pub fn reset_vars(&mut self) {
+        self.env = Rc::new(Scope::new(Self::default_env()))
+    }
+
+    // Only clones the scope if a nested one still points at it, which it doesn't between inputs
+    pub fn define(&mut self, var: String, val: Value) {
+        Rc::make_mut(&mut self.env).insert(var, val);
+    }
+
+    pub fn env(&self) -> &HashMap<String, Value> {
+        self.env.vars()
+    }
+
+    pub(crate) fn configure(&mut self, name: &str, value: &Value) -> Result<(), InterpretError> {
+        self.settings.set(name, value)
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+}
+
+impl Error for InterpretError {}
+
+impl InterpretError {
+    // Type errors point at the expression that's wrong, if it can be found in the input
+    pub fn report(&self, input: &str) -> String {
+        match self {
+            Self::TypeError(_, expr) => match parse::locate(input, expr) {
+                Some(span) => parse::report(self, span, input),
+                None => self.to_string(),
+            },
+            err => err.to_string(),
+        }
+    }
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::UnknownVariable(v, None) => format!("Unknown variable {}", v),
+                Self::UnknownVariable(v, Some(similar)) => {
+                    format!("Unknown variable {}, did you mean `{}`?", v, similar)
+                }
+                Self::Uncallable(f) => format!("Unknown function {}", f),
+                Self::UnInvokedFunction(f) => format!("Uninvoked function {}", f),
+                Self::InvalidArgument(m) => m.clone(),
+                Self::RuntimeError(m) => m.clone(),
+                Self::TypeError(m, _) => m.clone(),
+                Self::WrongArity(name, actual, expected) => format!(
+                    "Function {} takes {} arguments but {} were provided",
+                    name, expected, actual
+                ),
+            },
+        )
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Float(float) => inner_write(float, f),
+            Self::Int(int) => inner_write(int, f),
+            Self::BigInt(int) => inner_write(int, f),
+            Self::Fixed(int, ty) => write!(f, "{}{}", int, ty.name()),
+            Self::Rational(ratio) => inner_write(ratio, f),
+            Self::Decimal(decimal) => inner_write(decimal, f),
+            Self::Quantity(value, unit) => write!(f, "{} {}", value, unit),
+            Self::Date(date) => inner_write(date, f),
+            Self::DateTime(datetime) => inner_write(datetime::format_datetime(datetime), f),
+            Self::Duration(duration) => inner_write(datetime::format_duration(*duration), f),
+            Self::Complex(complex) => match (complex.re, complex.im) {
+                (0.0, im) => write!(f, "{}i", im),
+                (re, im) if im < 0.0 => write!(f, "{}-{}i", re, -im),
+                (re, im) => write!(f, "{}+{}i", re, im),
+            },
+            Self::Fn(func) => inner_write(func, f),
+            Self::String(string) => inner_write(string, f),
+            Self::Unit => inner_write("()", f),
+            Self::Nil => inner_write("nil", f),
+            Self::NaN => inner_write("NaN", f),
+            Self::Bool(bool) => inner_write(bool, f),
+            Self::List(elems) => inner_write(
+                format!(
+                    "[{}]",
+                    elems
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                f,
+            ),
+            Self::Tuple(elems) => inner_write(
+                format!(
+                    "{{{}}}",
+                    elems
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                f,
+            ),
+            Self::Record(fields) => inner_write(
+                format!(
+                    "{{{}}}",
+                    fields
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}", name, value))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                f,
+            ),
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = Result<Value, InterpretError>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let res = match (self, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => int_op(lhs, rhs, i64::checked_add, Add::add),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                fixed_op(lhs, rhs, Add::add)?
+            }
+            (lhs @ Value::Fixed(_, _), Value::Float(rhs)) => Value::Float(lhs.to_float()? + rhs),
+            (Value::Float(lhs), rhs @ Value::Fixed(_, _)) => Value::Float(lhs + rhs.to_float()?),
+            (Value::BigInt(lhs), Value::Int(rhs)) => Value::from(lhs + rhs),
+            (Value::Int(lhs), Value::BigInt(rhs)) => Value::from(lhs + rhs),
+            (Value::BigInt(lhs), Value::BigInt(rhs)) => Value::from(lhs + rhs),
+            (
+                lhs @ Value::Rational(_),
+                rhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Rational(_)) => {
+                rational_op(lhs, rhs, Add::add)?
+            }
+            (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs + rhs),
+            (Value::Int(lhs), Value::Float(rhs)) => Value::Float(lhs as f64 + rhs),
+            (Value::Float(lhs), Value::Int(rhs)) => Value::Float(lhs + rhs as f64),
+            (Value::BigInt(lhs), Value::Float(rhs)) => Value::Float(big_to_f64(&lhs) + rhs),
+            (Value::Float(lhs), Value::BigInt(rhs)) => Value::Float(lhs + big_to_f64(&rhs)),
+            (lhs @ Value::Rational(_), Value::Float(rhs)) => Value::Float(lhs.to_float()? + rhs),
+            (Value::Float(lhs), rhs @ Value::Rational(_)) => Value::Float(lhs + rhs.to_float()?),
+            (Value::List(mut lhs), Value::List(mut rhs)) => {
+                lhs.append(&mut rhs);
+                Value::List(lhs)
+            }
             (Value::List(mut lhs), other) => {
                 lhs.push(other);
                 Value::List(lhs)
@@ -657,6 +2424,32 @@ impl Add for Value {
                 lhs.push_str(&rhs);
                 Value::String(lhs)
             }
+            (lhs @ Value::Decimal(_), rhs) | (lhs, rhs @ Value::Decimal(_))
+                if rhs.is_real() && lhs.is_real() =>
+            {
+                decimal_op(lhs, rhs, Decimal::checked_add)?
+            }
+            (lhs @ Value::Complex(_), rhs) | (lhs, rhs @ Value::Complex(_))
+                if lhs.is_numeric() && rhs.is_numeric() =>
+            {
+                complex_op(lhs, rhs, Add::add)?
+            }
+            (Value::Quantity(lhs, unit), Value::Quantity(rhs, rhs_unit)) => {
+                let rhs = convert(rhs, &rhs_unit, &unit)?;
+                Value::Quantity(Add::add(lhs, rhs), unit)
+            }
+            (
+                date @ (Value::Date(_) | Value::DateTime(_)),
+                duration @ (Value::Duration(_) | Value::Quantity(_, _)),
+            )
+            | (
+                duration @ (Value::Duration(_) | Value::Quantity(_, _)),
+                date @ (Value::Date(_) | Value::DateTime(_)),
+            ) => shift(date, duration.to_duration()?)?,
+            (Value::Duration(lhs), Value::Duration(rhs)) => lhs
+                .checked_add(&rhs)
+                .map(Value::Duration)
+                .ok_or_else(|| InterpretError::RuntimeError("Duration out of range".to_string()))?,
             (Value::NaN, _) | (_, Value::NaN) => Value::NaN,
             _ => {
                 return Err(InterpretError::InvalidArgument(
@@ -674,10 +2467,60 @@ impl Sub for Value {
 
     fn sub(self, rhs: Self) -> Self::Output {
         let res = match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Value::Int(lhs - rhs),
+            (Value::Int(lhs), Value::Int(rhs)) => int_op(lhs, rhs, i64::checked_sub, Sub::sub),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                fixed_op(lhs, rhs, Sub::sub)?
+            }
+            (lhs @ Value::Fixed(_, _), Value::Float(rhs)) => Value::Float(lhs.to_float()? - rhs),
+            (Value::Float(lhs), rhs @ Value::Fixed(_, _)) => Value::Float(lhs - rhs.to_float()?),
+            (Value::BigInt(lhs), Value::Int(rhs)) => Value::from(lhs - rhs),
+            (Value::Int(lhs), Value::BigInt(rhs)) => Value::from(lhs - rhs),
+            (Value::BigInt(lhs), Value::BigInt(rhs)) => Value::from(lhs - rhs),
+            (
+                lhs @ Value::Rational(_),
+                rhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Rational(_)) => {
+                rational_op(lhs, rhs, Sub::sub)?
+            }
             (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs - rhs),
             (Value::Int(lhs), Value::Float(rhs)) => Value::Float(lhs as f64 - rhs),
             (Value::Float(lhs), Value::Int(rhs)) => Value::Float(lhs - rhs as f64),
+            (Value::BigInt(lhs), Value::Float(rhs)) => Value::Float(big_to_f64(&lhs) - rhs),
+            (Value::Float(lhs), Value::BigInt(rhs)) => Value::Float(lhs - big_to_f64(&rhs)),
+            (lhs @ Value::Rational(_), Value::Float(rhs)) => Value::Float(lhs.to_float()? - rhs),
+            (Value::Float(lhs), rhs @ Value::Rational(_)) => Value::Float(lhs - rhs.to_float()?),
+            (lhs @ Value::Decimal(_), rhs) | (lhs, rhs @ Value::Decimal(_))
+                if rhs.is_real() && lhs.is_real() =>
+            {
+                decimal_op(lhs, rhs, Decimal::checked_sub)?
+            }
+            (lhs @ Value::Complex(_), rhs) | (lhs, rhs @ Value::Complex(_))
+                if lhs.is_numeric() && rhs.is_numeric() =>
+            {
+                complex_op(lhs, rhs, Sub::sub)?
+            }
+            (Value::Quantity(lhs, unit), Value::Quantity(rhs, rhs_unit)) => {
+                let rhs = convert(rhs, &rhs_unit, &unit)?;
+                Value::Quantity(Sub::sub(lhs, rhs), unit)
+            }
+            (
+                date @ (Value::Date(_) | Value::DateTime(_)),
+                duration @ (Value::Duration(_) | Value::Quantity(_, _)),
+            ) => shift(date, -duration.to_duration()?)?,
+            (Value::Date(lhs), Value::Date(rhs)) => Value::Duration(lhs - rhs),
+            (
+                lhs @ (Value::Date(_) | Value::DateTime(_)),
+                rhs @ (Value::Date(_) | Value::DateTime(_)),
+            ) => Value::Duration(lhs.to_datetime()? - rhs.to_datetime()?),
+            (Value::Duration(lhs), Value::Duration(rhs)) => lhs
+                .checked_sub(&rhs)
+                .map(Value::Duration)
+                .ok_or_else(|| InterpretError::RuntimeError("Duration out of range".to_string()))?,
             (Value::NaN, _) | (_, Value::NaN) => Value::NaN,
             _ => {
                 return Err(InterpretError::InvalidArgument(
@@ -695,10 +2538,56 @@ impl Mul for Value {
 
     fn mul(self, rhs: Self) -> Self::Output {
         let res = match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Value::Int(lhs * rhs),
+            (Value::Int(lhs), Value::Int(rhs)) => int_op(lhs, rhs, i64::checked_mul, Mul::mul),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                fixed_op(lhs, rhs, Mul::mul)?
+            }
+            (lhs @ Value::Fixed(_, _), Value::Float(rhs)) => Value::Float(lhs.to_float()? * rhs),
+            (Value::Float(lhs), rhs @ Value::Fixed(_, _)) => Value::Float(lhs * rhs.to_float()?),
+            (Value::BigInt(lhs), Value::Int(rhs)) => Value::from(lhs * rhs),
+            (Value::Int(lhs), Value::BigInt(rhs)) => Value::from(lhs * rhs),
+            (Value::BigInt(lhs), Value::BigInt(rhs)) => Value::from(lhs * rhs),
+            (
+                lhs @ Value::Rational(_),
+                rhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Rational(_)) => {
+                rational_op(lhs, rhs, Mul::mul)?
+            }
             (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs * rhs),
             (Value::Int(lhs), Value::Float(rhs)) => Value::Float(lhs as f64 * rhs),
             (Value::Float(lhs), Value::Int(rhs)) => Value::Float(lhs * rhs as f64),
+            (Value::BigInt(lhs), Value::Float(rhs)) => Value::Float(big_to_f64(&lhs) * rhs),
+            (Value::Float(lhs), Value::BigInt(rhs)) => Value::Float(lhs * big_to_f64(&rhs)),
+            (lhs @ Value::Rational(_), Value::Float(rhs)) => Value::Float(lhs.to_float()? * rhs),
+            (Value::Float(lhs), rhs @ Value::Rational(_)) => Value::Float(lhs * rhs.to_float()?),
+            (lhs @ Value::Decimal(_), rhs) | (lhs, rhs @ Value::Decimal(_))
+                if rhs.is_real() && lhs.is_real() =>
+            {
+                decimal_op(lhs, rhs, Decimal::checked_mul)?
+            }
+            (lhs @ Value::Complex(_), rhs) | (lhs, rhs @ Value::Complex(_))
+                if lhs.is_numeric() && rhs.is_numeric() =>
+            {
+                complex_op(lhs, rhs, Mul::mul)?
+            }
+            (Value::Quantity(lhs, lhs_unit), Value::Quantity(rhs, rhs_unit)) => {
+                Value::quantity(lhs * rhs, lhs_unit.mul(&rhs_unit)?)
+            }
+            (Value::Quantity(value, unit), scale) | (scale, Value::Quantity(value, unit))
+                if scale.is_real() =>
+            {
+                Value::Quantity(value * scale.to_float()?, unit)
+            }
+            (Value::Duration(duration), factor) | (factor, Value::Duration(duration))
+                if factor.is_real() =>
+            {
+                scale(duration, factor.to_float()?)
+            }
             (Value::NaN, _) | (_, Value::NaN) => Value::NaN,
             _ => {
                 return Err(InterpretError::InvalidArgument(
@@ -716,10 +2605,62 @@ impl Div for Value {
 
     fn div(self, rhs: Self) -> Self::Output {
         let res = match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Value::Float(lhs as f64 / rhs as f64),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                if rhs.to_bigint()?.is_zero() {
+                    Value::NaN
+                } else {
+                    fixed_op(lhs, rhs, Div::div)?
+                }
+            }
+            (lhs @ Value::Fixed(_, _), Value::Float(rhs)) => Value::Float(lhs.to_float()? / rhs),
+            (Value::Float(lhs), rhs @ Value::Fixed(_, _)) => Value::Float(lhs / rhs.to_float()?),
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+            ) => {
+                let rhs = rhs.to_rational()?;
+                if rhs.is_zero() {
+                    Value::NaN
+                } else {
+                    Value::from(lhs.to_rational()? / rhs)
+                }
+            }
             (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs / rhs),
             (Value::Int(lhs), Value::Float(rhs)) => Value::Float(lhs as f64 / rhs),
             (Value::Float(lhs), Value::Int(rhs)) => Value::Float(lhs / rhs as f64),
+            (Value::BigInt(lhs), Value::Float(rhs)) => Value::Float(big_to_f64(&lhs) / rhs),
+            (Value::Float(lhs), Value::BigInt(rhs)) => Value::Float(lhs / big_to_f64(&rhs)),
+            (lhs @ Value::Rational(_), Value::Float(rhs)) => Value::Float(lhs.to_float()? / rhs),
+            (Value::Float(lhs), rhs @ Value::Rational(_)) => Value::Float(lhs / rhs.to_float()?),
+            (lhs @ Value::Decimal(_), rhs) | (lhs, rhs @ Value::Decimal(_))
+                if rhs.is_real() && lhs.is_real() =>
+            {
+                decimal_op(lhs, rhs, Decimal::checked_div)?
+            }
+            (lhs @ Value::Complex(_), rhs) | (lhs, rhs @ Value::Complex(_))
+                if lhs.is_numeric() && rhs.is_numeric() =>
+            {
+                complex_op(lhs, rhs, Div::div)?
+            }
+            (Value::Quantity(lhs, lhs_unit), Value::Quantity(rhs, rhs_unit)) => {
+                Value::quantity(lhs / rhs, lhs_unit.div(&rhs_unit)?)
+            }
+            (Value::Quantity(value, unit), rhs) if rhs.is_real() => {
+                Value::Quantity(value / rhs.to_float()?, unit)
+            }
+            (lhs, Value::Quantity(value, unit)) if lhs.is_real() => {
+                Value::quantity(lhs.to_float()? / value, unit.powi(-1)?)
+            }
+            (Value::Duration(lhs), Value::Duration(rhs)) => {
+                Value::Float(datetime::secs(lhs) / datetime::secs(rhs))
+            }
+            (Value::Duration(duration), rhs) if rhs.is_real() => {
+                scale(duration, 1.0 / rhs.to_float()?)
+            }
             (Value::NaN, _) | (_, Value::NaN) => Value::NaN,
             _ => {
                 return Err(InterpretError::InvalidArgument(
@@ -737,7 +2678,17 @@ impl Neg for Value {
 
     fn neg(self) -> Self::Output {
         match &self {
-            Value::Int(int) => Ok(Value::Int(-*int)),
+            Value::Int(int) => Ok(int
+                .checked_neg()
+                .map(Value::Int)
+                .unwrap_or_else(|| Value::from(-BigInt::from(*int)))),
+            Value::BigInt(int) => Ok(Value::from(-int)),
+            Value::Fixed(int, ty) => Ok(Value::Fixed(-int, *ty)),
+            Value::Rational(ratio) => Ok(Value::from(-ratio)),
+            Value::Complex(complex) => Ok(Value::Complex(-*complex)),
+            Value::Decimal(decimal) => Ok(Value::Decimal(-*decimal)),
+            Value::Quantity(value, unit) => Ok(Value::Quantity(-*value, unit.clone())),
+            Value::Duration(duration) => Ok(Value::Duration(-*duration)),
             Value::Float(float) => Ok(Value::Float(-*float)),
             _ => Err(InterpretError::RuntimeError(
                 "Cannot negate non numeric types".to_string(),
@@ -751,10 +2702,53 @@ impl Rem for Value {
 
     fn rem(self, rhs: Self) -> Self::Output {
         let res = match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Value::Float(lhs as f64 % rhs as f64),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                if rhs.to_bigint()?.is_zero() {
+                    Value::NaN
+                } else {
+                    fixed_op(lhs, rhs, Rem::rem)?
+                }
+            }
+            (lhs @ Value::Fixed(_, _), Value::Float(rhs)) => Value::Float(lhs.to_float()? % rhs),
+            (Value::Float(lhs), rhs @ Value::Fixed(_, _)) => Value::Float(lhs % rhs.to_float()?),
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_)),
+            ) => {
+                let rhs = rhs.to_bigint()?;
+                if rhs.is_zero() {
+                    Value::NaN
+                } else {
+                    Value::from(lhs.to_bigint()? % rhs)
+                }
+            }
             (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs % rhs),
             (Value::Int(lhs), Value::Float(rhs)) => Value::Float(lhs as f64 % rhs),
             (Value::Float(lhs), Value::Int(rhs)) => Value::Float(lhs % rhs as f64),
+            (Value::BigInt(lhs), Value::Float(rhs)) => Value::Float(big_to_f64(&lhs) % rhs),
+            (Value::Float(lhs), Value::BigInt(rhs)) => Value::Float(lhs % big_to_f64(&rhs)),
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+            ) => {
+                let rhs = rhs.to_rational()?;
+                if rhs.is_zero() {
+                    Value::NaN
+                } else {
+                    Value::from(lhs.to_rational()? % rhs)
+                }
+            }
+            (lhs @ Value::Rational(_), Value::Float(rhs)) => Value::Float(lhs.to_float()? % rhs),
+            (Value::Float(lhs), rhs @ Value::Rational(_)) => Value::Float(lhs % rhs.to_float()?),
+            (lhs @ Value::Decimal(_), rhs) | (lhs, rhs @ Value::Decimal(_))
+                if rhs.is_real() && lhs.is_real() =>
+            {
+                decimal_op(lhs, rhs, Decimal::checked_rem)?
+            }
             _ => {
                 return Err(InterpretError::InvalidArgument(
                     "Cannot calculate rem of non numeric types".to_string(),
@@ -772,6 +2766,17 @@ impl BitAnd for Value {
     fn bitand(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Int(lhs & rhs)),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                fixed_op(lhs, rhs, BitAnd::bitand)
+            }
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_)),
+            ) => Ok(Value::from(lhs.to_bigint()? & rhs.to_bigint()?)),
             _ => Err(InterpretError::InvalidArgument(
                 "& cannot be applied to this data type".to_string(),
             )),
@@ -785,6 +2790,17 @@ impl BitXor for Value {
     fn bitxor(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Int(lhs ^ rhs)),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                fixed_op(lhs, rhs, BitXor::bitxor)
+            }
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_)),
+            ) => Ok(Value::from(lhs.to_bigint()? ^ rhs.to_bigint()?)),
             _ => Err(InterpretError::InvalidArgument(
                 "^ cannot be applied to this data type".to_string(),
             )),
@@ -798,6 +2814,17 @@ impl BitOr for Value {
     fn bitor(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Int(lhs | rhs)),
+            (
+                lhs @ Value::Fixed(_, _),
+                rhs @ (Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_)),
+            )
+            | (lhs @ (Value::Int(_) | Value::BigInt(_)), rhs @ Value::Fixed(_, _)) => {
+                fixed_op(lhs, rhs, BitOr::bitor)
+            }
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_)),
+            ) => Ok(Value::from(lhs.to_bigint()? | rhs.to_bigint()?)),
             _ => Err(InterpretError::InvalidArgument(
                 "| cannot be applied to this data type".to_string(),
             )),
@@ -810,7 +2837,20 @@ impl Shl for Value {
 
     fn shl(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Int(lhs << rhs)),
+            (
+                Value::Fixed(int, ty),
+                rhs @ (Value::Int(_) | Value::BigInt(_) | Value::Fixed(_, _)),
+            ) => fixed_shift(int, ty, &rhs, Shl::shl),
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_)),
+            ) => {
+                let (lhs, amount) = (lhs.to_bigint()?, shift_amount(&rhs)?);
+                if !lhs.is_zero() && lhs.bits() + u64::from(amount) > MAX_BITS {
+                    return Err(too_many_bits());
+                }
+                Ok(Value::from(lhs << amount))
+            }
             _ => Err(InterpretError::InvalidArgument(
                 "<< cannot be applied to this data type".to_string(),
             )),
@@ -823,7 +2863,14 @@ impl Shr for Value {
 
     fn shr(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Int(lhs >> rhs)),
+            (
+                Value::Fixed(int, ty),
+                rhs @ (Value::Int(_) | Value::BigInt(_) | Value::Fixed(_, _)),
+            ) => fixed_shift(int, ty, &rhs, Shr::shr),
+            (
+                lhs @ (Value::Int(_) | Value::BigInt(_)),
+                rhs @ (Value::Int(_) | Value::BigInt(_)),
+            ) => Ok(Value::from(lhs.to_bigint()? >> shift_amount(&rhs)?)),
             _ => Err(InterpretError::InvalidArgument(
                 ">> cannot be applied to this data type".to_string(),
             )),
@@ -837,6 +2884,8 @@ impl Not for Value {
     fn not(self) -> Self::Output {
         match self {
             Value::Int(int) => Ok(Value::Int(!int)),
+            Value::BigInt(int) => Ok(Value::from(!int)),
+            Value::Fixed(int, ty) => Ok(Value::Fixed(ty.not(&int), ty)),
             Value::Bool(bool) => Ok(Value::Bool(!bool)),
             _ => Err(InterpretError::InvalidArgument(
                 "! cannot be applied to this data type".to_string(),
@@ -852,162 +2901,1180 @@ impl PartialOrd for Value {
             (Value::Float(lhs), Value::Float(rhs)) => lhs > rhs,
             (Value::Int(lhs), Value::Float(rhs)) => *lhs as f64 > *rhs,
             (Value::Float(lhs), Value::Int(rhs)) => *lhs > *rhs as f64,
-            _ => false,
-        }
-    }
-
-    fn lt(&self, other: &Self) -> bool {
-        !self.gt(other) && !self.eq(other)
+            (Value::BigInt(lhs), Value::BigInt(rhs)) => lhs > rhs,
+            (Value::BigInt(lhs), Value::Int(rhs)) => *lhs > BigInt::from(*rhs),
+            (Value::Int(lhs), Value::BigInt(rhs)) => BigInt::from(*lhs) > *rhs,
+            (Value::BigInt(lhs), Value::Float(rhs)) => big_to_f64(lhs) > *rhs,
+            (Value::Float(lhs), Value::BigInt(rhs)) => *lhs > big_to_f64(rhs),
+            (Value::Rational(lhs), Value::Rational(rhs)) => lhs > rhs,
+            (Value::Rational(_), Value::Int(_) | Value::BigInt(_))
+            | (Value::Int(_) | Value::BigInt(_), Value::Rational(_)) => {
+                self.to_rational().ok() > other.to_rational().ok()
+            }
+            (Value::Rational(_), Value::Float(_)) | (Value::Float(_), Value::Rational(_)) => {
+                self.to_float().ok() > other.to_float().ok()
+            }
+            (Value::Decimal(_), rhs) | (rhs, Value::Decimal(_)) if rhs.is_real() => {
+                self.to_decimal().ok() > other.to_decimal().ok()
+            }
+            (Value::Quantity(lhs, lhs_unit), Value::Quantity(rhs, rhs_unit)) => {
+                convert(*rhs, rhs_unit, lhs_unit).is_ok_and(|rhs| *lhs > rhs)
+            }
+            (Value::Fixed(_, _), Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_))
+            | (Value::Int(_) | Value::BigInt(_), Value::Fixed(_, _)) => {
+                self.to_bigint().ok() > other.to_bigint().ok()
+            }
+            (Value::Fixed(_, _), Value::Float(_)) | (Value::Float(_), Value::Fixed(_, _)) => {
+                self.to_float().ok() > other.to_float().ok()
+            }
+            (Value::Date(lhs), Value::Date(rhs)) => lhs > rhs,
+            (Value::Date(_) | Value::DateTime(_), Value::Date(_) | Value::DateTime(_)) => {
+                self.to_datetime().ok() > other.to_datetime().ok()
+            }
+            (Value::Duration(lhs), Value::Duration(rhs)) => lhs > rhs,
+            // Field by field in order of name, like words in a dictionary
+            (Value::Record(lhs), Value::Record(rhs)) => {
+                for ((lhs_name, lhs), (rhs_name, rhs)) in lhs.iter().zip(rhs) {
+                    if lhs_name != rhs_name {
+                        return lhs_name > rhs_name;
+                    }
+                    if lhs != rhs {
+                        return lhs > rhs;
+                    }
+                }
+                lhs.len() > rhs.len()
+            }
+            _ => false,
+        }
+    }
+
+    fn lt(&self, other: &Self) -> bool {
+        !self.gt(other) && !self.eq(other)
     }
 
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
-}
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(lhs), Value::Int(rhs)) => lhs == rhs,
+            (Value::Float(lhs), Value::Float(rhs)) => lhs == rhs,
+            (Value::Int(lhs), Value::Float(rhs)) => *lhs as f64 == *rhs,
+            (Value::Float(lhs), Value::Int(rhs)) => *lhs == *rhs as f64,
+            (Value::BigInt(lhs), Value::BigInt(rhs)) => lhs == rhs,
+            (Value::BigInt(lhs), Value::Int(rhs)) => *lhs == BigInt::from(*rhs),
+            (Value::Int(lhs), Value::BigInt(rhs)) => BigInt::from(*lhs) == *rhs,
+            (Value::BigInt(lhs), Value::Float(rhs)) => big_to_f64(lhs) == *rhs,
+            (Value::Float(lhs), Value::BigInt(rhs)) => *lhs == big_to_f64(rhs),
+            (Value::Rational(lhs), Value::Rational(rhs)) => lhs == rhs,
+            (Value::Complex(lhs), Value::Complex(rhs)) => lhs == rhs,
+            (Value::Quantity(lhs, lhs_unit), Value::Quantity(rhs, rhs_unit)) => {
+                convert(*rhs, rhs_unit, lhs_unit).is_ok_and(|rhs| *lhs == rhs)
+            }
+            (Value::Fixed(_, _), Value::Fixed(_, _) | Value::Int(_) | Value::BigInt(_))
+            | (Value::Int(_) | Value::BigInt(_), Value::Fixed(_, _)) => {
+                self.to_bigint().ok() == other.to_bigint().ok()
+            }
+            (Value::Fixed(_, _), Value::Float(_)) | (Value::Float(_), Value::Fixed(_, _)) => {
+                self.to_float().ok() == other.to_float().ok()
+            }
+            (Value::Date(lhs), Value::Date(rhs)) => lhs == rhs,
+            (Value::Date(_) | Value::DateTime(_), Value::Date(_) | Value::DateTime(_)) => {
+                self.to_datetime().ok() == other.to_datetime().ok()
+            }
+            (Value::Duration(lhs), Value::Duration(rhs)) => lhs == rhs,
+            (Value::Decimal(_), rhs) | (rhs, Value::Decimal(_)) if rhs.is_real() => {
+                self.to_decimal().ok() == other.to_decimal().ok()
+            }
+            (Value::Rational(_), Value::Float(_)) | (Value::Float(_), Value::Rational(_)) => {
+                self.to_float().ok() == other.to_float().ok()
+            }
+            (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+            (Value::Fn(fl), Value::Fn(fr)) => fl == fr,
+            (Value::Fn(_), _) => false,
+            (Value::Float(_), _) => false,
+            (Value::Int(_), _) => false,
+            (Value::BigInt(_), _) => false,
+            (Value::Fixed(_, _), _) => false,
+            (Value::Rational(_), _) => false,
+            (Value::Complex(_), _) => false,
+            (Value::Decimal(_), _) => false,
+            (Value::Quantity(_, _), _) => false,
+            (Value::Date(_), _) => false,
+            (Value::DateTime(_), _) => false,
+            (Value::Duration(_), _) => false,
+            (Value::String(_), _) => false,
+            (Value::Bool(bl), Value::Bool(br)) => bl == br,
+            (Value::Bool(_), _) => false,
+            (Value::List(ll), Value::List(lr)) => ll == lr,
+            (Value::List(_), _) => false,
+            (Value::Tuple(tl), Value::Tuple(tr)) => tl == tr,
+            (Value::Tuple(_), _) => false,
+            (Value::Record(rl), Value::Record(rr)) => rl == rr,
+            (Value::Record(_), _) => false,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Unit, _) => false,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Nil, _) => false,
+            (Value::NaN, Value::NaN) => true,
+            (Value::NaN, _) => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self.gt(other) {
+            std::cmp::Ordering::Greater
+        } else if self.lt(other) {
+            std::cmp::Ordering::Less
+        } else if self.eq(other) {
+            std::cmp::Ordering::Equal
+        } else {
+            panic!("Not possible");
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "|{}| {}", join_patterns(&self.parameters), self.body)
+    }
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Stmt::Assign(name, expr) => format!("let {} = {}", name, expr),
+                Stmt::Unpack(pattern, expr) => format!("let {} = {}", pattern.format(), expr),
+                Stmt::Expr(expr) => return inner_write(expr, f),
+                Stmt::Undef(vars) => format!("undef({})", vars.join(", ")),
+                Stmt::Config(name, expr) => format!("config {} = {}", name, expr),
+                Stmt::Seq(_) | Stmt::Fn(_) | Stmt::Simplify(_) => self.format(),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse::Parser, Tokenizer};
+
+    // Every backend should give the same results
+    fn backends() -> [Interpreter; 2] {
+        with_backends(Interpreter::new())
+    }
+
+    fn with_backends(tree: Interpreter) -> [Interpreter; 2] {
+        let mut vm = tree.clone();
+        vm.configure("backend", &Value::String("vm".to_string()))
+            .unwrap();
+        [tree, vm]
+    }
+
+    fn check(expr: Expr, expected: Result<Value, InterpretError>) {
+        for interpreter in backends() {
+            let res = interpreter.interpret_expr(&expr);
+            assert_eq!(res, expected, "{:?}", interpreter.settings().backend);
+        }
+    }
+
+    fn check_with_vars(
+        expr: Expr,
+        expected: Result<Value, InterpretError>,
+        env: HashMap<String, Value>,
+    ) {
+        for interpreter in with_backends(Interpreter::with_env(env)) {
+            let res = interpreter.interpret_expr(&expr);
+            assert_eq!(res, expected, "{:?}", interpreter.settings().backend);
+        }
+    }
+
+    #[test]
+    fn simple_add() {
+        check(
+            Expr::Binary(
+                Box::new(Expr::Float(1.1)),
+                Token::Plus,
+                Box::new(Expr::Float(5.0)),
+            ),
+            Ok(Value::Float(6.1)),
+        );
+    }
+
+    #[test]
+    fn simple_variable() {
+        check_with_vars(
+            Expr::Binary(
+                Box::new(Expr::Float(12.0)),
+                Token::Mult,
+                Box::new(Expr::Var("foo".to_string())),
+            ),
+            Ok(Value::Float(144.0)),
+            HashMap::from_iter([("foo".to_string(), Value::Float(12.0))]),
+        );
+    }
+
+    #[test]
+    fn function_with_closure() {
+        check_with_vars(
+            Expr::Call(
+                Box::new(Expr::Var("foo".to_string())),
+                vec![Expr::Var("bar".to_string())],
+            ),
+            Ok(Value::Int(12)),
+            HashMap::from_iter([
+                (
+                    "foo".to_string(),
+                    Value::Fn(Function::new(
+                        vec![Pattern::Bind("x".to_string())],
+                        Expr::Binary(
+                            Box::new(Expr::Binary(
+                                Box::new(Expr::Var("x".to_string())),
+                                Token::Mult,
+                                Box::new(Expr::Int(2)),
+                            )),
+                            Token::Plus,
+                            Box::new(Expr::Var("bar".to_string())),
+                        ),
+                        HashMap::from_iter([("bar".to_string(), Value::Int(2))]),
+                    )),
+                ),
+                ("bar".to_string(), Value::Int(5)), // The function uses the closure value
+            ]),
+        );
+    }
+
+    #[test]
+    fn unknown_variables() {
+        check_with_vars(
+            Expr::Var("fooo".to_string()),
+            Err(InterpretError::UnknownVariable(
+                "fooo".to_string(),
+                Some("foo".to_string()),
+            )),
+            HashMap::from_iter([("foo".to_string(), Value::Int(1))]),
+        );
+        check(
+            Expr::Var("sqr".to_string()),
+            Err(InterpretError::UnknownVariable(
+                "sqr".to_string(),
+                Some("sqrt".to_string()),
+            )),
+        );
+        check(
+            Expr::Var("x".to_string()),
+            Err(InterpretError::UnknownVariable("x".to_string(), None)),
+        );
+    }
+
+    #[test]
+    fn nan_for_nan() {
+        check(Expr::Func(Func::Ln, vec![Expr::Int(0)]), Ok(Value::NaN));
+    }
+
+    #[test]
+    fn complex_function_names_can_be_bound() {
+        for mut interpreter in backends() {
+            for (input, expected) in [
+                ("(|arg| arg + 1)(2)", Value::Int(3)),
+                ("(|im| im * 2)(3)", Value::Int(6)),
+                ("(|re, conj| re - conj)(5, 1)", Value::Int(4)),
+                ("let re = 5; re * 2", Value::Int(10)),
+                ("let arg = 1 in arg + re(2)", Value::Float(3.0)),
+            ] {
+                assert_eq!(run(&mut interpreter, input), Ok(expected), "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn complex_results() {
+        check(
+            Expr::Func(Func::Sqrt, vec![Expr::Int(-1)]),
+            Ok(Value::Complex(Complex64::new(0.0, 1.0))),
+        );
+        check(
+            Expr::Func(Func::Quadr, vec![Expr::Int(1), Expr::Int(0), Expr::Int(1)]),
+            Ok(Value::List(vec![
+                Value::Complex(Complex64::new(0.0, 1.0)),
+                Value::Complex(Complex64::new(0.0, -1.0)),
+            ])),
+        );
+        check(
+            Expr::Binary(
+                Box::new(Expr::Imaginary(2.0)),
+                Token::Mult,
+                Box::new(Expr::Imaginary(3.0)),
+            ),
+            Ok(Value::Float(-6.0)),
+        );
+        check(
+            Expr::Func(
+                Func::Abs,
+                vec![Expr::Binary(
+                    Box::new(Expr::Int(3)),
+                    Token::Plus,
+                    Box::new(Expr::Imaginary(4.0)),
+                )],
+            ),
+            Ok(Value::Float(5.0)),
+        );
+        check(
+            Expr::Func(
+                Func::Conj,
+                vec![Expr::Binary(
+                    Box::new(Expr::Int(1)),
+                    Token::Plus,
+                    Box::new(Expr::Imaginary(1.0)),
+                )],
+            ),
+            Ok(Value::Complex(Complex64::new(1.0, -1.0))),
+        );
+        assert_eq!(
+            Value::Complex(Complex64::new(1.5, -2.0)).to_string(),
+            "1.5-2i"
+        );
+    }
+
+    #[test]
+    fn decimal_arithmetic() {
+        check(
+            Expr::Binary(
+                Box::new(Expr::Decimal(Decimal::new(1, 1))),
+                Token::Plus,
+                Box::new(Expr::Decimal(Decimal::new(2, 1))),
+            ),
+            Ok(Value::Decimal(Decimal::new(3, 1))),
+        );
+        check(
+            Expr::Binary(
+                Box::new(Expr::Decimal(Decimal::new(15, 1))),
+                Token::Mult,
+                Box::new(Expr::Int(2)),
+            ),
+            Ok(Value::Decimal(Decimal::new(3, 0))),
+        );
+        check(
+            Expr::Binary(
+                Box::new(Expr::Decimal(Decimal::new(1, 0))),
+                Token::Div,
+                Box::new(Expr::Int(0)),
+            ),
+            Ok(Value::NaN),
+        );
+        check(
+            Expr::Func(Func::Round, vec![Expr::Decimal(Decimal::new(25, 1))]),
+            Ok(Value::Decimal(Decimal::new(2, 0))),
+        );
+        let pow = |base, exp| {
+            Expr::Binary(
+                Box::new(Expr::Decimal(base)),
+                Token::Pow,
+                Box::new(Expr::Int(exp)),
+            )
+        };
+        check(
+            pow(Decimal::new(15, 1), 10),
+            Ok(Value::Decimal(Decimal::new(576650390625, 10))),
+        );
+        // Large exponents are quick rather than one multiplication each
+        check(
+            pow(Decimal::new(-10, 1), 100_000_000_001),
+            Ok(Value::Decimal(Decimal::NEGATIVE_ONE)),
+        );
+        check(
+            pow(Decimal::new(11, 1), 100_000_000_000),
+            Err(InterpretError::RuntimeError("Decimal overflow".to_string())),
+        );
+        check(
+            pow(Decimal::new(5, 1), -100_000_000_000),
+            Err(InterpretError::RuntimeError("Decimal overflow".to_string())),
+        );
+        check(pow(Decimal::ZERO, -3), Ok(Value::NaN));
+        assert_eq!(Value::Decimal(Decimal::new(3, 1)), Value::Float(0.3));
+    }
+
+    #[test]
+    fn unit_arithmetic() {
+        let quantity = |value, unit: &str| {
+            Expr::Quantity(Box::new(Expr::Int(value)), vec![(unit.to_string(), 1)])
+        };
+        let speed = Unit::builtin("km")
+            .unwrap()
+            .div(&Unit::builtin("h").unwrap())
+            .unwrap();
+        check(
+            Expr::Binary(
+                Box::new(quantity(5, "km")),
+                Token::Div,
+                Box::new(quantity(2, "h")),
+            ),
+            Ok(Value::Quantity(2.5, speed)),
+        );
+        check(
+            Expr::Binary(
+                Box::new(quantity(1, "m")),
+                Token::Plus,
+                Box::new(quantity(20, "cm")),
+            ),
+            Ok(Value::Quantity(1.2, Unit::builtin("m").unwrap())),
+        );
+        check(
+            Expr::Binary(
+                Box::new(quantity(3, "m")),
+                Token::Plus,
+                Box::new(quantity(2, "s")),
+            ),
+            Err(InterpretError::RuntimeError(
+                "Incompatible units, cannot convert s to m".to_string(),
+            )),
+        );
+        check(
+            Expr::Binary(
+                Box::new(quantity(2, "km")),
+                Token::Div,
+                Box::new(quantity(500, "m")),
+            ),
+            Ok(Value::Float(4.0)),
+        );
+        check(
+            Expr::Convert(
+                Box::new(Expr::Quantity(
+                    Box::new(Expr::Int(90)),
+                    vec![("km".to_string(), 1), ("h".to_string(), -1)],
+                )),
+                vec![("m".to_string(), 1), ("s".to_string(), -1)],
+            ),
+            Ok(Value::Quantity(
+                25.0,
+                Unit::builtin("m")
+                    .unwrap()
+                    .div(&Unit::builtin("s").unwrap())
+                    .unwrap(),
+            )),
+        );
+        check(
+            quantity(1, "parsec"),
+            Err(InterpretError::RuntimeError(
+                "Unknown unit parsec".to_string(),
+            )),
+        );
+        check(
+            Expr::Binary(
+                Box::new(quantity(5, "m")),
+                Token::Div,
+                Box::new(Expr::Int(0)),
+            ),
+            Ok(Value::NaN),
+        );
+    }
+
+    #[test]
+    fn user_units() {
+        for mut interpreter in backends() {
+            interpreter
+                .interpret(Stmt::Assign(
+                    "furlong".to_string(),
+                    Expr::Quantity(Box::new(Expr::Float(201.168)), vec![("m".to_string(), 1)]),
+                ))
+                .unwrap();
+            let res = interpreter.interpret_expr(&Expr::Convert(
+                Box::new(Expr::Quantity(
+                    Box::new(Expr::Int(1)),
+                    vec![("mi".to_string(), 1)],
+                )),
+                vec![("furlong".to_string(), 1)],
+            ));
+            assert_eq!(res.unwrap().to_string(), "8 furlong");
+        }
+    }
+
+    #[test]
+    fn quantity_builtins() {
+        for mut interpreter in backends() {
+            for (input, expected) in [
+                ("sum([1 m, 2 km])", "2001 m"),
+                ("sqrt(4 m^2)", "2 m"),
+                ("cbrt(8 m^3/s^3)", "2 m/s"),
+                ("max([1 m, 2 km, 30 cm])", "2 km"),
+                ("min([1 m, 2 km, 30 cm])", "30 cm"),
+            ] {
+                assert_eq!(
+                    run(&mut interpreter, input).map(|value| value.to_string()),
+                    Ok(expected.to_string()),
+                    "{}",
+                    input
+                );
+            }
+            let err = |msg: &str| Err(InterpretError::RuntimeError(msg.to_string()));
+            assert_eq!(
+                run(&mut interpreter, "sum([1 m, 2 s])"),
+                err("Incompatible units, cannot convert s to m")
+            );
+            assert_eq!(
+                run(&mut interpreter, "sqrt(4 m*km)"),
+                err("Cannot take sqrt of m*km, the powers of its units must divide by 2")
+            );
+            assert_eq!(
+                run(&mut interpreter, "max([1 m, 2 s])"),
+                err("Incompatible units, cannot compare s to m")
+            );
+            assert_eq!(
+                run(&mut interpreter, "min([1 m, 2])"),
+                err("Cannot compare 2 to a quantity in m")
+            );
+        }
+    }
+
+    #[test]
+    fn unit_powers_too_large() {
+        for mut interpreter in backends() {
+            let too_large = Err(InterpretError::RuntimeError(
+                "Unit power too large".to_string(),
+            ));
+            for input in [
+                "(1 m^2) ** 100",
+                "(1 m) ** 100 * (1 m) ** 100",
+                "(2 m) ** 200",
+                "1 / (1 m) ** 100 / (1 m) ** 100",
+                "cube((1 m) ** 50)",
+            ] {
+                assert_eq!(run(&mut interpreter, input), too_large, "{}", input);
+            }
+            assert_eq!(
+                run(&mut interpreter, "(1 m) ** 127").unwrap().to_string(),
+                "1 m^127"
+            );
+        }
+    }
+
+    #[test]
+    fn blocks_and_sequences() {
+        for mut interpreter in backends() {
+            let assign = |name: &str, int| Stmt::Assign(name.to_string(), Expr::Int(int));
+            let var = |name: &str| Expr::Var(name.to_string());
+            let block = Expr::Block(vec![
+                assign("a", 2),
+                Stmt::Expr(Expr::Binary(
+                    Box::new(var("a")),
+                    Token::Mult,
+                    Box::new(var("b")),
+                )),
+            ]);
+            let res = interpreter.interpret(Stmt::Seq(vec![
+                assign("b", 3),
+                Stmt::Assign("c".to_string(), block),
+            ]));
+            assert_eq!(res, Ok(Value::Int(6)));
+            // Only the definitions outside of the block are kept
+            assert_eq!(interpreter.interpret_expr(&var("c")), Ok(Value::Int(6)));
+            assert!(interpreter.interpret_expr(&var("a")).is_err());
+        }
+    }
+
+    #[test]
+    fn local_bindings() {
+        for mut interpreter in backends() {
+            let var = |name: &str| Expr::Var(name.to_string());
+            // let a = 2, b = a + 1 in a * b
+            let expr = Expr::Let(
+                vec![
+                    ("a".to_string(), Expr::Int(2)),
+                    (
+                        "b".to_string(),
+                        Expr::Binary(Box::new(var("a")), Token::Plus, Box::new(Expr::Int(1))),
+                    ),
+                ],
+                Box::new(Expr::Binary(
+                    Box::new(var("a")),
+                    Token::Mult,
+                    Box::new(var("b")),
+                )),
+            );
+            let res = interpreter.interpret(Stmt::Assign("c".to_string(), expr));
+            assert_eq!(res, Ok(Value::Int(6)));
+            assert!(interpreter.interpret_expr(&var("a")).is_err());
+            assert!(!interpreter.env().contains_key("b"));
+        }
+    }
+
+    fn run(interpreter: &mut Interpreter, input: &str) -> Result<Value, InterpretError> {
+        let stmt = Parser::new(Tokenizer::new(input)).unwrap().parse().unwrap();
+        interpreter.interpret(stmt)
+    }
+
+    #[test]
+    fn recursive_functions() {
+        for mut interpreter in backends() {
+            let res = run(
+                &mut interpreter,
+                "fn fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2); fib(15)",
+            );
+            assert_eq!(res, Ok(Value::Int(610)));
+            let mutual = "fn is_even(n) = if n == 0 then true else is_odd(n - 1), \
+                is_odd(n) = if n == 0 then false else is_even(n - 1)";
+            run(&mut interpreter, mutual).unwrap();
+            assert_eq!(run(&mut interpreter, "is_odd(7)"), Ok(Value::Bool(true)));
+            // Tail calls don't count towards the depth
+            assert_eq!(
+                run(&mut interpreter, "is_even(10001)"),
+                Ok(Value::Bool(false))
+            );
+            assert_eq!(
+                interpreter.env().get("is_odd").unwrap().to_input("is_odd"),
+                "fn is_even(n) = if n==0 then true else is_odd(n-1), is_odd(n) = if n==0 then false else is_even(n-1)"
+            );
+        }
+    }
+
+    #[test]
+    fn records() {
+        for mut interpreter in backends() {
+            run(&mut interpreter, "let r = {name: \"x\", rate: 0.2}").unwrap();
+            assert_eq!(run(&mut interpreter, "r.rate"), Ok(Value::Float(0.2)));
+            assert_eq!(
+                run(&mut interpreter, "{..r, rate: 0.3, n: 2}.rate + r.rate"),
+                Ok(Value::Float(0.5))
+            );
+            assert_eq!(
+                run(&mut interpreter, "keys(r)"),
+                Ok(Value::List(vec![
+                    Value::String("name".to_string()),
+                    Value::String("rate".to_string())
+                ]))
+            );
+            assert_eq!(
+                run(&mut interpreter, "entries({b: 1})"),
+                Ok(Value::List(vec![Value::Tuple(vec![
+                    Value::String("b".to_string()),
+                    Value::Int(1)
+                ])]))
+            );
+            assert_eq!(
+                run(&mut interpreter, "{rate: 0.2, name: \"x\"} == r"),
+                Ok(Value::Bool(true))
+            );
+            assert_eq!(
+                run(&mut interpreter, "r.rat"),
+                Err(InterpretError::RuntimeError(
+                    "Record has no field rat, did you mean `rate`?".to_string()
+                ))
+            );
+            assert!(run(&mut interpreter, "values(r.name)").is_err());
+        }
+    }
+
+    #[test]
+    fn indexing_and_slicing() {
+        let list = |ints: &[i64]| Value::List(ints.iter().map(|int| Value::Int(*int)).collect());
+        for mut interpreter in backends() {
+            run(&mut interpreter, "let xs = [1, 2, 3, 4, 5]").unwrap();
+            for (input, expected) in [
+                ("xs[0]", Value::Int(1)),
+                ("xs[-1]", Value::Int(5)),
+                ("elem(xs, -2)", Value::Int(4)),
+                ("xs[1:3]", list(&[2, 3])),
+                ("xs[::2]", list(&[1, 3, 5])),
+                ("xs[::-2]", list(&[5, 3, 1])),
+                ("xs[3:0:-1]", list(&[4, 3, 2])),
+                ("xs[-2:]", list(&[4, 5])),
+                ("xs[-10:10]", list(&[1, 2, 3, 4, 5])),
+                ("xs[4:1]", list(&[])),
+                ("set(xs, -1, 0)", list(&[1, 2, 3, 4, 0])),
+                ("{1, \"a\"}[-1]", Value::String("a".to_string())),
+                ("\"héllo\"[1:-1]", Value::String("éll".to_string())),
+            ] {
+                assert_eq!(run(&mut interpreter, input), Ok(expected), "{}", input);
+            }
+            for input in [
+                "xs[5]",
+                "xs[-6]",
+                "[][0]",
+                "elem([], 0)",
+                "xs[::0]",
+                "set(xs, 5, 0)",
+            ] {
+                assert!(run(&mut interpreter, input).is_err(), "{}", input);
+            }
+        }
+    }
 
-impl PartialEq for Value {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Value::Int(lhs), Value::Int(rhs)) => lhs == rhs,
-            (Value::Float(lhs), Value::Float(rhs)) => lhs == rhs,
-            (Value::Int(lhs), Value::Float(rhs)) => *lhs as f64 == *rhs,
-            (Value::Float(lhs), Value::Int(rhs)) => *lhs == *rhs as f64,
-            (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
-            (Value::Fn(fl), Value::Fn(fr)) => fl == fr,
-            (Value::Fn(_), _) => false,
-            (Value::Float(_), _) => false,
-            (Value::Int(_), _) => false,
-            (Value::String(_), _) => false,
-            (Value::Bool(bl), Value::Bool(br)) => bl == br,
-            (Value::Bool(_), _) => false,
-            (Value::List(ll), Value::List(lr)) => ll == lr,
-            (Value::List(_), _) => false,
-            (Value::Tuple(tl), Value::Tuple(tr)) => tl == tr,
-            (Value::Tuple(_), _) => false,
-            (Value::Unit, Value::Unit) => true,
-            (Value::Unit, _) => false,
-            (Value::Nil, Value::Nil) => true,
-            (Value::Nil, _) => false,
-            (Value::NaN, Value::NaN) => true,
-            (Value::NaN, _) => false,
+    #[test]
+    fn string_functions() {
+        let string = |string: &str| Value::String(string.to_string());
+        let strings = |strings: &[&str]| Value::List(strings.iter().map(|s| string(s)).collect());
+        for mut interpreter in backends() {
+            for (input, expected) in [
+                ("len(\"héllo\")", Value::Int(5)),
+                ("len([1, 2])", Value::Int(2)),
+                ("upper(\"abc\") + lower(\"DEF\")", string("ABCdef")),
+                ("trim(\"\\t x \\n\")", string("x")),
+                ("split(\"a,b,,c\", \",\")", strings(&["a", "b", "", "c"])),
+                ("join([\"a\", 1, 2.5], \", \")", string("a, 1, 2.5")),
+                ("replace(\"a-b-c\", \"-\", \"+\")", string("a+b+c")),
+                ("contains(\"hello\", \"ell\")", Value::Bool(true)),
+                ("contains([1, 2], 3)", Value::Bool(false)),
+                ("contains({a: 1}, \"a\")", Value::Bool(true)),
+                ("starts_with(\"hello\", \"lo\")", Value::Bool(false)),
+                ("substr(\"héllo\", 1, 3)", string("éll")),
+                ("substr(\"hello\", -2, 10)", string("lo")),
+                ("chars(\"ab\")", strings(&["a", "b"])),
+                ("to_str(1 / 4)", string("1/4")),
+                ("parse_num(\" -0x10 \") + 1", Value::Int(-15)),
+                ("format(\"{:.2}\", 3.14159)", string("3.14")),
+                ("format(\"{}: {:>4}\", {\"x\", 42})", string("x:   42")),
+                ("\"tab\\there\"", string("tab\there")),
+            ] {
+                assert_eq!(run(&mut interpreter, input), Ok(expected), "{}", input);
+            }
+            for input in [
+                "len(1)",
+                "split(\"abc\", \"\")",
+                "substr(\"abc\", 0, -1)",
+                "parse_num(\"12abc\")",
+                "format(\"{} {}\", 1)",
+                "upper(1)",
+            ] {
+                assert!(run(&mut interpreter, input).is_err(), "{}", input);
+            }
         }
     }
-}
 
-impl Eq for Value {}
+    #[test]
+    fn interpolated_strings() {
+        let string = |string: &str| Value::String(string.to_string());
+        for mut interpreter in backends() {
+            run(&mut interpreter, "let x = 10; let r = {name: \"a\"}").unwrap();
+            for (input, expected) in [
+                ("f\"total {x * 1.2:.2} EUR\"", string("total 12.00 EUR")),
+                ("f\"{r.name}: {[x, x + 1]}\"", string("a: [10, 11]")),
+                ("f\"{{{x:>4}}}\"", string("{  10}")),
+                ("let f = |n| f\"<{n}>\" in f(\"y\")", string("<y>")),
+                ("f'{f\"{x}\" + \"!\"}'", string("10!")),
+            ] {
+                assert_eq!(run(&mut interpreter, input), Ok(expected), "{}", input);
+            }
+            assert_eq!(
+                run(&mut interpreter, "f\"{y}\""),
+                Err(InterpretError::UnknownVariable("y".to_string(), None))
+            );
+            assert!(run(&mut interpreter, "f\"{r:.2}\"").is_err());
+        }
+    }
 
-impl Ord for Value {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if self.gt(other) {
-            std::cmp::Ordering::Greater
-        } else if self.lt(other) {
-            std::cmp::Ordering::Less
-        } else if self.eq(other) {
-            std::cmp::Ordering::Equal
-        } else {
-            panic!("Not possible");
+    #[test]
+    fn pattern_matching() {
+        let string = |string: &str| Value::String(string.to_string());
+        for mut interpreter in backends() {
+            run(
+                &mut interpreter,
+                "let describe = |x| match x {
+                    nil => \"nothing\",
+                    0 => \"zero\",
+                    -1.5 => \"negative\",
+                    [] => \"empty\",
+                    [h] => f\"just {h}\",
+                    [h, ..t] => f\"{h} and {len(t)} more\",
+                    {a, b} => f\"pair of {a} and {b}\",
+                    {..rest} => f\"{len(rest)}-tuple\",
+                    n if n > 10 => \"big\",
+                    _ => \"other\",
+                }",
+            )
+            .unwrap();
+            for (input, expected) in [
+                ("nil", "nothing"),
+                ("0", "zero"),
+                ("-1.5", "negative"),
+                ("[]", "empty"),
+                ("[1]", "just 1"),
+                ("[1, 2, 3]", "1 and 2 more"),
+                ("{1, 2}", "pair of 1 and 2"),
+                ("{1, 2, 3}", "3-tuple"),
+                ("11", "big"),
+                ("5", "other"),
+            ] {
+                let input = format!("describe({})", input);
+                assert_eq!(
+                    run(&mut interpreter, &input),
+                    Ok(string(expected)),
+                    "{}",
+                    input
+                );
+            }
+            // Bindings are only visible in their arm
+            run(&mut interpreter, "let x = 1").unwrap();
+            assert_eq!(
+                run(&mut interpreter, "match [5, 6, 7] { [..a, x] => x } + x"),
+                Ok(Value::Int(8))
+            );
+            assert_eq!(
+                run(&mut interpreter, "match 2 { 1 => 1, n if n < 0 => 2 }"),
+                Err(InterpretError::RuntimeError(
+                    "No match arm fits 2".to_string()
+                ))
+            );
+            // Arms are in tail position
+            run(
+                &mut interpreter,
+                "fn total(xs, acc) = match xs { [] => acc, [h, ..t] => total(t, acc + h) }",
+            )
+            .unwrap();
+            assert_eq!(
+                run(&mut interpreter, "total(range(1, 2000), 0)"),
+                Ok(Value::Int(2001000))
+            );
         }
     }
-}
 
-impl Display for Function {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "|{}| {}", self.parameters.join(", "), self.body)
+    #[test]
+    fn pipelines() {
+        for mut interpreter in backends() {
+            for (input, expected) in [
+                ("range(1, 100) |> filter(odd) |> map(sq) |> sum", "166650"),
+                ("range(1, 10).filter(odd).map(sq).sum()", "165"),
+                ("fn inc(x, n) = x + n; 5 |> inc(2) |> sq", "49"),
+                ("[[1, 2], [3]] |> map(len)", "[2, 1]"),
+                ("\"a,b\".split(\",\").join(\"-\").upper()", "A-B"),
+                ("let f = if true then sq else cube; f(3)", "9"),
+                ("fold([1, 2], |acc, x| acc + x, 0) |> to_str", "3"),
+                ("let r = {double: |x| x * 2}; r.double(4)", "8"),
+                // Parameters and variables with a built-in's name come first
+                ("(|len| len)(1)", "1"),
+                ("(|now| now)(1)", "1"),
+                ("let sq = 2; map([1, 2], |x| x * sq)", "[2, 4]"),
+            ] {
+                let value = run(&mut interpreter, input).map(|value| value.to_string());
+                assert_eq!(value, Ok(expected.to_string()), "{}", input);
+            }
+        }
     }
-}
 
-impl Display for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Stmt::Assign(name, expr) => format!("let {} = {}", name, expr),
-                Stmt::Expr(expr) => return inner_write(expr, f),
-                Stmt::Undef(vars) => format!("undef({})", vars.join(", ")),
+    #[test]
+    fn destructuring() {
+        let unpack = |msg: &str| Err(InterpretError::InvalidArgument(msg.to_string()));
+        for mut interpreter in backends() {
+            for (input, expected) in [
+                ("let {x, y} = quadr(1, 1, -2); {x, y}", "{1, -2}"),
+                (
+                    "let [a, b, ..rest] = [1, 2, 3, 4]; {a, b, rest}",
+                    "{1, 2, [3, 4]}",
+                ),
+                ("let {a, {b, c}} = {1, {2, 3}}; a + b + c", "6"),
+                ("map([{1, 2}, {3, 4}], |{a, b}| a * b)", "[2, 12]"),
+                ("let x = 1; let f = |{x, _}| x; f({5, 6}) + x", "6"),
+                ("{ let [h, ..] = [7, 8]; h }", "7"),
+            ] {
+                let value = run(&mut interpreter, input).map(|value| value.to_string());
+                assert_eq!(value, Ok(expected.to_string()), "{}", input);
             }
-        )
+            run(&mut interpreter, "let f = |{x, y}| x + y").unwrap();
+            assert_eq!(
+                run(&mut interpreter, "f(5)"),
+                unpack("Cannot unpack 5 into {x, y}, expected a tuple or list")
+            );
+            assert_eq!(
+                run(&mut interpreter, "f({1, 2, 3})"),
+                unpack(
+                    "Cannot unpack {1, 2, 3} into {x, y}, the tuple has 3 elements instead of 2"
+                )
+            );
+            assert_eq!(
+                run(&mut interpreter, "let [p, ..] = []"),
+                unpack(
+                    "Cannot unpack [] into [p, ..], the list has 0 elements instead of at least 1"
+                )
+            );
+            assert_eq!(
+                run(&mut interpreter, "let {p, [q, r]} = {1, [2]}"),
+                unpack("Cannot unpack [2] into [q, r], the list has 1 elements instead of 2")
+            );
+            // Parameters are unpacked on every call, including tail calls
+            run(
+                &mut interpreter,
+                "fn total([h, ..t], acc) = if len(t) == 0 then acc + h else total(t, acc + h)",
+            )
+            .unwrap();
+            assert_eq!(
+                run(&mut interpreter, "total(range(1, 2000), 0)"),
+                Ok(Value::Int(2001000))
+            );
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn strings_round_trip() {
+        let mut interpreter = Interpreter::new();
+        let input = r#"let s = "say \"hi\"\n\tand \\ bye""#;
+        run(&mut interpreter, input).unwrap();
+        let saved = interpreter.env().get("s").unwrap().to_input("s");
+        assert_eq!(saved, input);
+        run(&mut interpreter, "let xs = [s, {s}]").unwrap();
+        let saved = interpreter.env().get("xs").unwrap().to_input("xs");
+        let mut restored = Interpreter::new();
+        run(&mut restored, &saved).unwrap();
+        run(&mut restored, input).unwrap();
+        assert_eq!(restored.env().get("xs"), interpreter.env().get("xs"));
+        assert_eq!(restored.env().get("s"), interpreter.env().get("s"));
+    }
 
-    fn check(expr: Expr, expected: Result<Value, InterpretError>) {
-        let interpreter = Interpreter::new();
-        let res = interpreter.interpret_expr(&expr);
-        assert_eq!(res, expected);
+    #[test]
+    fn records_round_trip() {
+        let mut interpreter = Interpreter::new();
+        let input = "let r = {a: [1, \"b\"], c: {d: 1.5d}, e: cast(-1, \"i8\"), f: |x| x+1}";
+        run(&mut interpreter, input).unwrap();
+        let saved = interpreter.env().get("r").unwrap().to_input("r");
+        assert_eq!(saved, input);
+        let mut restored = Interpreter::new();
+        run(&mut restored, &saved).unwrap();
+        assert_eq!(restored.env().get("r"), interpreter.env().get("r"));
     }
 
-    fn check_with_vars(
-        expr: Expr,
-        expected: Result<Value, InterpretError>,
-        env: HashMap<String, Value>,
-    ) {
-        let interpreter = Interpreter::with_env(env);
-        let res = interpreter.interpret_expr(&expr);
-        assert_eq!(res, expected);
+    #[test]
+    fn recursion_depth() {
+        for mut interpreter in backends() {
+            run(&mut interpreter, "config max_depth = 50").unwrap();
+            run(
+                &mut interpreter,
+                "fn count(n) = if n == 0 then 0 else 1 + count(n - 1)",
+            )
+            .unwrap();
+            assert_eq!(run(&mut interpreter, "count(49)"), Ok(Value::Int(49)));
+            assert_eq!(
+                run(&mut interpreter, "count(50)"),
+                Err(InterpretError::RuntimeError(
+                    "Maximum recursion depth of 50 exceeded".to_string()
+                ))
+            );
+            // Tail calls loop instead of nesting, but not forever
+            run(&mut interpreter, "fn f(n) = f(n + 1)").unwrap();
+            assert_eq!(
+                run(&mut interpreter, "f(0)"),
+                Err(InterpretError::RuntimeError(
+                    "Maximum of 5000 tail calls exceeded".to_string()
+                ))
+            );
+            run(
+                &mut interpreter,
+                "fn loop(n) = if n == 0 then 0 else loop(n - 1)",
+            )
+            .unwrap();
+            assert_eq!(run(&mut interpreter, "loop(5000)"), Ok(Value::Int(0)));
+            // Deep recursion doesn't overflow the stack
+            run(&mut interpreter, "config max_depth = 5000").unwrap();
+            assert_eq!(run(&mut interpreter, "count(4999)"), Ok(Value::Int(4999)));
+        }
     }
 
     #[test]
-    fn simple_add() {
+    fn date_arithmetic() {
+        let time = |literal: &str| Expr::Time(datetime::parse(literal).unwrap());
+        let date = |y, m, d| Value::Date(NaiveDate::from_ymd_opt(y, m, d).unwrap());
         check(
             Expr::Binary(
-                Box::new(Expr::Float(1.1)),
+                Box::new(time("2024-03-01")),
+                Token::Minus,
+                Box::new(time("2024-01-01")),
+            ),
+            Ok(Value::Duration(TimeDelta::days(60))),
+        );
+        check(
+            Expr::Binary(
+                Box::new(time("2024-01-01")),
                 Token::Plus,
-                Box::new(Expr::Float(5.0)),
+                Box::new(time("P90D")),
             ),
-            Ok(Value::Float(6.1)),
+            Ok(date(2024, 3, 31)),
+        );
+        check(
+            Expr::Binary(
+                Box::new(time("2024-01-01T08:00")),
+                Token::Minus,
+                Box::new(time("2024-01-01T09:00+02:00")),
+            ),
+            Ok(Value::Duration(TimeDelta::hours(1))),
+        );
+        check(
+            Expr::Binary(
+                Box::new(time("2024-01-01")),
+                Token::Plus,
+                Box::new(Expr::Quantity(
+                    Box::new(Expr::Int(36)),
+                    vec![("h".to_string(), 1)],
+                )),
+            ),
+            Ok(Value::DateTime(
+                DateTime::parse_from_rfc3339("2024-01-02T12:00:00Z").unwrap(),
+            )),
+        );
+        check(
+            Expr::Convert(Box::new(time("P1DT2H")), vec![("h".to_string(), 1)]),
+            Ok(Value::Quantity(26.0, Unit::builtin("h").unwrap())),
         );
     }
 
     #[test]
-    fn simple_variable() {
-        check_with_vars(
-            Expr::Binary(
-                Box::new(Expr::Float(12.0)),
-                Token::Mult,
-                Box::new(Expr::Var("foo".to_string())),
+    fn date_functions() {
+        let time = |literal: &str| Expr::Time(datetime::parse(literal).unwrap());
+        check(
+            Expr::Func(Func::Weekday, vec![time("2024-01-15")]),
+            Ok(Value::String("Monday".to_string())),
+        );
+        check(
+            Expr::Func(
+                Func::AddBusinessDays,
+                vec![time("2024-01-12"), Expr::Int(1)],
             ),
-            Ok(Value::Float(144.0)),
-            HashMap::from_iter([("foo".to_string(), Value::Float(12.0))]),
+            Ok(Value::Date(NaiveDate::from_ymd_opt(2024, 1, 15).unwrap())),
+        );
+        check(
+            Expr::Func(
+                Func::Strftime,
+                vec![
+                    time("2024-01-15T09:30"),
+                    Expr::String("%d/%m %H:%M".to_string()),
+                ],
+            ),
+            Ok(Value::String("15/01 09:30".to_string())),
         );
+        check(
+            Expr::Func(
+                Func::Tz,
+                vec![time("2024-01-15T09:30"), Expr::String("+05:30".to_string())],
+            ),
+            Ok(Value::DateTime(
+                DateTime::parse_from_rfc3339("2024-01-15T15:00:00+05:30").unwrap(),
+            )),
+        );
+        for interpreter in backends() {
+            assert!(interpreter
+                .interpret_expr(&Expr::Func(
+                    Func::Strftime,
+                    vec![time("2024-01-15"), Expr::String("%H".to_string())],
+                ))
+                .is_err());
+        }
     }
 
     #[test]
-    fn function_with_closure() {
-        check_with_vars(
-            Expr::Call(
-                Box::new(Expr::Var("foo".to_string())),
-                vec![Expr::Var("bar".to_string())],
+    fn decimal_settings() {
+        for mut interpreter in backends() {
+            let mut config = |name: &str, expr: Expr| {
+                interpreter
+                    .interpret(Stmt::Config(name.to_string(), expr))
+                    .unwrap();
+            };
+            config("decimal", Expr::Bool(true));
+            config("precision", Expr::Int(2));
+            config("rounding", Expr::String("half_up".to_string()));
+            assert_eq!(
+                interpreter.interpret_expr(&Expr::Binary(
+                    Box::new(Expr::Float(0.1)),
+                    Token::Plus,
+                    Box::new(Expr::Float(0.2)),
+                )),
+                Ok(Value::Decimal(Decimal::new(3, 1)))
+            );
+            assert_eq!(
+                interpreter.interpret_expr(&Expr::Binary(
+                    Box::new(Expr::Int(2)),
+                    Token::Div,
+                    Box::new(Expr::Float(3.0)),
+                )),
+                Ok(Value::Decimal(Decimal::new(67, 2)))
+            );
+            assert!(interpreter
+                .interpret(Stmt::Config("precision".to_string(), Expr::Int(-1)))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn fixed_width_ints() {
+        let fixed = |int: i64, ty: IntType| Expr::Fixed(BigInt::from(int), ty);
+        check(
+            Expr::Binary(
+                Box::new(fixed(255, IntType::U8)),
+                Token::Plus,
+                Box::new(Expr::Int(1)),
             ),
-            Ok(Value::Int(12)),
-            HashMap::from_iter([
-                (
-                    "foo".to_string(),
-                    Value::Fn(Function::new(
-                        vec!["x".to_string()],
-                        Expr::Binary(
-                            Box::new(Expr::Binary(
-                                Box::new(Expr::Var("x".to_string())),
-                                Token::Mult,
-                                Box::new(Expr::Int(2)),
-                            )),
-                            Token::Plus,
-                            Box::new(Expr::Var("bar".to_string())),
-                        ),
-                        HashMap::from_iter([("bar".to_string(), Value::Int(2))]),
-                    )),
-                ),
-                ("bar".to_string(), Value::Int(5)), // The function uses the closure value
-            ]),
+            Ok(Value::Fixed(BigInt::from(0), IntType::U8)),
+        );
+        check(
+            Expr::Unary(Box::new(fixed(45, IntType::U8)), Token::Not),
+            Ok(Value::Fixed(BigInt::from(210), IntType::U8)),
+        );
+        check(
+            Expr::Binary(
+                Box::new(fixed(-16, IntType::I8)),
+                Token::Shr,
+                Box::new(Expr::Int(2)),
+            ),
+            Ok(Value::Fixed(BigInt::from(-4), IntType::I8)),
+        );
+        check(
+            Expr::Func(
+                Func::Cast,
+                vec![Expr::Int(-1), Expr::String("u16".to_string())],
+            ),
+            Ok(Value::Fixed(BigInt::from(u16::MAX), IntType::U16)),
+        );
+        check(
+            Expr::Func(Func::Rotl, vec![fixed(0x81, IntType::U8), Expr::Int(1)]),
+            Ok(Value::Fixed(BigInt::from(3), IntType::U8)),
+        );
+        check(
+            Expr::Func(Func::Popcount, vec![fixed(-1, IntType::I16)]),
+            Ok(Value::Int(16)),
+        );
+        check(
+            Expr::Binary(
+                Box::new(fixed(5, IntType::U8)),
+                Token::Plus,
+                Box::new(fixed(5, IntType::I16)),
+            ),
+            Err(InterpretError::RuntimeError(
+                "Mismatched integer types u8 and i16".to_string(),
+            )),
         );
     }
 
     #[test]
-    fn nan_for_nan() {
-        check(Expr::Func(Func::Sqrt, vec![Expr::Int(-1)]), Ok(Value::NaN));
+    fn overflow_settings() {
+        for mut interpreter in backends() {
+            let product = Expr::Binary(
+                Box::new(Expr::Fixed(BigInt::from(200), IntType::U8)),
+                Token::Mult,
+                Box::new(Expr::Int(2)),
+            );
+            interpreter
+                .interpret(Stmt::Config(
+                    "overflow".to_string(),
+                    Expr::String("saturating".to_string()),
+                ))
+                .unwrap();
+            assert_eq!(
+                interpreter.interpret_expr(&product),
+                Ok(Value::Fixed(BigInt::from(255), IntType::U8))
+            );
+            interpreter
+                .interpret(Stmt::Config(
+                    "overflow".to_string(),
+                    Expr::String("checked".to_string()),
+                ))
+                .unwrap();
+            assert!(interpreter.interpret_expr(&product).is_err());
+        }
     }
 
     #[test]
@@ -1036,12 +4103,12 @@ mod tests {
         assert!(Value::Float(2.0) == Value::Int(2));
         let var = "foo".to_string();
         let fn_1 = Value::Fn(Function::new(
-            vec![var.clone()],
+            vec![Pattern::Bind(var.clone())],
             Expr::Var(var.clone()),
             HashMap::new(),
         ));
         let fn_2 = Value::Fn(Function::new(
-            vec![var.clone()],
+            vec![Pattern::Bind(var.clone())],
             Expr::Var(var.clone()),
             HashMap::new(),
         ));
@@ -1049,6 +4116,176 @@ mod tests {
 
         assert!(Value::Float(0.0) == Value::Int(0));
         assert!(Value::Int(0) == Value::Float(0.0));
+
+        let record = |fields: &[(&str, i64)]| {
+            Value::Record(
+                fields
+                    .iter()
+                    .map(|(name, int)| (name.to_string(), Value::Int(*int)))
+                    .collect(),
+            )
+        };
+        assert!(record(&[("a", 1), ("b", 2)]) == record(&[("b", 2), ("a", 1)]));
+        assert!(record(&[("a", 1), ("b", 2)]) < record(&[("a", 1), ("b", 3)]));
+        assert!(record(&[("a", 2)]) > record(&[("a", 1), ("b", 3)]));
+        assert!(record(&[("a", 1)]) < record(&[("a", 1), ("b", 0)]));
+        assert!(record(&[("b", 1)]) > record(&[("a", 9)]));
+    }
+
+    #[test]
+    fn int_overflow_promotes() {
+        let big = |digits: &str| Value::BigInt(digits.parse().unwrap());
+        check(
+            Expr::Binary(
+                Box::new(Expr::Int(i64::MAX)),
+                Token::Plus,
+                Box::new(Expr::Int(1)),
+            ),
+            Ok(big("9223372036854775808")),
+        );
+        check(
+            Expr::Binary(Box::new(Expr::Int(2)), Token::Pow, Box::new(Expr::Int(100))),
+            Ok(big("1267650600228229401496703205376")),
+        );
+        check(
+            Expr::Binary(Box::new(Expr::Int(1)), Token::Shl, Box::new(Expr::Int(64))),
+            Ok(big("18446744073709551616")),
+        );
+        check(
+            Expr::Func(Func::Fact, vec![Expr::Int(25)]),
+            Ok(big("15511210043330985984000000")),
+        );
+    }
+
+    #[test]
+    fn big_ints_demote() {
+        check(
+            Expr::Binary(
+                Box::new(Expr::BigInt("18446744073709551616".parse().unwrap())),
+                Token::Shr,
+                Box::new(Expr::Int(60)),
+            ),
+            Ok(Value::Int(16)),
+        );
+        check(
+            Expr::Binary(
+                Box::new(Expr::BigInt("18446744073709551616".parse().unwrap())),
+                Token::Mod,
+                Box::new(Expr::Int(1000)),
+            ),
+            Ok(Value::Int(616)),
+        );
+        assert_eq!(
+            Value::BigInt("18446744073709551616".parse().unwrap()).to_string(),
+            "18446744073709551616"
+        );
+    }
+
+    #[test]
+    fn big_ints_limited() {
+        let binary = |lhs, op, rhs| Expr::Binary(Box::new(Expr::Int(lhs)), op, Box::new(rhs));
+        let too_large = || {
+            Err(InterpretError::RuntimeError(
+                "Result too large, integers are limited to 262144 bits".to_string(),
+            ))
+        };
+        check(binary(2, Token::Pow, Expr::Int(4_000_000_000)), too_large());
+        check(binary(1, Token::Shl, Expr::Int(4_000_000_000)), too_large());
+        check(
+            binary(3, Token::Pow, Expr::Int(-4_000_000_000)),
+            too_large(),
+        );
+        check(binary(2, Token::Pow, Expr::Int(262_144)), too_large());
+        check(binary(1, Token::Shl, Expr::Int(262_144)), too_large());
+        // Nothing grows from these
+        check(
+            binary(1, Token::Pow, Expr::Int(4_000_000_000)),
+            Ok(Value::Int(1)),
+        );
+        check(
+            binary(-1, Token::Pow, Expr::Int(4_000_000_001)),
+            Ok(Value::Int(-1)),
+        );
+        check(
+            binary(0, Token::Shl, Expr::Int(4_000_000_000)),
+            Ok(Value::Int(0)),
+        );
+        check(
+            binary(5, Token::Shr, Expr::Int(4_000_000_000)),
+            Ok(Value::Int(0)),
+        );
+        for expr in [
+            binary(2, Token::Pow, Expr::Int(262_143)),
+            binary(1, Token::Shl, Expr::Int(262_143)),
+        ] {
+            for interpreter in backends() {
+                assert!(matches!(
+                    interpreter.interpret_expr(&expr),
+                    Ok(Value::BigInt(_))
+                ));
+            }
+        }
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Value {
+        Value::Rational(BigRational::new(numer.into(), denom.into()))
+    }
+
+    #[test]
+    fn exact_division() {
+        let third = || {
+            Box::new(Expr::Binary(
+                Box::new(Expr::Int(1)),
+                Token::Div,
+                Box::new(Expr::Int(3)),
+            ))
+        };
+        check(*third(), Ok(ratio(1, 3)));
+        check(
+            Expr::Binary(
+                Box::new(Expr::Binary(third(), Token::Plus, third())),
+                Token::Plus,
+                third(),
+            ),
+            Ok(Value::Int(1)),
+        );
+        check(
+            Expr::Binary(third(), Token::Pow, Box::new(Expr::Int(2))),
+            Ok(ratio(1, 9)),
+        );
+        check(
+            Expr::Binary(
+                Box::new(Expr::Int(2)),
+                Token::Pow,
+                Box::new(Expr::Unary(Box::new(Expr::Int(3)), Token::Minus)),
+            ),
+            Ok(ratio(1, 8)),
+        );
+        check(
+            Expr::Binary(Box::new(Expr::Int(1)), Token::Div, Box::new(Expr::Int(0))),
+            Ok(Value::NaN),
+        );
+        assert_eq!(ratio(-2, 6).to_string(), "-1/3");
+    }
+
+    #[test]
+    fn rationals_fall_to_float() {
+        let half = || {
+            Box::new(Expr::Binary(
+                Box::new(Expr::Int(1)),
+                Token::Div,
+                Box::new(Expr::Int(2)),
+            ))
+        };
+        check(
+            Expr::Binary(half(), Token::Plus, Box::new(Expr::Float(0.25))),
+            Ok(Value::Float(0.75)),
+        );
+        check(
+            Expr::Func(Func::Ln, vec![*half()]),
+            Ok(Value::Float(0.5_f64.ln())),
+        );
+        check(Expr::Func(Func::Recip, vec![Expr::Int(4)]), Ok(ratio(1, 4)));
     }
 
     #[test]
@@ -1,6 +1,6 @@
 use arboard::Clipboard;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use interpreter::{Interpreter, Value};
+use interpreter::{InterpretError, Interpreter, Stmt, Value};
 use parse::Parser;
 use ratatui::{prelude::CrosstermBackend, Terminal};
 use std::{error::Error, io};
@@ -14,12 +14,23 @@ use tui::Tui;
 const RC_PATH: &str = ".qcalcrc";
 
 mod app;
+mod compile;
+mod datetime;
 mod event;
+mod fixed;
+mod format;
 mod interpreter;
 mod parse;
+mod scope;
+mod settings;
+mod simplify;
+mod strings;
 mod token;
 mod tui;
+mod types;
 mod ui;
+mod units;
+mod vm;
 
 pub fn tui() -> Result<(), Box<dyn Error>> {
     let mut tui = Tui::new(
@@ -46,11 +57,47 @@ pub fn tui() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+pub use format::Format;
+
 pub fn eval(input: &str) -> Result<Value, Box<dyn Error>> {
-    let mut tokenizer = Tokenizer::new(input.chars().peekable()).peekable();
-    let current = tokenizer.next().ok_or("Expected expression")?;
-    let stmt = Parser::new(tokenizer, current).parse()?;
-    Ok(Interpreter::new().interpret(stmt)?)
+    let stmt = Parser::new(Tokenizer::new(input))
+        .and_then(|mut parser| parser.parse())
+        .map_err(|err| err.report(input))?;
+    Ok(Interpreter::new()
+        .interpret(stmt)
+        .map_err(|err| err.report(input))?)
+}
+
+// Things in the input which are allowed but probably not what was meant, eg. a match arm that
+// is never reached
+pub fn warnings(input: &str) -> Vec<String> {
+    Parser::new(Tokenizer::new(input))
+        .map(|mut parser| {
+            // Inputs that don't parse are reported by eval or run
+            let _ = parser.statements();
+            parser.warnings(input)
+        })
+        .unwrap_or_default()
+}
+
+// Runs a script, passing the value of each expression statement to output as it goes. Errors
+// point at the line they happened on
+pub fn run(source: &str, mut output: impl FnMut(Value)) -> Result<(), Box<dyn Error>> {
+    let stmts = Parser::new(Tokenizer::new(source))
+        .and_then(|mut parser| parser.statements())
+        .map_err(|err| err.report(source))?;
+    let mut interpreter = Interpreter::new();
+    for (stmt, span) in stmts {
+        let is_expr = matches!(stmt, Stmt::Expr(_));
+        let value = interpreter.interpret(stmt).map_err(|err| match err {
+            InterpretError::TypeError(_, _) => err.report(source),
+            err => parse::report(&err, span, source),
+        })?;
+        if is_expr {
+            output(value);
+        }
+    }
+    Ok(())
 }
 
 fn update(app: &mut App, key_event: KeyEvent) {
@@ -77,7 +124,7 @@ fn update(app: &mut App, key_event: KeyEvent) {
             }
             KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
                 if let Some(output) = app.output.as_ref() {
-                    match app.clipboard.set_text(output.to_string()) {
+                    match app.clipboard.set_text(app.format.value(output)) {
                         Ok(_) => {}
                         Err(err) => app.err = Some(err.to_string()),
                     }
@@ -96,6 +143,9 @@ fn update(app: &mut App, key_event: KeyEvent) {
             KeyCode::Char('f') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
                 app.popup = Some(Popup::Function);
             }
+            KeyCode::Char('o') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.format = app.format.next();
+            }
             KeyCode::Char('x') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
                 app.remove_expr();
             }
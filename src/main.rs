@@ -5,20 +5,71 @@
     clippy::needless_lifetimes
 )]
 
-use std::error::Error;
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+    process,
+};
 
-use qcalc::eval;
+use qcalc::{eval, run, warnings, Format};
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() {
+    // Returning the error from main would print its Debug form, with quotes around messages
+    if let Err(err) = cli() {
+        let _ = writeln!(io::stderr(), "Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn cli() -> Result<(), Box<dyn Error>> {
     let mut args = std::env::args();
     args.next();
-    let inputs = args.collect::<Vec<_>>();
+    let mut format = Format::default();
+    let mut inputs = vec![];
+    while let Some(arg) = args.next() {
+        // --format hex or --format=hex
+        if let Some(name) = arg.strip_prefix("--format=") {
+            format = name.parse()?;
+        } else if arg == "--format" {
+            format = args
+                .next()
+                .ok_or("Expected a format after --format")?
+                .parse()?;
+        } else {
+            inputs.push(arg);
+        }
+    }
     if inputs.is_empty() {
         qcalc::tui()
+    } else if inputs[0] == "run" {
+        // qcalc run sheet.qc
+        let path = inputs.get(1).ok_or("Expected a file after run")?;
+        let res = fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path, err).into())
+            .and_then(|source| {
+                for warning in warnings(&source) {
+                    writeln!(io::stderr(), "Warning: {}", warning)?;
+                }
+                run(&source, |res| println!("{}", format.value(&res)))
+            });
+        if let Err(err) = res {
+            writeln!(io::stderr(), "Error: {}", err)?;
+            process::exit(1);
+        }
+        Ok(())
     } else {
         for input in inputs.iter() {
-            let res = eval(input)?;
-            println!("{}", res);
+            for warning in warnings(input) {
+                writeln!(io::stderr(), "Warning: {}", warning)?;
+            }
+            match eval(input) {
+                Ok(res) => println!("{}", format.value(&res)),
+                Err(err) => {
+                    writeln!(io::stderr(), "Error: {}", err)?;
+                    process::exit(1);
+                }
+            }
         }
         Ok(())
     }
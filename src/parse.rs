@@ -1,13 +1,25 @@
 use crate::Tokenizer;
 use core::iter::Peekable;
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, vec};
 
-use crate::{interpreter::Stmt, token::Token};
+use num_bigint::BigInt;
+use rust_decimal::Decimal;
 
-pub const FNS: [&str; 40] = [
+use crate::{
+    datetime,
+    fixed::IntType,
+    interpreter::Stmt,
+    token::{escape, Fragment, LexError, Span, Token},
+    units,
+};
+
+pub const FNS: [&str; 74] = [
     COS, COSH, ACOS, ACOSH, ABS, SIN, SINH, ASIN, ASINH, TAN, TANH, ATAN, ATANH, LOG, LN, DEGS,
-    RADS, SQRT, SQ, CBRT, CUBE, ROUND, CEIL, FLOOR, EXP, EXP2, FRACT, RECIP, MAP, SUM, FOLD,
-    FILTER, ODD, EVEN, FACTORIAL, RANGE, ELEM, MIN, MAX, QUADR,
+    RADS, SQRT, SQ, CBRT, CUBE, ROUND, CEIL, FLOOR, EXP, EXP2, FRACT, RECIP, FLOAT, MAP, SUM, FOLD,
+    FILTER, ODD, EVEN, FACTORIAL, RANGE, ELEM, MIN, MAX, QUADR, RE, IM, CONJ, ARG, NOW, TODAY,
+    WEEKDAY, ADD_BDAYS, STRFTIME, TZ, CAST, ROTL, ROTR, POPCOUNT, CLZ, KEYS, VALUES, ENTRIES, SET,
+    LEN, UPPER, LOWER, SPLIT, JOIN, TRIM, REPLACE, CONTAINS, STARTSWITH, SUBSTR, CHARS, TO_STR,
+    PARSE_NUM, FORMAT,
 ];
 
 const COS: &str = "cos";
@@ -38,6 +50,7 @@ const EXP: &str = "exp";
 const EXP2: &str = "exp2";
 const FRACT: &str = "fract";
 const RECIP: &str = "recip";
+const FLOAT: &str = "float";
 const MAP: &str = "map";
 const SUM: &str = "sum";
 const FOLD: &str = "fold";
@@ -50,19 +63,72 @@ const ELEM: &str = "elem";
 const MIN: &str = "min";
 const MAX: &str = "max";
 const QUADR: &str = "quadr";
+const RE: &str = "re";
+const IM: &str = "im";
+const CONJ: &str = "conj";
+const ARG: &str = "arg";
+const NOW: &str = "now";
+const TODAY: &str = "today";
+const WEEKDAY: &str = "weekday";
+const ADD_BDAYS: &str = "add_business_days";
+const STRFTIME: &str = "strftime";
+const TZ: &str = "tz";
+const CAST: &str = "cast";
+const ROTL: &str = "rotl";
+const ROTR: &str = "rotr";
+const POPCOUNT: &str = "popcount";
+const CLZ: &str = "clz";
+const KEYS: &str = "keys";
+const VALUES: &str = "values";
+const ENTRIES: &str = "entries";
+const SET: &str = "set";
+const LEN: &str = "len";
+const UPPER: &str = "upper";
+const LOWER: &str = "lower";
+const SPLIT: &str = "split";
+const JOIN: &str = "join";
+const TRIM: &str = "trim";
+const REPLACE: &str = "replace";
+const CONTAINS: &str = "contains";
+const STARTSWITH: &str = "starts_with";
+const SUBSTR: &str = "substr";
+const CHARS: &str = "chars";
+const TO_STR: &str = "to_str";
+const PARSE_NUM: &str = "parse_num";
+const FORMAT: &str = "format";
 
 #[derive(Debug)]
-pub struct Parser<'a> {
-    tokenizer: Peekable<Tokenizer<'a>>,
+pub struct Parser {
+    tokens: Peekable<vec::IntoIter<(Token, Span)>>,
     current: Token,
+    // Where the current token and the one before it are in the input
+    span: Span,
+    previous: Span,
+    // Errors that were recovered from, parsing carries on after them
+    errors: Vec<Diagnostic>,
+    // Where operations and calls are in the input, only kept when locating one, see `locate`
+    spans: Option<Vec<(Expr, Span)>>,
+    // Allowed but probably not what was meant, eg. a match arm that's never reached
+    warnings: Vec<(&'static str, Span)>,
 }
 
-#[derive(Debug, PartialEq)]
-pub struct ParseErr {
-    pub token: Token,
+// A single problem with the input and where it is
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
     pub msg: &'static str,
+    pub span: Span,
+    // What was there instead, as it was written
+    pub found: String,
+    // The token that was wanted, when there was only one option
+    pub expected: Option<Token>,
+    // Hints shown below the error, eg. did you mean `sqrt`?
+    pub notes: Vec<String>,
 }
 
+// Every problem found in an input, in the order they appear
+#[derive(Debug, PartialEq)]
+pub struct ParseErr(pub Vec<Diagnostic>);
+
 // "Built in" functions, separate of user defined functions
 #[derive(Debug, PartialEq, Clone)]
 pub enum Func {
@@ -94,6 +160,7 @@ pub enum Func {
     Exp2,
     Fract,
     Recip,
+    Float,
     Map,
     Sum,
     Fold,
@@ -106,6 +173,39 @@ pub enum Func {
     Min,
     Max,
     Quadr,
+    Re,
+    Im,
+    Conj,
+    Arg,
+    Now,
+    Today,
+    Weekday,
+    AddBusinessDays,
+    Strftime,
+    Tz,
+    Cast,
+    Rotl,
+    Rotr,
+    Popcount,
+    Clz,
+    Keys,
+    Values,
+    Entries,
+    Set,
+    Len,
+    Upper,
+    Lower,
+    Split,
+    Join,
+    Trim,
+    Replace,
+    Contains,
+    StartsWith,
+    Substr,
+    Chars,
+    ToStr,
+    ParseNum,
+    Format,
 }
 
 impl Func {
@@ -139,6 +239,7 @@ impl Func {
             Func::Exp2 => 1,
             Func::Fract => 1,
             Func::Recip => 1,
+            Func::Float => 1,
             Func::Map => 2,
             Func::Sum => 1,
             Func::Fold => 3,
@@ -151,6 +252,39 @@ impl Func {
             Func::Min => 1,
             Func::Max => 1,
             Func::Quadr => 3,
+            Func::Re => 1,
+            Func::Im => 1,
+            Func::Conj => 1,
+            Func::Arg => 1,
+            Func::Now => 0,
+            Func::Today => 0,
+            Func::Weekday => 1,
+            Func::AddBusinessDays => 2,
+            Func::Strftime => 2,
+            Func::Tz => 2,
+            Func::Cast => 2,
+            Func::Rotl => 2,
+            Func::Rotr => 2,
+            Func::Popcount => 1,
+            Func::Clz => 1,
+            Func::Keys => 1,
+            Func::Values => 1,
+            Func::Entries => 1,
+            Func::Set => 3,
+            Func::Len => 1,
+            Func::Upper => 1,
+            Func::Lower => 1,
+            Func::Split => 2,
+            Func::Join => 2,
+            Func::Trim => 1,
+            Func::Replace => 3,
+            Func::Contains => 2,
+            Func::StartsWith => 2,
+            Func::Substr => 3,
+            Func::Chars => 1,
+            Func::ToStr => 1,
+            Func::ParseNum => 1,
+            Func::Format => 2,
         }
     }
 }
@@ -161,18 +295,218 @@ pub enum Expr {
     Grouping(Box<Expr>),
     Float(f64),
     Int(i64),
+    BigInt(BigInt),
+    Fixed(BigInt, IntType),
+    Imaginary(f64),
+    Decimal(Decimal),
+    Time(datetime::Literal),
+    // A number followed by its unit, eg. 5 km/h
+    Quantity(Box<Expr>, Vec<(String, i32)>),
+    // Conversion with `to`, eg. 90 km/h to m/s
+    Convert(Box<Expr>, Vec<(String, i32)>),
     Unary(Box<Expr>, Token),
     Call(Box<Expr>, Vec<Expr>),
     Func(Func, Vec<Expr>),
-    Fun(Vec<String>, Box<Expr>),
+    Fun(Vec<Pattern>, Box<Expr>),
     Var(String),
     Bool(bool),
     If(Box<Expr>, Box<Expr>, Box<Expr>),
     String(String),
     List(Vec<Expr>),
     Tuple(Vec<Expr>),
+    // Named fields, eg. {name: "x", rate: 0.2}
+    Record(Vec<(String, Expr)>),
+    // A copy of a record with some fields changed or added, eg. {..r, rate: 0.3}
+    Update(Box<Expr>, Vec<(String, Expr)>),
+    // Dot access, eg. r.rate
+    Field(Box<Expr>, String),
+    // An element of a list, tuple or string, counting from the end when negative, eg. xs[-1]
+    Index(Box<Expr>, Box<Expr>),
+    // Start, end and step, each of which can be left out, eg. xs[1:3] or xs[::2]
+    Slice(Box<Expr>, [Option<Box<Expr>>; 3]),
+    // A string with expressions in it, eg. f"total {x * 1.2:.2} EUR"
+    Interpolated(Vec<Part>),
+    // The first arm whose pattern matches the value, eg. match xs { [] => 0, [h, ..t] => h }
+    Match(Box<Expr>, Vec<Arm>),
     Nil,
     Nan,
+    // Statements with their own scope, the value is that of the last one
+    Block(Vec<Stmt>),
+    // Names only visible in the body, from let .. in or where
+    Let(Vec<(String, Expr)>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Part {
+    Text(String),
+    // With an optional format spec, eg. .2
+    Expr(Expr, Option<String>),
+}
+
+impl Part {
+    pub fn expr(&self) -> Option<&Expr> {
+        match self {
+            Part::Text(_) => None,
+            Part::Expr(expr, _) => Some(expr),
+        }
+    }
+}
+
+// What the value of a match is compared with
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Wildcard,
+    // Matches anything, giving it a name in the arm
+    Bind(String),
+    // Compared with ==, eg. 0, -1.5, "a", true or nil
+    Literal(Expr),
+    List(Vec<Pattern>),
+    Tuple(Vec<Pattern>),
+    // The elements the others in a list or tuple didn't take, eg. ..t in [h, ..t]
+    Rest(Option<String>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+}
+
+impl Pattern {
+    // The names it binds, in order
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Pattern::Bind(name) | Pattern::Rest(Some(name)) => vec![name],
+            Pattern::List(patterns) | Pattern::Tuple(patterns) => {
+                patterns.iter().flat_map(Pattern::names).collect()
+            }
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Rest(None) => vec![],
+        }
+    }
+
+    pub fn format(&self) -> String {
+        match self {
+            Pattern::Wildcard => "_".to_string(),
+            Pattern::Bind(name) => name.clone(),
+            Pattern::Literal(expr) => expr.format(),
+            Pattern::List(patterns) => format!("[{}]", join_patterns(patterns)),
+            Pattern::Tuple(patterns) => format!("{{{}}}", join_patterns(patterns)),
+            Pattern::Rest(name) => format!("..{}", name.as_deref().unwrap_or_default()),
+        }
+    }
+
+    fn literals(&self) -> bool {
+        match self {
+            Pattern::Literal(_) => true,
+            Pattern::List(patterns) | Pattern::Tuple(patterns) => {
+                patterns.iter().any(Pattern::literals)
+            }
+            Pattern::Wildcard | Pattern::Bind(_) | Pattern::Rest(_) => false,
+        }
+    }
+
+    fn irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard | Pattern::Bind(_))
+    }
+}
+
+impl Arm {
+    pub fn format(&self) -> String {
+        let guard = self
+            .guard
+            .as_ref()
+            .map(|guard| format!(" if {}", guard.format()))
+            .unwrap_or_default();
+        format!(
+            "{}{} => {}",
+            self.pattern.format(),
+            guard,
+            self.body.format()
+        )
+    }
+}
+
+// Whether there's always an arm that matches, as far as that can be told without knowing the
+// type of the value: a _ or name, true and false, or lists or tuples of every length
+fn exhaustive(patterns: &[&Pattern]) -> bool {
+    let literal = |bool| Pattern::Literal(Expr::Bool(bool));
+    if patterns.iter().any(|pattern| pattern.irrefutable())
+        || [true, false]
+            .map(literal)
+            .iter()
+            .all(|bool| patterns.contains(&bool))
+    {
+        return true;
+    }
+    let lengths = |tuple: bool| {
+        // How many elements each pattern takes, and whether it can take more
+        let shapes = patterns
+            .iter()
+            .filter_map(|pattern| match (pattern, tuple) {
+                (Pattern::List(patterns), false) | (Pattern::Tuple(patterns), true) => patterns
+                    .iter()
+                    .all(|pattern| pattern.irrefutable() || matches!(pattern, Pattern::Rest(_)))
+                    .then(|| {
+                        let rest = patterns.iter().any(|p| matches!(p, Pattern::Rest(_)));
+                        (patterns.len() - usize::from(rest), rest)
+                    }),
+                _ => None,
+            });
+        let shapes = shapes.collect::<Vec<_>>();
+        shapes
+            .iter()
+            .filter(|(_, rest)| *rest)
+            .map(|(len, _)| *len)
+            .min()
+            .is_some_and(|min| (0..min).all(|len| shapes.contains(&(len, false))))
+    };
+    lengths(false) || lengths(true)
+}
+
+// Binding the same name twice would silently drop one of the values, wherever the pattern is
+fn distinct_names(pattern: &Pattern, span: Span) -> Result<(), Diagnostic> {
+    let names = pattern.names();
+    match names
+        .iter()
+        .enumerate()
+        .find(|(i, name)| names[..*i].contains(name))
+    {
+        Some((_, name)) => Err(Diagnostic::new(
+            Token::Ident(name.to_string()),
+            "A name can only be used once in a pattern",
+            span,
+        )),
+        None => Ok(()),
+    }
+}
+
+// A named function which can call itself and the others defined along with it, from
+// fn NAME(PARAMS) = BODY
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub parameters: Vec<Pattern>,
+    pub body: Expr,
+}
+
+impl Definition {
+    pub fn format(&self) -> String {
+        format!(
+            "{}({}) = {}",
+            self.name,
+            join_patterns(&self.parameters),
+            self.body.format()
+        )
+    }
+}
+
+pub fn join_patterns(patterns: &[Pattern]) -> String {
+    patterns
+        .iter()
+        .map(Pattern::format)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl Expr {
@@ -184,7 +518,38 @@ impl Expr {
             }
             Self::Nan => "NaN".to_string(),
             Self::Int(int) => int.to_string(),
-            Self::String(string) => format!("\"{}\"", string.clone()),
+            Self::BigInt(int) => int.to_string(),
+            Self::Fixed(int, ty) => format!("{}{}", int, ty.name()),
+            Self::Imaginary(im) => format!("{}i", im),
+            Self::Decimal(decimal) => format!("{}d", decimal),
+            Self::Time(literal) => literal.to_string(),
+            Self::Quantity(expr, unit) => format!("{} {}", expr.format(), units::format(unit)),
+            Self::Convert(expr, unit) => format!("{} to {}", expr.format(), units::format(unit)),
+            Self::String(string) => format!("\"{}\"", escape(string)),
+            Self::Interpolated(parts) => format!(
+                "f\"{}\"",
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Text(text) => escape(text).replace('{', "{{").replace('}', "}}"),
+                        Part::Expr(expr, spec) => {
+                            // {{ would be read back as a brace
+                            let space = if expr.format().starts_with('{') {
+                                " "
+                            } else {
+                                ""
+                            };
+                            let spec = spec.as_ref().map(|spec| format!(":{}", spec));
+                            format!("{{{}{}{}}}", space, expr.format(), spec.unwrap_or_default())
+                        }
+                    })
+                    .collect::<String>()
+            ),
+            Self::Match(subject, arms) => format!(
+                "match {} {{ {} }}",
+                subject.format(),
+                arms.iter().map(Arm::format).collect::<Vec<_>>().join(", ")
+            ),
             Self::Unary(expr, operator) => format!("{}{}", operator, expr.format()),
             Self::Grouping(expr) => format!("({})", expr.format()),
             Self::Var(var) => var.to_string(),
@@ -193,7 +558,7 @@ impl Expr {
             }
             Self::Bool(bool) => bool.to_string(),
             Self::Fun(params, body) => {
-                format!("|{}| {}", params.join(", "), body,)
+                format!("|{}| {}", join_patterns(params), body,)
             }
             Self::Call(name, args) => {
                 format!(
@@ -230,35 +595,405 @@ impl Expr {
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
+            Self::Record(fields) => format!("{{{}}}", format_fields(fields)),
+            Self::Update(record, fields) if fields.is_empty() => {
+                format!("{{..{}}}", record.format())
+            }
+            Self::Update(record, fields) => {
+                format!("{{..{}, {}}}", record.format(), format_fields(fields))
+            }
+            Self::Field(record, name) => format!("{}.{}", record.format(), name),
+            Self::Index(expr, index) => format!("{}[{}]", expr.format(), index.format()),
+            Self::Slice(expr, [start, end, step]) => {
+                let bound = |bound: &Option<Box<Expr>>| {
+                    bound
+                        .as_ref()
+                        .map(|bound| bound.format())
+                        .unwrap_or_default()
+                };
+                let step = step
+                    .as_ref()
+                    .map(|step| format!(":{}", step.format()))
+                    .unwrap_or_default();
+                format!("{}[{}:{}{}]", expr.format(), bound(start), bound(end), step)
+            }
             Self::Nil => "nil".to_string(),
+            Self::Let(bindings, body) => format!(
+                "let {} in {}",
+                bindings
+                    .iter()
+                    .map(|(name, expr)| format!("{} = {}", name, expr.format()))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                body.format()
+            ),
+            Self::Block(stmts) => format!(
+                "{{ {} }}",
+                stmts
+                    .iter()
+                    .map(|s| s.format())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        }
+    }
+}
+
+fn format_fields(fields: &[(String, Expr)]) -> String {
+    fields
+        .iter()
+        .map(|(name, expr)| format!("{}: {}", name, expr.format()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Diagnostic {
+    pub fn new(found: Token, msg: &'static str, span: Span) -> Self {
+        Self {
+            msg,
+            span,
+            found: found.to_string(),
+            expected: None,
+            notes: vec![],
+        }
+    }
+
+    fn expected(mut self, token: Token) -> Self {
+        self.expected = Some(token);
+        self
+    }
+
+    fn note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    // The error followed by the line of input it's on with the offending part underlined, eg.
+    // Unexpected character, got: $ at column 3
+    // 2 $ 3
+    //   ^
+    pub fn report(&self, input: &str) -> String {
+        let mut report = report(self, self.span, input);
+        for note in &self.notes {
+            report.push_str(&format!("\nnote: {}", note));
+        }
+        report
+    }
+}
+
+// An error with where it happened in the input, see Diagnostic::report
+pub fn report(err: &impl Display, span: Span, input: &str) -> String {
+    let start = span.start.min(input.len());
+    let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+    let column = input[line_start..start].chars().count();
+    let width = input[start..span.end.clamp(start, line_end)]
+        .chars()
+        .count();
+    let location = if line_start == 0 && line_end == input.len() {
+        format!("column {}", column + 1)
+    } else {
+        let line = input[..line_start].matches('\n').count() + 1;
+        format!("line {}, column {}", line, column + 1)
+    };
+    format!(
+        "{} at {}\n{}\n{}{}",
+        err,
+        location,
+        &input[line_start..line_end],
+        " ".repeat(column),
+        "^".repeat(width.max(1))
+    )
+}
+
+impl From<LexError> for Diagnostic {
+    fn from(err: LexError) -> Self {
+        Self {
+            msg: err.msg,
+            span: err.span,
+            found: err.text,
+            expected: None,
+            notes: vec![],
         }
     }
 }
 
 impl ParseErr {
-    pub fn new(token: Token, msg: &'static str) -> Self {
-        Self { token, msg }
+    // Each diagnostic reported against the input, one after the other
+    pub fn report(&self, input: &str) -> String {
+        self.0
+            .iter()
+            .map(|diagnostic| diagnostic.report(input))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Tokens a line can't end with, so that the statement carries on over the newline, eg.
+// let f = |x|
+//     x + 1
+fn continues(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Comma
+            | Token::Colon
+            | Token::Dot
+            | Token::Semicolon
+            | Token::Newline
+            | Token::LParen
+            | Token::LBracket
+            | Token::LCurly
+            | Token::Let
+            | Token::In
+            | Token::Where
+            | Token::Fn
+            | Token::Undef
+            | Token::Config
+            | Token::Simplify
+            | Token::To
+            | Token::Assign
+            | Token::Pipe
+            | Token::Mod
+            | Token::Div
+            | Token::Mult
+            | Token::Plus
+            | Token::Minus
+            | Token::Pow
+            | Token::Not
+            | Token::BitAnd
+            | Token::BitXor
+            | Token::Shr
+            | Token::Shl
+            | Token::Eq
+            | Token::Ne
+            | Token::Gt
+            | Token::Gte
+            | Token::Lt
+            | Token::Lte
+            | Token::Or
+            | Token::And
+            | Token::If
+            | Token::Then
+            | Token::Else
+            | Token::Match
+            | Token::Arrow
+            | Token::Pipeline
+    )
+}
+
+// The built-in function with this name
+pub(crate) fn builtin(name: &str) -> Option<Func> {
+    Some(match name {
+        ABS => Func::Abs,
+        SIN => Func::Sin,
+        SINH => Func::Sinh,
+        ASIN => Func::Asin,
+        ASINH => Func::Asinh,
+        COS => Func::Cos,
+        COSH => Func::Cosh,
+        ACOS => Func::Acos,
+        ACOSH => Func::Acosh,
+        TAN => Func::Tan,
+        TANH => Func::Tanh,
+        ATAN => Func::Atan,
+        ATANH => Func::Atanh,
+        LN => Func::Ln,
+        LOG => Func::Log,
+        DEGS => Func::Degs,
+        RADS => Func::Rads,
+        SQ => Func::Sq,
+        SQRT => Func::Sqrt,
+        CUBE => Func::Cube,
+        CBRT => Func::Cbrt,
+        ROUND => Func::Round,
+        CEIL => Func::Ceil,
+        FLOOR => Func::Floor,
+        EXP => Func::Exp,
+        EXP2 => Func::Exp2,
+        FRACT => Func::Fract,
+        RECIP => Func::Recip,
+        FLOAT => Func::Float,
+        MAP => Func::Map,
+        SUM => Func::Sum,
+        FOLD => Func::Fold,
+        FILTER => Func::Filter,
+        EVEN => Func::Even,
+        ODD => Func::Odd,
+        FACTORIAL => Func::Fact,
+        RANGE => Func::Range,
+        ELEM => Func::Elem,
+        MIN => Func::Min,
+        MAX => Func::Max,
+        QUADR => Func::Quadr,
+        RE => Func::Re,
+        IM => Func::Im,
+        CONJ => Func::Conj,
+        ARG => Func::Arg,
+        NOW => Func::Now,
+        TODAY => Func::Today,
+        WEEKDAY => Func::Weekday,
+        ADD_BDAYS => Func::AddBusinessDays,
+        STRFTIME => Func::Strftime,
+        TZ => Func::Tz,
+        CAST => Func::Cast,
+        ROTL => Func::Rotl,
+        ROTR => Func::Rotr,
+        POPCOUNT => Func::Popcount,
+        CLZ => Func::Clz,
+        KEYS => Func::Keys,
+        VALUES => Func::Values,
+        ENTRIES => Func::Entries,
+        SET => Func::Set,
+        LEN => Func::Len,
+        UPPER => Func::Upper,
+        LOWER => Func::Lower,
+        SPLIT => Func::Split,
+        JOIN => Func::Join,
+        TRIM => Func::Trim,
+        REPLACE => Func::Replace,
+        CONTAINS => Func::Contains,
+        STARTSWITH => Func::StartsWith,
+        SUBSTR => Func::Substr,
+        CHARS => Func::Chars,
+        TO_STR => Func::ToStr,
+        PARSE_NUM => Func::ParseNum,
+        FORMAT => Func::Format,
+        _ => return None,
+    })
+}
+
+// The parameters and body of a lambda calling a built-in, eg. |x| odd(x) for odd
+pub(crate) fn function_value(func: Func) -> (Vec<Pattern>, Expr) {
+    let params = ["x", "y", "z"][..func.arity()]
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let args = params.iter().cloned().map(Expr::Var).collect();
+    (
+        params.into_iter().map(Pattern::Bind).collect(),
+        Expr::Func(func, args),
+    )
+}
+
+// Where an operation or call in the input is, by parsing it again. The first of several equal
+// ones is picked
+pub fn locate(input: &str, expr: &Expr) -> Option<Span> {
+    let mut parser = Parser::new(Tokenizer::new(input)).ok()?;
+    parser.spans = Some(vec![]);
+    parser.statements().ok()?;
+    parser
+        .spans?
+        .into_iter()
+        .find_map(|(spanned, span)| (spanned == *expr).then_some(span))
+}
+
+// The closest name within a few edits, for suggestions such as did you mean `sqrt`?
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max = name.chars().count() / 3;
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| (1..=max).contains(distance))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance, the number of single character edits to get from one string to the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
     }
+    row[b.len()]
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokenizer: Peekable<Tokenizer<'a>>, current: Token) -> Self {
-        Self { tokenizer, current }
+impl Parser {
+    // Reads all of the tokens up front so that lexical errors anywhere in the input are reported
+    pub fn new(tokenizer: Tokenizer) -> Result<Self, ParseErr> {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        // Newlines only separate statements outside of parentheses and brackets
+        let mut depth = 0usize;
+        for token in tokenizer {
+            match token {
+                Ok((Token::Newline, _))
+                    if depth > 0 || tokens.last().is_none_or(|(last, _)| continues(last)) => {}
+                Ok((token, span)) => {
+                    match token {
+                        Token::LParen | Token::LBracket => depth += 1,
+                        Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+                        _ => (),
+                    }
+                    // A pipeline can also carry on with |> at the start of the next line
+                    if matches!(
+                        token,
+                        Token::RParen | Token::RBracket | Token::RCurly | Token::Pipeline
+                    ) && tokens
+                        .last()
+                        .is_some_and(|(last, _)| *last == Token::Newline)
+                    {
+                        tokens.pop();
+                    }
+                    tokens.push((token, span));
+                }
+                Err(err) => errors.push(Diagnostic::from(err)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ParseErr(errors));
+        }
+        Ok(Self::from_tokens(tokens))
+    }
+
+    fn from_tokens(mut tokens: Vec<(Token, Span)>) -> Self {
+        // Past the last token, so that errors about a missing token point at the end
+        let end = tokens.last().map_or(0, |(_, span)| span.end);
+        tokens.push((Token::Eof, Span::new(end, end + 1)));
+        let mut tokens = tokens.into_iter().peekable();
+        let (current, span) = tokens.next().unwrap_or((Token::Eof, Span::default()));
+        Self {
+            tokens,
+            current,
+            span,
+            previous: span,
+            errors: vec![],
+            spans: None,
+            warnings: vec![],
+        }
+    }
+
+    // Each warning with the line it's on, like an error
+    pub fn warnings(&self, input: &str) -> Vec<String> {
+        self.warnings
+            .iter()
+            .map(|(msg, span)| report(msg, *span, input))
+            .collect()
+    }
+
+    fn spanned(&mut self, start: usize, expr: Expr) -> Expr {
+        if let Some(spans) = &mut self.spans {
+            spans.push((expr.clone(), Span::new(start, self.previous.end)));
+        }
+        expr
     }
 
     fn at_end(&mut self) -> bool {
-        self.tokenizer.peek().is_none()
+        self.current == Token::Eof
     }
 
+    // Moves on to the next token, returning the current one. Stays on Eof once it's reached
     fn advance(&mut self) -> Token {
-        if !self.at_end() {
-            let mut next = self.tokenizer.next().unwrap();
-            std::mem::swap(&mut self.current, &mut next);
-            next
-        } else {
-            // Not a big deal because this is only for the last token
-            self.current.clone()
-        }
+        let (next, span) = self.tokens.next().unwrap_or((Token::Eof, self.span));
+        self.previous = self.span;
+        self.span = span;
+        std::mem::replace(&mut self.current, next)
     }
 
     fn peek(&self) -> &Token {
@@ -269,31 +1004,189 @@ impl<'a> Parser<'a> {
         self.current == *token
     }
 
-    fn consume(&mut self, token: Token, msg: &'static str) -> Result<Token, ParseErr> {
+    fn consume(&mut self, token: Token, msg: &'static str) -> Result<Token, Diagnostic> {
         if self.check(&token) {
             Ok(self.advance())
         } else {
-            Err(ParseErr::new(token, msg))
+            let mut err = Diagnostic::new(self.peek().clone(), msg, self.span);
+            // A misspelled keyword, eg. thn instead of then
+            if let Token::Ident(name) = self.peek() {
+                let keyword = token.to_string();
+                if let Some(keyword) = suggest(name, [keyword.as_str()]) {
+                    err = err.note(format!("did you mean `{}`?", keyword));
+                }
+            }
+            Err(err.expected(token))
+        }
+    }
+
+    // Keeps the error and carries on, unless there already is one for the same place
+    fn record(&mut self, err: Diagnostic) {
+        if !self
+            .errors
+            .iter()
+            .any(|existing| existing.span.start == err.span.start)
+        {
+            self.errors.push(err);
+        }
+    }
+
+    // Skips ahead to the next comma or closing bracket which isn't nested, so that the rest of a
+    // list or argument list can still be checked after an error in one of its elements
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Token::Eof => return,
+                Token::Comma | Token::RParen | Token::RBracket | Token::RCurly if depth == 0 => {
+                    return
+                }
+                Token::LParen | Token::LBracket | Token::LCurly => depth += 1,
+                Token::RParen | Token::RBracket | Token::RCurly => depth -= 1,
+                _ => (),
+            }
+            self.advance();
+        }
+    }
+
+    // Skips to the end of the statement, past any nested blocks
+    fn skip_statement(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                Token::Eof => return,
+                Token::Semicolon | Token::Newline if depth == 0 => return,
+                Token::LParen | Token::LBracket | Token::LCurly => depth += 1,
+                Token::RParen | Token::RBracket | Token::RCurly => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+            self.advance();
+        }
+    }
+
+    fn separator(&self) -> bool {
+        matches!(self.peek(), Token::Semicolon | Token::Newline)
+    }
+
+    fn skip_separators(&mut self) {
+        while self.separator() {
+            self.advance();
+        }
+    }
+
+    // An expression inside brackets. On errors the rest of it is skipped and None returned
+    fn element(&mut self) -> Option<Expr> {
+        match self.expression().and_then(|expr| self.where_clause(expr)) {
+            Ok(expr) => Some(expr),
+            Err(err) => {
+                self.record(err);
+                self.synchronize();
+                None
+            }
         }
     }
+
+    // Comma separated expressions followed by the closing token
+    fn elements(&mut self, close: Token, msg: &'static str) -> Result<Vec<Expr>, Diagnostic> {
+        let mut elems = vec![];
+        if !self.check(&close) {
+            loop {
+                elems.extend(self.element());
+                if !self.check(&Token::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.consume(close, msg)?;
+        Ok(elems)
+    }
 }
 
-impl<'a> Parser<'a> {
+impl Parser {
+    // A whole input as one statement, several are combined into a sequence
     pub fn parse(&mut self) -> Result<Stmt, ParseErr> {
-        let res = match self.peek() {
-            Token::Let => self.assign()?,
-            Token::Undef => self.undef()?,
-            _ => Stmt::Expr(self.expression()?),
-        };
-        if self.at_end() {
-            Ok(res)
+        let mut stmts = self
+            .statements()?
+            .into_iter()
+            .map(|(stmt, _)| stmt)
+            .collect::<Vec<_>>();
+        match stmts.len() {
+            0 => Err(ParseErr(vec![Diagnostic::new(
+                Token::Eof,
+                "Expected expression",
+                self.span,
+            )])),
+            1 => Ok(stmts.remove(0)),
+            _ => Ok(Stmt::Seq(stmts)),
+        }
+    }
+
+    // Statements separated by ; or newlines, each with where it is in the input. An error skips
+    // the rest of its statement so that the following ones are still checked
+    pub fn statements(&mut self) -> Result<Vec<(Stmt, Span)>, ParseErr> {
+        let mut stmts = vec![];
+        self.skip_separators();
+        while !self.at_end() {
+            let start = self.span.start;
+            match self.statement() {
+                Ok(stmt) if self.separator() || self.at_end() => {
+                    stmts.push((stmt, Span::new(start, self.previous.end)))
+                }
+                // A complete statement was parsed but there were more tokens
+                Ok(_) => {
+                    let err = Diagnostic::new(self.peek().clone(), "Unexpected token", self.span);
+                    self.record(err);
+                    self.skip_statement();
+                }
+                Err(err) => {
+                    self.record(err);
+                    self.skip_statement();
+                }
+            }
+            self.skip_separators();
+        }
+        if self.errors.is_empty() {
+            Ok(stmts)
         } else {
-            // A complete expression was parsed but there were more tokens
-            Err(ParseErr::new(self.peek().clone(), "Unexpected token"))
+            let mut errors = std::mem::take(&mut self.errors);
+            errors.sort_by_key(|err| err.span.start);
+            Err(ParseErr(errors))
         }
     }
 
-    fn undef(&mut self) -> Result<Stmt, ParseErr> {
+    fn statement(&mut self) -> Result<Stmt, Diagnostic> {
+        match self.peek() {
+            Token::Let => self.assign(),
+            Token::Undef => self.undef(),
+            Token::Config => self.config(),
+            Token::Simplify => self.simplify(),
+            Token::Fn => self.definitions(),
+            _ => {
+                let expr = self.expression()?;
+                Ok(Stmt::Expr(self.where_clause(expr)?))
+            }
+        }
+    }
+
+    // Statements in braces with a scope of their own, the first expression has already been
+    // parsed if there was one, eg. { let a = 1; a + 2 }
+    fn block(&mut self, mut stmts: Vec<Stmt>) -> Result<Expr, Diagnostic> {
+        loop {
+            self.skip_separators();
+            if self.check(&Token::RCurly) || self.at_end() {
+                break;
+            }
+            stmts.push(self.statement()?);
+            if !self.separator() {
+                break;
+            }
+        }
+        self.consume(Token::RCurly, "Missing closing brace")?;
+        Ok(Expr::Block(stmts))
+    }
+
+    fn undef(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance();
         self.consume(Token::LParen, "Missing opening parentheses")?;
         let mut parameters = Vec::new();
@@ -302,14 +1195,15 @@ impl<'a> Parser<'a> {
                 let next = self.advance();
                 if let Token::Ident(arg) = next {
                     if parameters.contains(&arg) {
-                        return Err(ParseErr::new(
+                        return Err(Diagnostic::new(
                             Token::Ident(arg),
                             "Function parameters must be unique",
+                            self.previous,
                         ));
                     }
                     parameters.push(arg);
                 } else {
-                    return Err(ParseErr::new(next, "Expected argument"));
+                    return Err(Diagnostic::new(next, "Expected argument", self.previous));
                 }
                 if *self.peek() != Token::Comma {
                     break;
@@ -321,61 +1215,370 @@ impl<'a> Parser<'a> {
         Ok(Stmt::Undef(parameters))
     }
 
-    fn assign(&mut self) -> Result<Stmt, ParseErr> {
+    fn assign(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance();
+        if matches!(self.peek(), Token::LCurly | Token::LBracket) {
+            return self.unpack();
+        }
+        let name = match self.advance() {
+            Token::Ident(name) => name,
+            token => {
+                return Err(Diagnostic::new(
+                    token,
+                    "Missing function name",
+                    self.previous,
+                ))
+            }
+        };
+        self.consume(Token::Assign, "Missing =")?;
+        let expr = self.expression()?;
+        // let a = 1 in a + 1 is an expression rather than a definition
+        if self.check(&Token::In) || self.binding_follows() {
+            let mut bindings = vec![(name, expr)];
+            if self.check(&Token::Comma) {
+                self.advance();
+                bindings.extend(self.bindings()?);
+            }
+            let expr = self.let_body(bindings)?;
+            return Ok(Stmt::Expr(self.where_clause(expr)?));
+        }
+        Ok(Stmt::Assign(name, self.where_clause(expr)?))
+    }
+
+    // let {x, y} = quadr(1, 1, -2) or let [a, b, ..rest] = xs
+    fn unpack(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.span.start;
+        let pattern = self.unpacking()?;
+        self.consume(Token::Assign, "Missing =")?;
+        let expr = self.expression()?;
+        if self.check(&Token::In) || self.binding_follows() {
+            return Err(Diagnostic::new(
+                self.peek().clone(),
+                "Only names can be bound with let .. in, use match to take values apart",
+                Span::new(start, self.span.end),
+            ));
+        }
+        Ok(Stmt::Unpack(pattern, self.where_clause(expr)?))
+    }
+
+    // let a = 1, b = a + 1 in a + b
+    fn let_expr(&mut self) -> Result<Expr, Diagnostic> {
+        self.consume(Token::Let, "Missing let")?;
+        let bindings = self.bindings()?;
+        self.let_body(bindings)
+    }
+
+    fn let_body(&mut self, bindings: Vec<(String, Expr)>) -> Result<Expr, Diagnostic> {
+        self.consume(Token::In, "Missing in after let bindings")?;
+        let body = self.expression()?;
+        Ok(Expr::Let(bindings, Box::new(body)))
+    }
+
+    // expr where a = 1, b = 2
+    fn where_clause(&mut self, expr: Expr) -> Result<Expr, Diagnostic> {
+        if !self.check(&Token::Where) {
+            return Ok(expr);
+        }
+        self.advance();
+        Ok(Expr::Let(self.bindings()?, Box::new(expr)))
+    }
+
+    // NAME = EXPR separated by commas
+    fn bindings(&mut self) -> Result<Vec<(String, Expr)>, Diagnostic> {
+        let mut bindings = vec![];
+        loop {
+            let name = match self.advance() {
+                Token::Ident(name) => name,
+                token => return Err(Diagnostic::new(token, "Expected name", self.previous)),
+            };
+            self.consume(Token::Assign, "Missing =")?;
+            bindings.push((name, self.expression()?));
+            if !self.binding_follows() {
+                break;
+            }
+            self.advance();
+        }
+        Ok(bindings)
+    }
+
+    // Whether a comma is followed by another binding, otherwise it belongs to the surrounding
+    // list or arguments as in [a where a = 1, 2]
+    fn binding_follows(&self) -> bool {
+        if *self.peek() != Token::Comma {
+            return false;
+        }
+        let mut ahead = self.tokens.clone();
+        matches!(ahead.next(), Some((Token::Ident(_), _)))
+            && matches!(ahead.next(), Some((Token::Assign, _)))
+    }
+
+    fn config(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance();
         let name = match self.advance() {
             Token::Ident(name) => name,
-            token => return Err(ParseErr::new(token, "Missing function name")),
+            token => {
+                return Err(Diagnostic::new(
+                    token,
+                    "Missing setting name",
+                    self.previous,
+                ))
+            }
         };
-        self.consume(Token::Assign, "Expected =")?;
+        self.consume(Token::Assign, "Missing =")?;
+        let expr = self.expression()?;
+        Ok(Stmt::Config(name, self.where_clause(expr)?))
+    }
+
+    // simplify(EXPR)
+    fn simplify(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance();
+        self.consume(Token::LParen, "Missing opening parentheses")?;
         let expr = self.expression()?;
-        Ok(Stmt::Assign(name, expr))
+        self.consume(Token::RParen, "Missing closing parentheses")?;
+        Ok(Stmt::Simplify(expr))
     }
 
-    fn expression(&mut self) -> Result<Expr, ParseErr> {
+    fn expression(&mut self) -> Result<Expr, Diagnostic> {
         match self.peek() {
             Token::Pipe => self.callable(),
-            _ => self.if_expr(),
+            Token::Let => self.let_expr(),
+            _ => self.pipeline(),
+        }
+    }
+
+    // [EXPR] |> f(a, b) is f([EXPR], a, b), and [EXPR] |> f is f([EXPR])
+    fn pipeline(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
+        let mut expr = self.conversion()?;
+        while self.check(&Token::Pipeline) {
+            self.advance();
+            // A built-in on its own is called rather than made into a function value
+            if let Token::Ident(name) = self.peek() {
+                let mut ahead = self.tokens.clone();
+                if let Some(func) =
+                    builtin(name).filter(|_| !matches!(ahead.next(), Some((Token::LParen, _))))
+                {
+                    self.advance();
+                    expr = self.spanned(start, Expr::Func(func, vec![expr]));
+                    continue;
+                }
+            }
+            expr = match self.call()? {
+                Expr::Func(func, args) => {
+                    Expr::Func(func, [expr].into_iter().chain(args).collect())
+                }
+                Expr::Call(callee, args) => {
+                    Expr::Call(callee, [expr].into_iter().chain(args).collect())
+                }
+                callee => Expr::Call(Box::new(callee), vec![expr]),
+            };
+            expr = self.spanned(start, expr);
+        }
+        Ok(expr)
+    }
+
+    fn conversion(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.if_expr()?;
+        while *self.peek() == Token::To && !self.at_end() {
+            self.advance();
+            expr = Expr::Convert(Box::new(expr), self.unit(true)?);
+        }
+        Ok(expr)
+    }
+
+    // Unit symbols separated by * or /, each with an optional integer power, eg. kg*m/s^2.
+    // After to every name is a unit, otherwise only built-in ones carry a unit on
+    fn unit(&mut self, target: bool) -> Result<Vec<(String, i32)>, Diagnostic> {
+        let mut unit = vec![];
+        let mut sign = 1;
+        loop {
+            let name = match self.advance() {
+                Token::Ident(name) => name,
+                token => return Err(Diagnostic::new(token, "Expected unit", self.previous)),
+            };
+            let mut power = 1;
+            if matches!(self.peek(), Token::BitXor | Token::Pow) && !self.at_end() {
+                self.advance();
+                let negative = *self.peek() == Token::Minus;
+                if negative {
+                    self.advance();
+                }
+                power = match self.advance() {
+                    Token::Int(int) => i32::try_from(int).map_err(|_| {
+                        Diagnostic::new(Token::Int(int), "Unit power too large", self.previous)
+                    })?,
+                    token => {
+                        return Err(Diagnostic::new(token, "Expected unit power", self.previous))
+                    }
+                };
+                if negative {
+                    power = -power;
+                }
+            }
+            unit.push((name, sign * power));
+            // Only carry on if another unit follows so that `5 km / 2 h` and `5 m * d` are
+            // operations. Variables holding quantities give the same result either way
+            let unit = |name: &str| target || units::Unit::builtin(name).is_some();
+            if !matches!(self.peek(), Token::Mult | Token::Div)
+                || !matches!(self.tokens.peek(), Some((Token::Ident(name), _)) if unit(name))
+            {
+                break;
+            }
+            sign = if self.advance() == Token::Div { -1 } else { 1 };
         }
+        Ok(unit)
     }
 
-    fn callable(&mut self) -> Result<Expr, ParseErr> {
+    // Number literals directly followed by a unit become quantities
+    fn quantity(&mut self, expr: Expr) -> Result<Expr, Diagnostic> {
+        if matches!(self.peek(), Token::Ident(_)) {
+            Ok(Expr::Quantity(Box::new(expr), self.unit(false)?))
+        } else {
+            Ok(expr)
+        }
+    }
+
+    fn callable(&mut self) -> Result<Expr, Diagnostic> {
         self.consume(Token::Pipe, "Missing opening pipe")?;
-        let mut parameters = Vec::new();
-        if *self.peek() != Token::Pipe && !self.at_end() {
+        let parameters = self.parameters(&Token::Pipe)?;
+        self.consume(Token::Pipe, "Missing closing pipe")?;
+        // A where clause after the body can use the parameters
+        let expr = self.expression()?;
+        Ok(Expr::Fun(parameters, Box::new(self.where_clause(expr)?)))
+    }
+
+    // Unique names separated by commas up to the closing token, _ ignores an argument and
+    // lists or tuples are taken apart, eg. |{x, y}, [h, ..t]|
+    fn parameters(&mut self, close: &Token) -> Result<Vec<Pattern>, Diagnostic> {
+        let mut parameters: Vec<Pattern> = Vec::new();
+        if self.peek() != close && !self.at_end() {
             loop {
-                let next = self.advance();
-                match next {
-                    Token::Ident(arg) => {
-                        if parameters.contains(&arg) {
-                            return Err(ParseErr::new(
-                                Token::Ident(arg),
-                                "Function parameters must be unique",
-                            ));
+                let start = self.span.start;
+                let parameter = match self.peek() {
+                    Token::LCurly | Token::LBracket => self.unpacking()?,
+                    _ => match self.advance() {
+                        Token::Ident(arg) => Pattern::Bind(arg),
+                        Token::UnderScore => Pattern::Wildcard,
+                        next => {
+                            return Err(Diagnostic::new(next, "Expected argument", self.previous))
                         }
-                        parameters.push(arg);
-                    }
-                    Token::UnderScore => parameters.push("_".to_string()),
-                    _ => return Err(ParseErr::new(next, "Expected argument")),
+                    },
                 };
+                let names = parameters
+                    .iter()
+                    .flat_map(Pattern::names)
+                    .collect::<Vec<_>>();
+                if let Some(name) = parameter
+                    .names()
+                    .into_iter()
+                    .find(|name| names.contains(name))
+                {
+                    return Err(Diagnostic::new(
+                        Token::Ident(name.to_string()),
+                        "Function parameters must be unique",
+                        Span::new(start, self.previous.end),
+                    ));
+                }
+                parameters.push(parameter);
                 if *self.peek() != Token::Comma {
                     break;
                 }
                 self.advance();
             }
         }
-        self.consume(Token::Pipe, "Missing closing pipe")?;
-        let expr = Box::new(self.expression()?);
-        Ok(Expr::Fun(parameters, expr))
+        Ok(parameters)
+    }
+
+    // fn NAME(PARAMS) = BODY, further definitions separated by commas can call each other
+    fn definitions(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance();
+        let mut definitions = vec![];
+        loop {
+            let name = match self.advance() {
+                // Calls to these would still go to the built-in
+                Token::Ident(name) if FNS.contains(&name.as_str()) => {
+                    return Err(Diagnostic::new(
+                        Token::Ident(name),
+                        "Built-in functions can't be redefined",
+                        self.previous,
+                    ))
+                }
+                Token::Ident(name) => name,
+                token => {
+                    return Err(Diagnostic::new(
+                        token,
+                        "Missing function name",
+                        self.previous,
+                    ))
+                }
+            };
+            self.consume(Token::LParen, "Missing opening parentheses")?;
+            let parameters = self.parameters(&Token::RParen)?;
+            self.consume(Token::RParen, "Missing closing parentheses")?;
+            self.consume(Token::Assign, "Missing =")?;
+            let body = self.expression()?;
+            definitions.push(Definition {
+                name,
+                parameters,
+                body: self.where_clause(body)?,
+            });
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        Ok(Stmt::Fn(definitions))
+    }
+
+    // NAME: EXPR separated by commas up to the closing brace
+    fn fields(&mut self) -> Result<Vec<(String, Expr)>, Diagnostic> {
+        let mut fields: Vec<(String, Expr)> = vec![];
+        loop {
+            let name = match self.advance() {
+                Token::Ident(name) => name,
+                token => return Err(Diagnostic::new(token, "Expected field name", self.previous)),
+            };
+            if fields.iter().any(|(field, _)| *field == name) {
+                return Err(Diagnostic::new(
+                    Token::Ident(name),
+                    "Record fields must be unique",
+                    self.previous,
+                ));
+            }
+            self.consume(Token::Colon, "Missing : after field name")?;
+            // The error was recorded if there is no expression, so nil is never evaluated
+            fields.push((name, self.element().unwrap_or(Expr::Nil)));
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume(Token::RCurly, "Missing closing brace")?;
+        Ok(fields)
+    }
+
+    // {..RECORD, NAME: EXPR}, the opening brace has already been consumed
+    fn update(&mut self) -> Result<Expr, Diagnostic> {
+        self.consume(Token::Dot, "Missing ..")?;
+        self.consume(Token::Dot, "Missing ..")?;
+        let record = self.expression()?;
+        let fields = if self.check(&Token::Comma) {
+            self.advance();
+            self.fields()?
+        } else {
+            self.consume(Token::RCurly, "Missing closing brace")?;
+            vec![]
+        };
+        Ok(Expr::Update(Box::new(record), fields))
     }
 
-    fn if_expr(&mut self) -> Result<Expr, ParseErr> {
+    fn if_expr(&mut self) -> Result<Expr, Diagnostic> {
         if *self.peek() == Token::If {
             self.advance();
             let cond = Box::new(self.expression()?);
-            self.consume(Token::Then, "Expected then after condition")?;
+            self.consume(Token::Then, "Missing then after condition")?;
             let then = Box::new(self.expression()?);
-            self.consume(Token::Else, "Expected else after then body")?;
+            self.consume(Token::Else, "Missing else after then body")?;
             let else_expr = Box::new(self.expression()?);
             Ok(Expr::If(cond, then, else_expr))
         } else {
@@ -383,48 +1586,56 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn or(&mut self) -> Result<Expr, ParseErr> {
+    fn or(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
         let mut expr = self.and()?;
         while *self.peek() == Token::Or && !self.at_end() {
             self.advance();
             let rhs = Box::new(self.and()?);
-            expr = Expr::Binary(Box::new(expr), Token::Or, rhs);
+            expr = self.spanned(start, Expr::Binary(Box::new(expr), Token::Or, rhs));
         }
         Ok(expr)
     }
 
-    fn and(&mut self) -> Result<Expr, ParseErr> {
+    fn and(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
         let mut expr = self.bit_binary()?;
         while *self.peek() == Token::And && !self.at_end() {
             self.advance();
             let rhs = Box::new(self.bit_binary()?);
-            expr = Expr::Binary(Box::new(expr), Token::And, rhs);
+            expr = self.spanned(start, Expr::Binary(Box::new(expr), Token::And, rhs));
         }
         Ok(expr)
     }
 
-    fn bit_binary(&mut self) -> Result<Expr, ParseErr> {
+    fn bit_binary(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
         let mut expr = self.equality()?;
         while matches!(*self.peek(), Token::Pipe | Token::BitXor | Token::BitAnd) && !self.at_end()
         {
             let operator = self.advance();
             let right = self.equality()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right))
+            expr = self.spanned(
+                start,
+                Expr::Binary(Box::new(expr), operator, Box::new(right)),
+            );
         }
         Ok(expr)
     }
 
-    fn equality(&mut self) -> Result<Expr, ParseErr> {
+    fn equality(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
         let mut expr = self.comparison()?;
         while matches!(*self.peek(), Token::Eq | Token::Ne) && !self.at_end() {
             let operator = self.advance();
             let right = Box::new(self.comparison()?);
-            expr = Expr::Binary(Box::new(expr), operator, right);
+            expr = self.spanned(start, Expr::Binary(Box::new(expr), operator, right));
         }
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, ParseErr> {
+    fn comparison(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
         let mut expr = self.bit_shift()?;
         while matches!(
             *self.peek(),
@@ -433,95 +1644,393 @@ impl<'a> Parser<'a> {
         {
             let operator = self.advance();
             let right = Box::new(self.bit_shift()?);
-            expr = Expr::Binary(Box::new(expr), operator, right);
+            expr = self.spanned(start, Expr::Binary(Box::new(expr), operator, right));
         }
         Ok(expr)
     }
 
-    fn bit_shift(&mut self) -> Result<Expr, ParseErr> {
+    fn bit_shift(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
         let mut expr = self.term()?;
         while matches!(*self.peek(), Token::Shr | Token::Shl) && !self.at_end() {
             let operator = self.advance();
             let right = self.term()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+            expr = self.spanned(
+                start,
+                Expr::Binary(Box::new(expr), operator, Box::new(right)),
+            );
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
+        let mut expr = self.factor()?;
+        while *self.peek() == Token::Plus || *self.peek() == Token::Minus && !self.at_end() {
+            let operator = self.advance();
+            let right = self.factor()?;
+            expr = self.spanned(
+                start,
+                Expr::Binary(Box::new(expr), operator, Box::new(right)),
+            );
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
+        let mut expr = self.unary()?;
+        while matches!(*self.peek(), Token::Div | Token::Mult | Token::Mod) && !self.at_end() {
+            let operator = self.advance();
+            let right = self.unary()?;
+            expr = self.spanned(
+                start,
+                Expr::Binary(Box::new(expr), operator, Box::new(right)),
+            );
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
+        if matches!(*self.peek(), Token::Minus | Token::Not) {
+            let operator = self.advance();
+            let right = self.unary()?;
+            match right {
+                Expr::Fixed(int, ty) if operator == Token::Minus => Ok(Expr::Fixed(-int, ty)),
+                right => Ok(self.spanned(start, Expr::Unary(Box::new(right), operator))),
+            }
+        } else {
+            self.exponent()
+        }
+    }
+
+    fn exponent(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
+        let mut expr = self.call()?;
+        while *self.peek() == Token::Pow && !self.at_end() {
+            let operator = self.advance();
+            let right = self.call()?;
+            expr = self.spanned(
+                start,
+                Expr::Binary(Box::new(expr), operator, Box::new(right)),
+            );
+        }
+        Ok(expr)
+    }
+
+    fn call(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
+        let mut expr = self.primary()?;
+
+        loop {
+            match self.peek() {
+                Token::LParen => {
+                    self.advance();
+                    let args = self.elements(Token::RParen, "Missing closing parentheses")?;
+                    expr = self.spanned(start, Expr::Call(Box::new(expr), args));
+                }
+                Token::Dot => {
+                    self.advance();
+                    let name = match self.advance() {
+                        Token::Ident(name) => name,
+                        token => {
+                            return Err(Diagnostic::new(
+                                token,
+                                "Expected field name",
+                                self.previous,
+                            ))
+                        }
+                    };
+                    // Built-ins can be called like methods, eg. xs.map(sq) is map(xs, sq).
+                    // Other names are fields, which can hold functions to call
+                    if let Some(func) = builtin(&name).filter(|_| self.check(&Token::LParen)) {
+                        self.advance();
+                        let args = self.elements(Token::RParen, "Missing closing parentheses")?;
+                        let args = [expr].into_iter().chain(args).collect();
+                        expr = self.spanned(start, Expr::Func(func, args));
+                        continue;
+                    }
+                    expr = self.spanned(start, Expr::Field(Box::new(expr), name));
+                }
+                Token::LBracket => {
+                    self.advance();
+                    expr = self.index(expr)?;
+                    expr = self.spanned(start, expr);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    // xs[INDEX] or xs[START:END:STEP], the opening bracket has already been consumed
+    fn index(&mut self, expr: Expr) -> Result<Expr, Diagnostic> {
+        let start = self.bound()?;
+        if let Some(index) = start.as_ref().filter(|_| self.check(&Token::RBracket)) {
+            self.advance();
+            return Ok(Expr::Index(Box::new(expr), index.clone()));
+        }
+        self.consume(Token::Colon, "Missing : or closing bracket")?;
+        let end = self.bound()?;
+        let step = if self.check(&Token::Colon) {
+            self.advance();
+            self.bound()?
+        } else {
+            None
+        };
+        self.consume(Token::RBracket, "Missing closing bracket")?;
+        Ok(Expr::Slice(Box::new(expr), [start, end, step]))
+    }
+
+    // A part of a slice, unless it's been left out
+    fn bound(&mut self) -> Result<Option<Box<Expr>>, Diagnostic> {
+        if matches!(self.peek(), Token::Colon | Token::RBracket) {
+            Ok(None)
+        } else {
+            self.expression().map(|bound| Some(Box::new(bound)))
         }
-        Ok(expr)
     }
 
-    fn term(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = self.factor()?;
-        while *self.peek() == Token::Plus || *self.peek() == Token::Minus && !self.at_end() {
-            let operator = self.advance();
-            let right = self.factor()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+    // match [EXPR] { [PATTERN] if [GUARD] => [EXPR], .. } where arms are separated by commas or
+    // newlines and the guard is optional
+    fn match_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let keyword = self.span;
+        self.advance();
+        let subject = self.expression()?;
+        self.consume(Token::LCurly, "Missing { after match value")?;
+        let mut arms = vec![];
+        let mut spans = vec![];
+        while !self.check(&Token::RCurly) {
+            let start = self.span.start;
+            let pattern = self.pattern()?;
+            let span = Span::new(start, self.previous.end);
+            distinct_names(&pattern, span)?;
+            spans.push(span);
+            let guard = if self.check(&Token::If) {
+                self.advance();
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            self.consume(Token::Arrow, "Missing => after pattern")?;
+            let body = self.expression()?;
+            arms.push(Arm {
+                pattern,
+                guard,
+                body,
+            });
+            match self.peek() {
+                Token::Comma | Token::Newline => {
+                    while matches!(self.peek(), Token::Comma | Token::Newline) {
+                        self.advance();
+                    }
+                }
+                _ => break,
+            }
         }
-        Ok(expr)
+        self.consume(Token::RCurly, "Missing closing brace after match arms")?;
+        if arms.is_empty() {
+            return Err(Diagnostic::new(
+                Token::RCurly,
+                "Expected at least one match arm",
+                self.previous,
+            ));
+        }
+        // Guarded arms might not match, so they don't count
+        let unguarded = arms
+            .iter()
+            .filter(|arm| arm.guard.is_none())
+            .map(|arm| &arm.pattern);
+        if let Some(i) = arms
+            .iter()
+            .position(|arm| arm.guard.is_none() && arm.pattern.irrefutable())
+            .filter(|i| i + 1 < arms.len())
+        {
+            self.warnings.push((
+                "Unreachable match arm, the one before it matches everything",
+                spans[i + 1],
+            ));
+        } else if !exhaustive(&unguarded.collect::<Vec<_>>()) {
+            self.warnings.push((
+                "Match might not cover every value, add a _ arm for the rest",
+                keyword,
+            ));
+        }
+        Ok(Expr::Match(Box::new(subject), arms))
     }
 
-    fn factor(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = self.unary()?;
-        while matches!(*self.peek(), Token::Div | Token::Mult | Token::Mod) && !self.at_end() {
-            let operator = self.advance();
-            let right = self.unary()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+    fn pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        let token = self.peek().clone();
+        match token {
+            Token::UnderScore => {
+                self.advance();
+                Ok(Pattern::Wildcard)
+            }
+            Token::Ident(name) => {
+                self.advance();
+                Ok(Pattern::Bind(name))
+            }
+            Token::LBracket => {
+                self.advance();
+                let patterns = self.patterns(Token::RBracket, "Missing closing bracket")?;
+                Ok(Pattern::List(patterns))
+            }
+            Token::LCurly => {
+                self.advance();
+                let patterns = self.patterns(Token::RCurly, "Missing closing brace")?;
+                Ok(Pattern::Tuple(patterns))
+            }
+            Token::Minus => {
+                self.advance();
+                let expr = self.literal()?;
+                Ok(Pattern::Literal(Expr::Unary(Box::new(expr), Token::Minus)))
+            }
+            _ => Ok(Pattern::Literal(self.literal()?)),
         }
-        Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, ParseErr> {
-        if matches!(*self.peek(), Token::Minus | Token::Not) {
-            let operator = self.advance();
-            let right = self.unary()?;
-            Ok(Expr::Unary(Box::new(right), operator))
-        } else {
-            self.exponent()
+    fn literal(&mut self) -> Result<Expr, Diagnostic> {
+        match self.peek() {
+            Token::Int(_)
+            | Token::BigInt(_)
+            | Token::Fixed(_, _)
+            | Token::Float(_)
+            | Token::Decimal(_)
+            | Token::Imaginary(_)
+            | Token::Time(_)
+            | Token::String(_)
+            | Token::True
+            | Token::False
+            | Token::Nil => self.primary(),
+            token => Err(Diagnostic::new(
+                token.clone(),
+                "Expected pattern",
+                self.span,
+            )),
         }
     }
 
-    fn exponent(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = self.call()?;
-        while *self.peek() == Token::Pow && !self.at_end() {
-            let operator = self.advance();
-            let right = self.call()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+    // A pattern that has to fit every value it's given, from let or a parameter, so it only
+    // takes apart lists and tuples, eg. {x, y} or [a, b, ..rest]
+    fn unpacking(&mut self) -> Result<Pattern, Diagnostic> {
+        let (token, start) = (self.peek().clone(), self.span.start);
+        let pattern = self.pattern()?;
+        let span = Span::new(start, self.previous.end);
+        if pattern.literals() {
+            return Err(Diagnostic::new(
+                token,
+                "Only names, _, lists and tuples can be unpacked, use match to compare values",
+                span,
+            ));
         }
-        Ok(expr)
+        distinct_names(&pattern, span)?;
+        Ok(pattern)
     }
 
-    fn call(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = self.primary()?;
-
-        while *self.peek() == Token::LParen && !self.at_end() {
-            self.advance();
-            let mut args = vec![];
-            if !self.check(&Token::RParen) {
-                loop {
-                    args.push(self.expression()?);
-                    if !self.check(&Token::Comma) {
-                        break;
+    // Patterns separated by commas up to the closing token, one of which can be a ..rest
+    fn patterns(&mut self, close: Token, msg: &'static str) -> Result<Vec<Pattern>, Diagnostic> {
+        let mut patterns = vec![];
+        while !self.check(&close) {
+            if self.check(&Token::Dot) {
+                let start = self.span.start;
+                self.advance();
+                self.consume(Token::Dot, "Missing ..")?;
+                let name = match self.peek().clone() {
+                    Token::Ident(name) => {
+                        self.advance();
+                        Some(name)
                     }
-                    self.advance();
+                    _ => None,
+                };
+                if patterns
+                    .iter()
+                    .any(|pattern| matches!(pattern, Pattern::Rest(_)))
+                {
+                    return Err(Diagnostic::new(
+                        Token::Dot,
+                        "Only one .. is allowed in a pattern",
+                        Span::new(start, self.previous.end),
+                    ));
                 }
+                patterns.push(Pattern::Rest(name));
+            } else {
+                patterns.push(self.pattern()?);
             }
-            self.consume(Token::RParen, "Missing closing parentheses")?;
-            expr = Expr::Call(Box::new(expr), args);
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance();
         }
+        self.consume(close, msg)?;
+        Ok(patterns)
+    }
 
-        Ok(expr)
+    // The expressions of an interpolated string are parsed on their own, from the tokens that
+    // were read for each of them
+    fn part(&mut self, fragment: Fragment) -> Result<Part, Diagnostic> {
+        let (tokens, spec) = match fragment {
+            Fragment::Text(text) => return Ok(Part::Text(text)),
+            Fragment::Code(tokens, spec) => (tokens, spec),
+        };
+        let mut parser = Parser::from_tokens(tokens);
+        parser.spans = self.spans.take();
+        let expr = parser.expression();
+        self.spans = parser.spans.take();
+        let expr = expr?;
+        if !parser.at_end() {
+            return Err(Diagnostic::new(
+                parser.peek().clone(),
+                "Expected } after expression in string",
+                parser.span,
+            ));
+        }
+        for err in parser.errors {
+            self.record(err);
+        }
+        self.warnings.extend(parser.warnings);
+        Ok(Part::Expr(expr, spec))
     }
 
-    fn primary(&mut self) -> Result<Expr, ParseErr> {
+    fn primary(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.span.start;
         match self.peek() {
             Token::Float(float) => {
-                let res = Ok(Expr::Float(*float));
+                let res = Expr::Float(*float);
                 self.advance();
-                res
+                self.quantity(res)
             }
             Token::Int(int) => {
-                let res = Ok(Expr::Int(*int));
+                let res = Expr::Int(*int);
+                self.advance();
+                self.quantity(res)
+            }
+            Token::BigInt(int) => {
+                let res = Expr::BigInt(int.clone());
+                self.advance();
+                self.quantity(res)
+            }
+            Token::Fixed(int, ty) => {
+                let res = Ok(Expr::Fixed(int.clone(), *ty));
+                self.advance();
+                res
+            }
+            Token::Imaginary(im) => {
+                let res = Ok(Expr::Imaginary(*im));
                 self.advance();
                 res
             }
+            Token::Time(literal) => {
+                let res = Ok(Expr::Time(*literal));
+                self.advance();
+                res
+            }
+            Token::Decimal(decimal) => {
+                let res = Expr::Decimal(*decimal);
+                self.advance();
+                self.quantity(res)
+            }
             Token::Nil => {
                 let res = Ok(Expr::Nil);
                 self.advance();
@@ -537,6 +2046,16 @@ impl<'a> Parser<'a> {
                 self.advance();
                 res
             }
+            Token::Match => self.match_expr(),
+            Token::Interpolated(fragments) => {
+                let fragments = fragments.clone();
+                self.advance();
+                let parts = fragments
+                    .into_iter()
+                    .map(|fragment| self.part(fragment))
+                    .collect::<Result<_, _>>()?;
+                Ok(Expr::Interpolated(parts))
+            }
             Token::True => {
                 self.advance();
                 Ok(Expr::Bool(true))
@@ -547,101 +2066,91 @@ impl<'a> Parser<'a> {
             }
             Token::LParen => {
                 self.advance();
-                let expr = Box::new(self.expression()?);
+                let expr = self.element();
                 self.consume(Token::RParen, "Missing closing parentheses")?;
-                Ok(Expr::Grouping(expr))
+                // The error was recorded if there is no expression, so nil is never evaluated
+                Ok(Expr::Grouping(Box::new(expr.unwrap_or(Expr::Nil))))
             }
             Token::LBracket => {
                 self.advance();
-                let mut elems = vec![];
-                if !self.check(&Token::RBracket) {
-                    loop {
-                        elems.push(self.expression()?);
-                        if !self.check(&Token::Comma) {
-                            break;
-                        }
-                        self.advance();
-                    }
-                }
-                self.consume(Token::RBracket, "Missing closing bracket")?;
+                let elems = self.elements(Token::RBracket, "Missing closing bracket")?;
                 Ok(Expr::List(elems))
             }
             Token::LCurly => {
                 self.advance();
-                let mut elems = vec![];
-                if !self.check(&Token::RCurly) {
-                    loop {
-                        elems.push(self.expression()?);
-                        if !self.check(&Token::Comma) {
-                            break;
-                        }
-                        self.advance();
-                    }
+                // Blocks start with a statement or have a separator after the first expression,
+                // anything else is a tuple
+                if matches!(
+                    self.peek(),
+                    Token::Let | Token::Undef | Token::Config | Token::Simplify
+                ) {
+                    return self.block(vec![]);
+                }
+                // Records start with a field name and a colon, updates with ..
+                if matches!(self.peek(), Token::Ident(_))
+                    && matches!(self.tokens.peek(), Some((Token::Colon, _)))
+                {
+                    return Ok(Expr::Record(self.fields()?));
+                }
+                if self.check(&Token::Dot) {
+                    return self.update();
+                }
+                if self.check(&Token::RCurly) {
+                    self.advance();
+                    return Ok(Expr::Tuple(vec![]));
+                }
+                let first = self.element();
+                if let Some(first) = first.clone().filter(|_| self.separator()) {
+                    return self.block(vec![Stmt::Expr(first)]);
+                }
+                let mut elems = first.into_iter().collect::<Vec<_>>();
+                if self.check(&Token::Comma) {
+                    self.advance();
+                    elems.extend(self.elements(Token::RCurly, "Missing closing bracket")?);
+                } else {
+                    self.consume(Token::RCurly, "Missing closing bracket")?;
                 }
-                self.consume(Token::RCurly, "Missing closing bracket")?;
                 Ok(Expr::Tuple(elems))
             }
-            Token::Ident(func) => {
-                let func = func.to_owned();
+            Token::Ident(name) => {
+                let name = name.to_owned();
                 self.advance();
-                let func = match func.as_str() {
-                    ABS => Func::Abs,
-                    SIN => Func::Sin,
-                    SINH => Func::Sinh,
-                    ASIN => Func::Asin,
-                    ASINH => Func::Asinh,
-                    COS => Func::Cos,
-                    COSH => Func::Cosh,
-                    ACOS => Func::Acos,
-                    ACOSH => Func::Acosh,
-                    TAN => Func::Tan,
-                    TANH => Func::Tanh,
-                    ATAN => Func::Atan,
-                    ATANH => Func::Atanh,
-                    LN => Func::Ln,
-                    LOG => Func::Log,
-                    DEGS => Func::Degs,
-                    RADS => Func::Rads,
-                    SQ => Func::Sq,
-                    SQRT => Func::Sqrt,
-                    CUBE => Func::Cube,
-                    CBRT => Func::Cbrt,
-                    ROUND => Func::Round,
-                    CEIL => Func::Ceil,
-                    FLOOR => Func::Floor,
-                    EXP => Func::Exp,
-                    EXP2 => Func::Exp2,
-                    FRACT => Func::Fract,
-                    RECIP => Func::Recip,
-                    MAP => Func::Map,
-                    SUM => Func::Sum,
-                    FOLD => Func::Fold,
-                    FILTER => Func::Filter,
-                    EVEN => Func::Even,
-                    ODD => Func::Odd,
-                    FACTORIAL => Func::Fact,
-                    RANGE => Func::Range,
-                    ELEM => Func::Elem,
-                    MIN => Func::Min,
-                    MAX => Func::Max,
-                    QUADR => Func::Quadr,
-                    _ => return Ok(Expr::Var(func)),
+                let Some(func) = builtin(&name) else {
+                    return Ok(Expr::Var(name));
                 };
-                self.consume(Token::LParen, "Missing opening parentheses")?;
-                let mut args = vec![];
-                if !self.check(&Token::RParen) {
-                    loop {
-                        args.push(self.expression()?);
-                        if !self.check(&Token::Comma) {
-                            break;
-                        }
-                        self.advance();
-                    }
+                // Without arguments it's a variable, which is the built-in as a function value
+                // unless a parameter or variable has the name, eg. filter(xs, odd) or |len| len.
+                // An argument without parentheses is still a mistake, eg. sin 5
+                if !matches!(
+                    self.peek(),
+                    Token::LParen
+                        | Token::Int(_)
+                        | Token::BigInt(_)
+                        | Token::Fixed(_, _)
+                        | Token::Float(_)
+                        | Token::Decimal(_)
+                        | Token::Imaginary(_)
+                        | Token::Time(_)
+                        | Token::String(_)
+                        | Token::Ident(_)
+                ) {
+                    return Ok(self.spanned(start, Expr::Var(name)));
                 }
-                self.consume(Token::RParen, "Missing closing parentheses")?;
-                Ok(Expr::Func(func, args))
+                self.consume(Token::LParen, "Missing opening parentheses")
+                    .map_err(|err| {
+                        err.note(format!(
+                            "built-in functions take their arguments in parentheses, eg. {}(x)",
+                            func
+                        ))
+                    })?;
+                let args = self.elements(Token::RParen, "Missing closing parentheses")?;
+                Ok(self.spanned(start, Expr::Func(func, args)))
             }
-            _ => Err(ParseErr::new(self.peek().clone(), "Expected expression")),
+            _ => Err(Diagnostic::new(
+                self.peek().clone(),
+                "Expected expression",
+                self.span,
+            )),
         }
     }
 }
@@ -650,7 +2159,28 @@ impl Error for ParseErr {}
 
 impl Display for ParseErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}, got: {}", self.msg, self.token)
+        write!(
+            f,
+            "{}",
+            self.0
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.expected {
+            Some(expected) => write!(
+                f,
+                "{}, expected: {}, got: {}",
+                self.msg, expected, self.found
+            ),
+            None => write!(f, "{}, got: {}", self.msg, self.found),
+        }
     }
 }
 
@@ -694,6 +2224,7 @@ impl Display for Func {
                 Func::Exp2 => EXP2,
                 Func::Fract => FRACT,
                 Func::Recip => RECIP,
+                Func::Float => FLOAT,
                 Func::Map => MAP,
                 Func::Sum => SUM,
                 Func::Fold => FOLD,
@@ -706,6 +2237,39 @@ impl Display for Func {
                 Func::Min => MIN,
                 Func::Max => MAX,
                 Func::Quadr => QUADR,
+                Func::Re => RE,
+                Func::Im => IM,
+                Func::Conj => CONJ,
+                Func::Arg => ARG,
+                Func::Now => NOW,
+                Func::Today => TODAY,
+                Func::Weekday => WEEKDAY,
+                Func::AddBusinessDays => ADD_BDAYS,
+                Func::Strftime => STRFTIME,
+                Func::Tz => TZ,
+                Func::Cast => CAST,
+                Func::Rotl => ROTL,
+                Func::Rotr => ROTR,
+                Func::Popcount => POPCOUNT,
+                Func::Clz => CLZ,
+                Func::Keys => KEYS,
+                Func::Values => VALUES,
+                Func::Entries => ENTRIES,
+                Func::Set => SET,
+                Func::Len => LEN,
+                Func::Upper => UPPER,
+                Func::Lower => LOWER,
+                Func::Split => SPLIT,
+                Func::Join => JOIN,
+                Func::Trim => TRIM,
+                Func::Replace => REPLACE,
+                Func::Contains => CONTAINS,
+                Func::StartsWith => STARTSWITH,
+                Func::Substr => SUBSTR,
+                Func::Chars => CHARS,
+                Func::ToStr => TO_STR,
+                Func::ParseNum => PARSE_NUM,
+                Func::Format => FORMAT,
             }
         )
     }
@@ -718,9 +2282,8 @@ mod tests {
     use super::*;
 
     fn check(str: &str, expected: Expr) {
-        let mut tokenizer = Tokenizer::new(str.chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        let mut parser = Parser::new(tokenizer, current);
+        let tokenizer = Tokenizer::new(str);
+        let mut parser = Parser::new(tokenizer).unwrap();
         let expr = parser.parse().unwrap();
 
         assert_eq!(expr, Stmt::Expr(expected));
@@ -775,11 +2338,15 @@ mod tests {
 
     #[test]
     fn test_missing_closing_paren() {
-        let mut tokenizer = Tokenizer::new("-(5".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
+        let tokenizer = Tokenizer::new("-(5");
         assert_eq!(
-            Err(ParseErr::new(Token::RParen, "Missing closing parentheses")),
-            Parser::new(tokenizer, current).parse()
+            Err(ParseErr(vec![Diagnostic::new(
+                Token::Eof,
+                "Missing closing parentheses",
+                Span::new(3, 4)
+            )
+            .expected(Token::RParen)])),
+            Parser::new(tokenizer).unwrap().parse()
         );
     }
 
@@ -788,7 +2355,10 @@ mod tests {
         let expected = Stmt::Assign(
             "foo".to_string(),
             Expr::Fun(
-                vec!["x".to_string(), "y".to_string()],
+                vec![
+                    Pattern::Bind("x".to_string()),
+                    Pattern::Bind("y".to_string()),
+                ],
                 Box::new(Expr::Binary(
                     Box::new(Expr::Var("x".to_string())),
                     Token::Plus,
@@ -797,9 +2367,8 @@ mod tests {
             ),
         );
 
-        let mut tokenizer = Tokenizer::new("let foo = |x, y| x + y".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        let stmt = Parser::new(tokenizer, current).parse().unwrap();
+        let tokenizer = Tokenizer::new("let foo = |x, y| x + y");
+        let stmt = Parser::new(tokenizer).unwrap().parse().unwrap();
         assert_eq!(stmt, expected);
     }
 
@@ -808,15 +2377,13 @@ mod tests {
         let expected = Stmt::Assign(
             "foo".to_string(),
             Expr::Fun(
-                vec!["_".to_string(), "_".to_string()],
+                vec![Pattern::Wildcard, Pattern::Wildcard],
                 Box::new(Expr::Float(200.2)),
             ),
         );
 
-        let mut tokenizer =
-            Tokenizer::new("let foo = |_, _foobar| 200.2".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        let stmt = Parser::new(tokenizer, current).parse().unwrap();
+        let tokenizer = Tokenizer::new("let foo = |_, _foobar| 200.2");
+        let stmt = Parser::new(tokenizer).unwrap().parse().unwrap();
         assert_eq!(stmt, expected);
     }
 
@@ -825,7 +2392,7 @@ mod tests {
         let expected = Stmt::Assign(
             "foo".to_string(),
             Expr::Fun(
-                vec!["y".to_string()],
+                vec![Pattern::Bind("y".to_string())],
                 Box::new(Expr::Binary(
                     Box::new(Expr::Var("y".to_string())),
                     Token::Plus,
@@ -834,31 +2401,29 @@ mod tests {
             ),
         );
 
-        let mut tokenizer = Tokenizer::new("let foo = |y| y+1".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        let stmt = Parser::new(tokenizer, current).parse().unwrap();
+        let tokenizer = Tokenizer::new("let foo = |y| y+1");
+        let stmt = Parser::new(tokenizer).unwrap().parse().unwrap();
         assert_eq!(stmt, expected);
     }
 
     #[test]
     fn test_uniq_params() {
-        let expected = Err(ParseErr::new(
+        let expected = Err(ParseErr(vec![Diagnostic::new(
             Token::Ident("y".to_string()),
             "Function parameters must be unique",
-        ));
+            Span::new(14, 15),
+        )]));
 
-        let mut tokenizer = Tokenizer::new("let foo = |y, y, y, y|".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), expected);
+        let tokenizer = Tokenizer::new("let foo = |y, y, y, y|");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), expected);
     }
 
     #[test]
     fn test_no_params() {
         let expected = Stmt::Assign("foo".to_string(), Expr::Fun(vec![], Box::new(Expr::Int(2))));
 
-        let mut tokenizer = Tokenizer::new("let foo = || 2".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        let stmt = Parser::new(tokenizer, current).parse().unwrap();
+        let tokenizer = Tokenizer::new("let foo = || 2");
+        let stmt = Parser::new(tokenizer).unwrap().parse().unwrap();
         assert_eq!(stmt, expected);
     }
 
@@ -869,18 +2434,16 @@ mod tests {
             Expr::Func(Func::Sq, vec![Expr::Float(2.0)]),
         );
 
-        let mut tokenizer = Tokenizer::new("let foo = sq(2.0)".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), Ok(expected));
+        let tokenizer = Tokenizer::new("let foo = sq(2.0)");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), Ok(expected));
     }
 
     #[test]
     fn integer_base_log() {
         let expected = Stmt::Expr(Expr::Func(Func::Log, vec![Expr::Int(10), Expr::Int(1000)]));
 
-        let mut tokenizer = Tokenizer::new("log(10, 1000)".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), Ok(expected));
+        let tokenizer = Tokenizer::new("log(10, 1000)");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), Ok(expected));
     }
 
     #[test]
@@ -891,9 +2454,8 @@ mod tests {
             Box::new(Expr::Bool(false)),
         ));
 
-        let mut tokenizer = Tokenizer::new("true or false".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), Ok(expected));
+        let tokenizer = Tokenizer::new("true or false");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), Ok(expected));
     }
 
     #[test]
@@ -904,33 +2466,284 @@ mod tests {
             Box::new(Expr::String("this is the answer".to_string())),
         ));
 
-        let mut tokenizer = Tokenizer::new(
-            "if true then 1 else \"this is the answer\""
-                .chars()
-                .peekable(),
-        )
-        .peekable();
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), Ok(expected));
+        let tokenizer = Tokenizer::new("if true then 1 else \"this is the answer\"");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), Ok(expected));
     }
 
     #[test]
     fn test_fact() {
         let expected = Stmt::Expr(Expr::Func(Func::Fact, vec![Expr::Int(5)]));
 
-        let mut tokenizer = Tokenizer::new("factorial(5)".chars().peekable()).peekable();
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), Ok(expected));
+        let tokenizer = Tokenizer::new("factorial(5)");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), Ok(expected));
     }
 
     #[test]
     fn test_tuple() {
         let expected = Stmt::Expr(Expr::Tuple(vec![Expr::Int(2), Expr::Int(3)]));
 
-        let mut tokenizer = Tokenizer::new("{2, 3}".chars().peekable()).peekable();
+        let tokenizer = Tokenizer::new("{2, 3}");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), Ok(expected));
+    }
+
+    #[test]
+    fn test_record() {
+        let var = |name: &str| Expr::Var(name.to_string());
+        check(
+            "{..r, rate: r.rate * 2}.rate",
+            Expr::Field(
+                Box::new(Expr::Update(
+                    Box::new(var("r")),
+                    vec![(
+                        "rate".to_string(),
+                        Expr::Binary(
+                            Box::new(Expr::Field(Box::new(var("r")), "rate".to_string())),
+                            Token::Mult,
+                            Box::new(Expr::Int(2)),
+                        ),
+                    )],
+                )),
+                "rate".to_string(),
+            ),
+        );
+        check(
+            "{name: \"x\"}",
+            Expr::Record(vec![("name".to_string(), Expr::String("x".to_string()))]),
+        );
+        // Still a tuple or a block without the colon
+        check("{a}", Expr::Tuple(vec![var("a")]));
+        let err = |input: &str| Parser::new(Tokenizer::new(input)).unwrap().parse();
+        assert!(err("{a: 1, a: 2}").is_err());
+        assert!(err("r.1").is_err());
+    }
+
+    #[test]
+    fn test_index() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        check(
+            "xs[-1]",
+            Expr::Index(
+                var("xs"),
+                Box::new(Expr::Unary(Box::new(Expr::Int(1)), Token::Minus)),
+            ),
+        );
+        check(
+            "f(x)[1:][::2]",
+            Expr::Slice(
+                Box::new(Expr::Slice(
+                    Box::new(Expr::Call(var("f"), vec![Expr::Var("x".to_string())])),
+                    [Some(Box::new(Expr::Int(1))), None, None],
+                )),
+                [None, None, Some(Box::new(Expr::Int(2)))],
+            ),
+        );
+        assert_eq!(
+            Expr::Slice(var("xs"), [None, Some(var("n")), None]).format(),
+            "xs[:n]"
+        );
+    }
+
+    #[test]
+    fn test_interpolated() {
+        check(
+            "f\"{x + 1:.2} EUR\"",
+            Expr::Interpolated(vec![
+                Part::Expr(
+                    Expr::Binary(
+                        Box::new(Expr::Var("x".to_string())),
+                        Token::Plus,
+                        Box::new(Expr::Int(1)),
+                    ),
+                    Some(".2".to_string()),
+                ),
+                Part::Text(" EUR".to_string()),
+            ]),
+        );
+        // Formats back to something that parses the same
+        for input in ["f\"{ {a: 1}.a} {{\\n\"", "f\"{join([\"a\"], \"}\")}\""] {
+            let expr = Parser::new(Tokenizer::new(input)).unwrap().parse().unwrap();
+            let Stmt::Expr(expr) = expr else { panic!() };
+            let formatted = expr.format();
+            let reparsed = Parser::new(Tokenizer::new(&formatted)).unwrap().parse();
+            assert_eq!(reparsed, Ok(Stmt::Expr(expr)), "{}", formatted);
+        }
+        let err = Parser::new(Tokenizer::new("f\"{1 2}\"")).unwrap().parse();
+        let err = err.unwrap_err().0.remove(0);
+        assert_eq!(
+            (err.msg, err.span),
+            ("Expected } after expression in string", Span::new(5, 6))
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let var = |name: &str| Expr::Var(name.to_string());
+        let bind = |name: &str| Pattern::Bind(name.to_string());
+        check(
+            "match xs {\n  [] => 0\n  [h, ..t] if h > 0 => h,\n  {-1, _} => nil,\n}",
+            Expr::Match(
+                Box::new(var("xs")),
+                vec![
+                    Arm {
+                        pattern: Pattern::List(vec![]),
+                        guard: None,
+                        body: Expr::Int(0),
+                    },
+                    Arm {
+                        pattern: Pattern::List(vec![
+                            bind("h"),
+                            Pattern::Rest(Some("t".to_string())),
+                        ]),
+                        guard: Some(Expr::Binary(
+                            Box::new(var("h")),
+                            Token::Gt,
+                            Box::new(Expr::Int(0)),
+                        )),
+                        body: var("h"),
+                    },
+                    Arm {
+                        pattern: Pattern::Tuple(vec![
+                            Pattern::Literal(Expr::Unary(Box::new(Expr::Int(1)), Token::Minus)),
+                            Pattern::Wildcard,
+                        ]),
+                        guard: None,
+                        body: Expr::Nil,
+                    },
+                ],
+            ),
+        );
+        let input = "match x { [..a, b] => b, \"s\" => 1, _ => 2 }";
+        let expr = Parser::new(Tokenizer::new(input)).unwrap().parse();
+        assert_eq!(expr.map(|stmt| stmt.format()), Ok(input.to_string()));
+
+        let warnings = |input: &str| {
+            let mut parser = Parser::new(Tokenizer::new(input)).unwrap();
+            parser.parse().unwrap();
+            parser
+                .warnings
+                .into_iter()
+                .map(|(msg, _)| msg)
+                .collect::<Vec<_>>()
+        };
+        let unreachable = "Unreachable match arm, the one before it matches everything";
+        let uncovered = "Match might not cover every value, add a _ arm for the rest";
+        assert_eq!(warnings("match x { n => 1, 0 => 2 }"), vec![unreachable]);
+        assert_eq!(
+            warnings("match x { 0 => 1, n if n > 0 => 2 }"),
+            vec![uncovered]
+        );
+        assert_eq!(warnings("match x { [a] => 1, [] => 2 }"), vec![uncovered]);
+        for input in [
+            "match x { true => 1, false => 0 }",
+            "match x { [] => 0, [a] => a, [a, _, ..] => a }",
+            "match x { {} => 0, {..t} => 1 }",
+            "match x { 0 => 1, n if n > 0 => 2, _ => 3 }",
+        ] {
+            assert_eq!(warnings(input), Vec::<&str>::new(), "{}", input);
+        }
+
+        let err = |input: &str| {
+            let err = Parser::new(Tokenizer::new(input)).unwrap().parse();
+            err.unwrap_err().0.remove(0).msg
+        };
+        assert_eq!(
+            err("match x { [..a, ..] => 1 }"),
+            "Only one .. is allowed in a pattern"
+        );
+        assert_eq!(err("match x { 1 + 2 => 1 }"), "Missing => after pattern");
+        assert_eq!(err("match x { f(1) => 1 }"), "Missing => after pattern");
+        assert_eq!(err("match x {}"), "Expected at least one match arm");
+        assert_eq!(
+            err("match [1, 2] { [a, a] => a, _ => 0 }"),
+            "A name can only be used once in a pattern"
+        );
+        assert_eq!(
+            err("match x { {a, [b, ..a]} => a, _ => 0 }"),
+            "A name can only be used once in a pattern"
+        );
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let parse = |input: &str| Parser::new(Tokenizer::new(input)).unwrap().parse();
+        let nested = parse("sum(map(filter(range(1, 100), odd), |x| x ** 2))");
+        for input in [
+            "range(1, 100) |> filter(odd) |> map(|x| x ** 2) |> sum",
+            "range(1, 100)\n  |> filter(odd) |>\n  map(|x| x ** 2)\n  |> sum",
+            "range(1, 100).filter(odd).map(|x| x ** 2).sum()",
+        ] {
+            assert_eq!(parse(input), nested, "{}", input);
+        }
+        check("odd", Expr::Var("odd".to_string()));
+        assert_eq!(parse("xs |> f(1) |> g"), parse("g(f(xs, 1))"));
+        // Names that aren't built-ins are fields, which can hold functions
+        check(
+            "r.f(1)",
+            Expr::Call(
+                Box::new(Expr::Field(
+                    Box::new(Expr::Var("r".to_string())),
+                    "f".to_string(),
+                )),
+                vec![Expr::Int(1)],
+            ),
+        );
+        assert!(parse("sin 5").is_err());
+    }
+
+    #[test]
+    fn test_unpack() {
+        let bind = |name: &str| Pattern::Bind(name.to_string());
+        let parse = |input: &str| Parser::new(Tokenizer::new(input)).unwrap().parse();
+        assert_eq!(
+            parse("let {x, y} = quadr(1, 1, -2)"),
+            Ok(Stmt::Unpack(
+                Pattern::Tuple(vec![bind("x"), bind("y")]),
+                Expr::Func(
+                    Func::Quadr,
+                    vec![
+                        Expr::Int(1),
+                        Expr::Int(1),
+                        Expr::Unary(Box::new(Expr::Int(2)), Token::Minus)
+                    ]
+                ),
+            ))
+        );
+        check(
+            "|{x, y}, [h, ..t], _| x",
+            Expr::Fun(
+                vec![
+                    Pattern::Tuple(vec![bind("x"), bind("y")]),
+                    Pattern::List(vec![bind("h"), Pattern::Rest(Some("t".to_string()))]),
+                    Pattern::Wildcard,
+                ],
+                Box::new(Expr::Var("x".to_string())),
+            ),
+        );
+        for input in [
+            "let [a, _, ..rest] = xs",
+            "let f = |{a, [b, c]}| a",
+            "fn f({a, b}, c) = a",
+        ] {
+            assert_eq!(
+                parse(input).map(|stmt| stmt.format()),
+                Ok(input.to_string())
+            );
+        }
 
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), Ok(expected));
+        let err = |input: &str| parse(input).unwrap_err().0.remove(0).msg;
+        assert_eq!(
+            err("let {x, 0} = xs"),
+            "Only names, _, lists and tuples can be unpacked, use match to compare values"
+        );
+        assert_eq!(
+            err("let [a, a] = xs"),
+            "A name can only be used once in a pattern"
+        );
+        assert_eq!(err("|a, {b, a}| a"), "Function parameters must be unique");
+        assert_eq!(
+            err("let {a, b} = xs in a"),
+            "Only names can be bound with let .. in, use match to take values apart"
+        );
     }
 
     #[test]
@@ -941,10 +2754,8 @@ mod tests {
             Box::new(Expr::Nil),
         ));
 
-        let mut tokenizer = Tokenizer::new("nil == nil".chars().peekable()).peekable();
-
-        let current = tokenizer.next().unwrap();
-        assert_eq!(Parser::new(tokenizer, current).parse(), Ok(expected));
+        let tokenizer = Tokenizer::new("nil == nil");
+        assert_eq!(Parser::new(tokenizer).unwrap().parse(), Ok(expected));
     }
 
     #[test]
@@ -961,4 +2772,252 @@ mod tests {
             ),
         );
     }
+
+    #[test]
+    fn test_units() {
+        let unit = |symbols: &[(&str, i32)]| {
+            symbols
+                .iter()
+                .map(|(name, power)| (name.to_string(), *power))
+                .collect::<Vec<_>>()
+        };
+        check(
+            "5 km / 2 h",
+            Expr::Binary(
+                Box::new(Expr::Quantity(Box::new(Expr::Int(5)), unit(&[("km", 1)]))),
+                Token::Div,
+                Box::new(Expr::Quantity(Box::new(Expr::Int(2)), unit(&[("h", 1)]))),
+            ),
+        );
+        check(
+            "90 km/h to m/s",
+            Expr::Convert(
+                Box::new(Expr::Quantity(
+                    Box::new(Expr::Int(90)),
+                    unit(&[("km", 1), ("h", -1)]),
+                )),
+                unit(&[("m", 1), ("s", -1)]),
+            ),
+        );
+        check(
+            "9.8 kg*m/s^2",
+            Expr::Quantity(
+                Box::new(Expr::Float(9.8)),
+                unit(&[("kg", 1), ("m", 1), ("s", -2)]),
+            ),
+        );
+        // Names that aren't built-in units are operands, except after to
+        check(
+            "5 m * d",
+            Expr::Binary(
+                Box::new(Expr::Quantity(Box::new(Expr::Int(5)), unit(&[("m", 1)]))),
+                Token::Mult,
+                Box::new(Expr::Var("d".to_string())),
+            ),
+        );
+        check(
+            "x to km/furlong",
+            Expr::Convert(
+                Box::new(Expr::Var("x".to_string())),
+                unit(&[("km", 1), ("furlong", -1)]),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let errors = |str: &str| {
+            Parser::new(Tokenizer::new(str))
+                .unwrap()
+                .parse()
+                .unwrap_err()
+                .0
+                .into_iter()
+                .map(|err| (err.msg, err.span))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("[1 +, 2, max(3 *), (4 -)]"),
+            vec![
+                ("Expected expression", Span::new(4, 5)),
+                ("Expected expression", Span::new(16, 17)),
+                ("Expected expression", Span::new(23, 24)),
+            ]
+        );
+        // Errors at the same place are only reported once
+        assert_eq!(
+            errors("f(1, [2"),
+            vec![("Missing closing bracket", Span::new(7, 8))]
+        );
+        assert_eq!(
+            errors("{1, 2) + (3"),
+            vec![("Missing closing bracket", Span::new(5, 6))]
+        );
+        let err = Parser::new(Tokenizer::new("1 $ 2 \"3"))
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err.0.len(), 2);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let err = Parser::new(Tokenizer::new("if x thn 1 else 2"))
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(err.0[0].found, "thn");
+        assert_eq!(err.0[0].expected, Some(Token::Then));
+        assert_eq!(err.0[0].notes, vec!["did you mean `then`?"]);
+        assert_eq!(
+            err.report("if x thn 1 else 2"),
+            "Missing then after condition, expected: then, got: thn at column 6\n\
+             if x thn 1 else 2\n     ^^^\nnote: did you mean `then`?"
+        );
+
+        assert_eq!(suggest("sqr", FNS), Some(SQRT));
+        assert_eq!(suggest("flor", FNS), Some(FLOOR));
+        assert_eq!(suggest("x", ["y"]), None);
+        assert_eq!(suggest("banana", FNS), None);
+    }
+
+    #[test]
+    fn test_statements() {
+        let parse = |str: &str| Parser::new(Tokenizer::new(str)).unwrap().parse();
+        let assign = Stmt::Assign("a".to_string(), Expr::Int(1));
+        let var = Stmt::Expr(Expr::Var("a".to_string()));
+        assert_eq!(
+            parse("let a = 1; a"),
+            Ok(Stmt::Seq(vec![assign.clone(), var.clone()]))
+        );
+        assert_eq!(
+            parse("\n# setup\nlet a =\n  1\n\na;\n"),
+            Ok(Stmt::Seq(vec![assign.clone(), var.clone()]))
+        );
+        assert_eq!(
+            parse("[1,\n 2\n]"),
+            Ok(Stmt::Expr(Expr::List(vec![Expr::Int(1), Expr::Int(2)])))
+        );
+        assert_eq!(
+            parse("{ let a = 1; a }"),
+            Ok(Stmt::Expr(Expr::Block(vec![assign.clone(), var.clone()])))
+        );
+        assert_eq!(
+            parse("{\n  a\n  a\n}"),
+            Ok(Stmt::Expr(Expr::Block(vec![var.clone(), var.clone()])))
+        );
+        assert_eq!(
+            parse("{a, 1}"),
+            Ok(Stmt::Expr(Expr::Tuple(vec![
+                Expr::Var("a".to_string()),
+                Expr::Int(1)
+            ])))
+        );
+        assert_eq!(
+            parse("{a}"),
+            Ok(Stmt::Expr(Expr::Tuple(vec![Expr::Var("a".to_string())])))
+        );
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let input = "let a = 1\n\nmax(a, 2); a";
+        let spans = Parser::new(Tokenizer::new(input))
+            .unwrap()
+            .statements()
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| &input[span.start..span.end])
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec!["let a = 1", "max(a, 2)", "a"]);
+
+        // Each broken statement is reported
+        let input = "let = 1\n2 3\n\n(3";
+        let err = Parser::new(Tokenizer::new(input))
+            .unwrap()
+            .statements()
+            .unwrap_err();
+        assert_eq!(
+            err.0.iter().map(|err| err.msg).collect::<Vec<_>>(),
+            vec![
+                "Missing function name",
+                "Unexpected token",
+                "Missing closing parentheses"
+            ]
+        );
+        assert!(err.report(input).contains("at line 4, column 3\n(3\n  ^"));
+    }
+
+    #[test]
+    fn test_local_bindings() {
+        let var = |name: &str| Expr::Var(name.to_string());
+        let bindings = vec![("a".to_string(), Expr::Int(1)), ("b".to_string(), var("a"))];
+        let body = Expr::Binary(Box::new(var("a")), Token::Plus, Box::new(var("b")));
+        let expected = Expr::Let(bindings, Box::new(body));
+        check("let a = 1, b = a in a + b", expected.clone());
+        check("a + b where a = 1, b = a", expected.clone());
+        check(
+            "(let a = 1, b = a in a + b)",
+            Expr::Grouping(Box::new(expected)),
+        );
+        // The comma belongs to the list when no binding follows it
+        check(
+            "[a where a = 1, a]",
+            Expr::List(vec![
+                Expr::Let(vec![("a".to_string(), Expr::Int(1))], Box::new(var("a"))),
+                var("a"),
+            ]),
+        );
+
+        let stmt = Parser::new(Tokenizer::new("let f = |x| y where y = x"))
+            .unwrap()
+            .parse();
+        assert_eq!(
+            stmt,
+            Ok(Stmt::Assign(
+                "f".to_string(),
+                Expr::Fun(
+                    vec![Pattern::Bind("x".to_string())],
+                    Box::new(Expr::Let(
+                        vec![("y".to_string(), var("x"))],
+                        Box::new(var("y"))
+                    ))
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_definitions() {
+        let var = |name: &str| Expr::Var(name.to_string());
+        let stmt = Parser::new(Tokenizer::new("fn f(x, _) = g(x), g(y) = y"))
+            .unwrap()
+            .parse();
+        assert_eq!(
+            stmt,
+            Ok(Stmt::Fn(vec![
+                Definition {
+                    name: "f".to_string(),
+                    parameters: vec![Pattern::Bind("x".to_string()), Pattern::Wildcard],
+                    body: Expr::Call(Box::new(var("g")), vec![var("x")]),
+                },
+                Definition {
+                    name: "g".to_string(),
+                    parameters: vec![Pattern::Bind("y".to_string())],
+                    body: var("y"),
+                },
+            ]))
+        );
+
+        let err = Parser::new(Tokenizer::new("fn sqrt(x) = x"))
+            .unwrap()
+            .parse();
+        assert_eq!(
+            err,
+            Err(ParseErr(vec![Diagnostic::new(
+                Token::Ident("sqrt".to_string()),
+                "Built-in functions can't be redefined",
+                Span::new(3, 7)
+            )]))
+        );
+    }
 }
@@ -0,0 +1,217 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    interpreter::{Stmt, Value},
+    parse::{Expr, Part},
+};
+
+// Variables defined at one level of nesting. Lookups fall through to the enclosing scope, which
+// is shared rather than copied when a block, let or function call starts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scope {
+    vars: HashMap<String, Value>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn new(vars: HashMap<String, Value>) -> Self {
+        Self { vars, parent: None }
+    }
+
+    pub fn child(parent: Rc<Scope>) -> Self {
+        Self {
+            vars: HashMap::new(),
+            parent: Some(parent),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.vars
+            .get(name)
+            .or_else(|| self.parent.as_ref().and_then(|parent| parent.get(name)))
+    }
+
+    pub fn insert(&mut self, name: String, value: Value) {
+        self.vars.insert(name, value);
+    }
+
+    // Only this scope is changed, a variable from an enclosing one stays visible
+    pub fn remove(&mut self, name: &str) {
+        self.vars.remove(name);
+    }
+
+    // The variables defined in this scope, without the enclosing ones
+    pub fn vars(&self) -> &HashMap<String, Value> {
+        &self.vars
+    }
+
+    // Every name that can be looked up, including shadowed ones
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::successors(Some(self), |scope| scope.parent.as_deref())
+            .flat_map(|scope| scope.vars.keys().map(String::as_str))
+    }
+
+    // A snapshot of only the variables the bodies can refer to, for closures. Bound names are
+    // defined when the closure is called so they aren't needed
+    pub fn capture<'a>(
+        &self,
+        bodies: impl IntoIterator<Item = &'a Expr>,
+        bound: &[String],
+    ) -> HashMap<String, Value> {
+        let mut names = HashSet::new();
+        bodies
+            .into_iter()
+            .for_each(|body| expr_names(body, &mut names));
+        names
+            .into_iter()
+            .filter(|name| !bound.iter().any(|bound| bound == name))
+            .filter_map(|name| Some((name.to_string(), self.get(name)?.clone())))
+            .collect()
+    }
+}
+
+// Names an expression might look up. Names it defines itself are included too, which only
+// means a few more variables are captured than needed
+fn expr_names<'a>(expr: &'a Expr, names: &mut HashSet<&'a str>) {
+    match expr {
+        Expr::Var(name) => {
+            names.insert(name);
+        }
+        // Variables holding quantities can be used as units
+        Expr::Quantity(expr, units) | Expr::Convert(expr, units) => {
+            names.extend(units.iter().map(|(name, _)| name.as_str()));
+            expr_names(expr, names);
+        }
+        Expr::Binary(left, _, right) => {
+            expr_names(left, names);
+            expr_names(right, names);
+        }
+        Expr::Grouping(expr) | Expr::Unary(expr, _) | Expr::Fun(_, expr) => expr_names(expr, names),
+        Expr::Call(callee, args) => {
+            expr_names(callee, names);
+            args.iter().for_each(|arg| expr_names(arg, names));
+        }
+        Expr::Func(_, elems) | Expr::List(elems) | Expr::Tuple(elems) => {
+            elems.iter().for_each(|elem| expr_names(elem, names))
+        }
+        Expr::Record(fields) => fields.iter().for_each(|(_, expr)| expr_names(expr, names)),
+        Expr::Update(record, fields) => {
+            expr_names(record, names);
+            fields.iter().for_each(|(_, expr)| expr_names(expr, names));
+        }
+        Expr::Field(record, _) => expr_names(record, names),
+        Expr::Index(expr, index) => {
+            expr_names(expr, names);
+            expr_names(index, names);
+        }
+        Expr::Interpolated(parts) => parts
+            .iter()
+            .filter_map(Part::expr)
+            .for_each(|expr| expr_names(expr, names)),
+        Expr::Slice(expr, bounds) => {
+            expr_names(expr, names);
+            bounds
+                .iter()
+                .flatten()
+                .for_each(|bound| expr_names(bound, names));
+        }
+        Expr::If(cond, then, else_expr) => {
+            expr_names(cond, names);
+            expr_names(then, names);
+            expr_names(else_expr, names);
+        }
+        Expr::Block(stmts) => stmts.iter().for_each(|stmt| stmt_names(stmt, names)),
+        Expr::Let(bindings, body) => {
+            bindings
+                .iter()
+                .for_each(|(_, expr)| expr_names(expr, names));
+            expr_names(body, names);
+        }
+        Expr::Match(subject, arms) => {
+            expr_names(subject, names);
+            for arm in arms {
+                arm.guard.iter().for_each(|guard| expr_names(guard, names));
+                expr_names(&arm.body, names);
+            }
+        }
+        Expr::Float(_)
+        | Expr::Int(_)
+        | Expr::BigInt(_)
+        | Expr::Fixed(_, _)
+        | Expr::Imaginary(_)
+        | Expr::Decimal(_)
+        | Expr::Time(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Nil
+        | Expr::Nan => {}
+    }
+}
+
+fn stmt_names<'a>(stmt: &'a Stmt, names: &mut HashSet<&'a str>) {
+    match stmt {
+        Stmt::Expr(expr)
+        | Stmt::Assign(_, expr)
+        | Stmt::Unpack(_, expr)
+        | Stmt::Config(_, expr)
+        | Stmt::Simplify(expr) => expr_names(expr, names),
+        Stmt::Seq(stmts) => stmts.iter().for_each(|stmt| stmt_names(stmt, names)),
+        Stmt::Fn(definitions) => definitions
+            .iter()
+            .for_each(|definition| expr_names(&definition.body, names)),
+        Stmt::Undef(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse::Parser, Tokenizer};
+
+    fn parse(input: &str) -> Expr {
+        match Parser::new(Tokenizer::new(input)).unwrap().parse().unwrap() {
+            Stmt::Expr(expr) => expr,
+            stmt => panic!("Expected an expression, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn nested_scopes() {
+        let mut outer = Scope::new(HashMap::from([
+            ("a".to_string(), Value::Int(1)),
+            ("b".to_string(), Value::Int(2)),
+        ]));
+        outer.insert("c".to_string(), Value::Int(3));
+        let mut inner = Scope::child(Rc::new(outer));
+        inner.insert("a".to_string(), Value::Int(10));
+        assert_eq!(inner.get("a"), Some(&Value::Int(10)));
+        assert_eq!(inner.get("c"), Some(&Value::Int(3)));
+        assert_eq!(inner.get("d"), None);
+        assert_eq!(inner.vars().len(), 1);
+        let mut names = inner.names().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["a", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn captured_variables() {
+        let scope = Scope::new(HashMap::from([
+            ("x".to_string(), Value::Int(1)),
+            ("y".to_string(), Value::Int(2)),
+            ("unused".to_string(), Value::Int(3)),
+            ("furlong".to_string(), Value::Int(4)),
+        ]));
+        let body = parse("x + y + z + { let w = 2; w } + 5 furlong");
+        let captured = scope.capture([&body], &["y".to_string()]);
+        assert_eq!(
+            captured,
+            HashMap::from([
+                ("x".to_string(), Value::Int(1)),
+                ("furlong".to_string(), Value::Int(4)),
+            ])
+        );
+    }
+}
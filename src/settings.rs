@@ -0,0 +1,296 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::interpreter::{InterpretError, Value};
+
+const DECIMAL: &str = "decimal";
+const PRECISION: &str = "precision";
+const ROUNDING: &str = "rounding";
+const OVERFLOW: &str = "overflow";
+const MAX_DEPTH: &str = "max_depth";
+const BACKEND: &str = "backend";
+const TYPECHECK: &str = "typecheck";
+
+pub const SETTINGS: [&str; 7] = [
+    DECIMAL, PRECISION, ROUNDING, OVERFLOW, MAX_DEPTH, BACKEND, TYPECHECK,
+];
+
+// Every level of recursion holds on to some memory, so there has to be an upper bound
+const DEPTH_LIMIT: usize = 100_000;
+
+// Interpreter wide options, changed with `config [NAME] = [VALUE]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    // Float literals are read as decimals
+    pub decimal: bool,
+    // Number of decimal places decimal results are rounded to
+    pub precision: u32,
+    pub rounding: Rounding,
+    // What happens when fixed width integers go out of range
+    pub overflow: Overflow,
+    // How deeply user functions can call each other. Tail calls don't count, but a call can
+    // only be replaced by 100 times as many
+    pub max_depth: usize,
+    pub backend: Backend,
+    // Inputs are checked for type errors before they run, function bodies included
+    pub typecheck: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    Up,
+    Down,
+    Ceiling,
+    Floor,
+}
+
+impl Rounding {
+    const ALL: [Rounding; 7] = [
+        Rounding::HalfEven,
+        Rounding::HalfUp,
+        Rounding::HalfDown,
+        Rounding::Up,
+        Rounding::Down,
+        Rounding::Ceiling,
+        Rounding::Floor,
+    ];
+
+    pub fn strategy(&self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfDown => RoundingStrategy::MidpointTowardZero,
+            Rounding::Up => RoundingStrategy::AwayFromZero,
+            Rounding::Down => RoundingStrategy::ToZero,
+            Rounding::Ceiling => RoundingStrategy::ToPositiveInfinity,
+            Rounding::Floor => RoundingStrategy::ToNegativeInfinity,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Rounding::HalfEven => "half_even",
+            Rounding::HalfUp => "half_up",
+            Rounding::HalfDown => "half_down",
+            Rounding::Up => "up",
+            Rounding::Down => "down",
+            Rounding::Ceiling => "ceiling",
+            Rounding::Floor => "floor",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Wrapping,
+    Checked,
+    Saturating,
+}
+
+impl Overflow {
+    const ALL: [Overflow; 3] = [Overflow::Wrapping, Overflow::Checked, Overflow::Saturating];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Overflow::Wrapping => "wrapping",
+            Overflow::Checked => "checked",
+            Overflow::Saturating => "saturating",
+        }
+    }
+}
+
+// How expressions are evaluated. Both give the same results
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    // Walks the syntax tree
+    Tree,
+    // Compiles to bytecode for a stack machine first
+    Vm,
+}
+
+impl Backend {
+    const ALL: [Backend; 2] = [Backend::Tree, Backend::Vm];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::Tree => "tree",
+            Backend::Vm => "vm",
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            decimal: false,
+            precision: Decimal::MAX_SCALE,
+            rounding: Rounding::HalfEven,
+            overflow: Overflow::Wrapping,
+            max_depth: 10_000,
+            backend: Backend::Tree,
+            typecheck: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn set(&mut self, name: &str, value: &Value) -> Result<(), InterpretError> {
+        match name {
+            DECIMAL => self.decimal = value.truthy(),
+            TYPECHECK => self.typecheck = value.truthy(),
+            PRECISION => {
+                let precision = value.to_int()?;
+                if !(0..=Decimal::MAX_SCALE as i64).contains(&precision) {
+                    return Err(InterpretError::InvalidArgument(format!(
+                        "Precision must be between 0 and {}",
+                        Decimal::MAX_SCALE
+                    )));
+                }
+                self.precision = precision as u32;
+            }
+            ROUNDING => {
+                self.rounding = Rounding::ALL
+                    .into_iter()
+                    .find(|rounding| Value::String(rounding.name().to_string()) == *value)
+                    .ok_or_else(|| {
+                        InterpretError::InvalidArgument(format!(
+                            "Unknown rounding mode {}, expected one of {}",
+                            value,
+                            Rounding::ALL.map(|r| r.name()).join(", ")
+                        ))
+                    })?
+            }
+            OVERFLOW => {
+                self.overflow = Overflow::ALL
+                    .into_iter()
+                    .find(|overflow| Value::String(overflow.name().to_string()) == *value)
+                    .ok_or_else(|| {
+                        InterpretError::InvalidArgument(format!(
+                            "Unknown overflow mode {}, expected one of {}",
+                            value,
+                            Overflow::ALL.map(|o| o.name()).join(", ")
+                        ))
+                    })?
+            }
+            MAX_DEPTH => {
+                let depth = value.to_int()?;
+                if !(1..=DEPTH_LIMIT as i64).contains(&depth) {
+                    return Err(InterpretError::InvalidArgument(format!(
+                        "Maximum depth must be between 1 and {}",
+                        DEPTH_LIMIT
+                    )));
+                }
+                self.max_depth = depth as usize;
+            }
+            BACKEND => {
+                self.backend = Backend::ALL
+                    .into_iter()
+                    .find(|backend| Value::String(backend.name().to_string()) == *value)
+                    .ok_or_else(|| {
+                        InterpretError::InvalidArgument(format!(
+                            "Unknown backend {}, expected one of {}",
+                            value,
+                            Backend::ALL.map(|b| b.name()).join(", ")
+                        ))
+                    })?
+            }
+            _ => {
+                return Err(InterpretError::InvalidArgument(format!(
+                    "Unknown setting {}, expected one of {}",
+                    name,
+                    SETTINGS.join(", ")
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    // Commands to restore the settings which differ from the defaults
+    pub fn to_inputs(self) -> Vec<String> {
+        let default = Self::default();
+        let mut inputs = vec![];
+        if self.decimal != default.decimal {
+            inputs.push(format!("config {} = {}", DECIMAL, self.decimal));
+        }
+        if self.precision != default.precision {
+            inputs.push(format!("config {} = {}", PRECISION, self.precision));
+        }
+        if self.rounding != default.rounding {
+            inputs.push(format!(
+                "config {} = \"{}\"",
+                ROUNDING,
+                self.rounding.name()
+            ));
+        }
+        if self.overflow != default.overflow {
+            inputs.push(format!(
+                "config {} = \"{}\"",
+                OVERFLOW,
+                self.overflow.name()
+            ));
+        }
+        if self.max_depth != default.max_depth {
+            inputs.push(format!("config {} = {}", MAX_DEPTH, self.max_depth));
+        }
+        if self.backend != default.backend {
+            inputs.push(format!("config {} = \"{}\"", BACKEND, self.backend.name()));
+        }
+        if self.typecheck != default.typecheck {
+            inputs.push(format!("config {} = {}", TYPECHECK, self.typecheck));
+        }
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_and_restore() {
+        let mut settings = Settings::default();
+        assert!(settings.to_inputs().is_empty());
+
+        settings.set(PRECISION, &Value::Int(2)).unwrap();
+        settings
+            .set(ROUNDING, &Value::String("half_up".to_string()))
+            .unwrap();
+        assert_eq!(settings.rounding, Rounding::HalfUp);
+        settings
+            .set(OVERFLOW, &Value::String("checked".to_string()))
+            .unwrap();
+        settings.set(MAX_DEPTH, &Value::Int(100)).unwrap();
+        settings
+            .set(BACKEND, &Value::String("vm".to_string()))
+            .unwrap();
+        settings.set(TYPECHECK, &Value::Bool(false)).unwrap();
+        assert_eq!(
+            settings.to_inputs(),
+            vec![
+                "config precision = 2",
+                "config rounding = \"half_up\"",
+                "config overflow = \"checked\"",
+                "config max_depth = 100",
+                "config backend = \"vm\"",
+                "config typecheck = false"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let mut settings = Settings::default();
+        assert!(settings.set(PRECISION, &Value::Int(29)).is_err());
+        assert!(settings
+            .set(ROUNDING, &Value::String("sideways".to_string()))
+            .is_err());
+        assert!(settings.set(MAX_DEPTH, &Value::Int(0)).is_err());
+        assert!(settings
+            .set(BACKEND, &Value::String("jit".to_string()))
+            .is_err());
+        assert!(settings.set("foo", &Value::Int(1)).is_err());
+        assert_eq!(settings, Settings::default());
+    }
+}
@@ -0,0 +1,440 @@
+use num_traits::Signed;
+
+use crate::{
+    interpreter::{Interpreter, Stmt, Value},
+    parse::{Arm, Definition, Expr, Func, Part, Pattern},
+};
+
+// Simplifies a statement before it runs. Sequences should be split up first, so that each
+// statement sees what the ones before it defined and configured
+pub fn stmt(stmt: Stmt, interpreter: &Interpreter) -> Stmt {
+    Simplifier::new(interpreter).stmt(stmt)
+}
+
+// Constant parts are worked out, groupings that don't change the order of operations dropped
+// and built-in constants like pi replaced by their value
+pub fn expr(expr: Expr, interpreter: &Interpreter) -> Expr {
+    Simplifier::new(interpreter).neutral(expr)
+}
+
+struct Simplifier<'a> {
+    interpreter: &'a Interpreter,
+    // Names defined by the code being simplified, which hide the built-in constants
+    bound: Vec<String>,
+    // Turned off after a config in a block, since the rest of it could work out differently
+    fold: bool,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(interpreter: &'a Interpreter) -> Self {
+        Self {
+            interpreter,
+            bound: vec![],
+            fold: true,
+        }
+    }
+
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Expr(expr) => Stmt::Expr(self.neutral(expr)),
+            Stmt::Assign(name, expr) => {
+                let expr = self.neutral(expr);
+                self.bound.push(name.clone());
+                Stmt::Assign(name, expr)
+            }
+            Stmt::Unpack(pattern, expr) => {
+                let expr = self.neutral(expr);
+                self.bound
+                    .extend(pattern.names().into_iter().map(str::to_string));
+                Stmt::Unpack(pattern, expr)
+            }
+            Stmt::Undef(names) => {
+                self.bound.extend(names.iter().cloned());
+                Stmt::Undef(names)
+            }
+            Stmt::Config(name, expr) => {
+                let expr = self.neutral(expr);
+                self.fold = false;
+                Stmt::Config(name, expr)
+            }
+            Stmt::Seq(stmts) => Stmt::Seq(stmts.into_iter().map(|stmt| self.stmt(stmt)).collect()),
+            Stmt::Fn(definitions) => {
+                self.bound
+                    .extend(definitions.iter().map(|definition| definition.name.clone()));
+                Stmt::Fn(
+                    definitions
+                        .into_iter()
+                        .map(|definition| Definition {
+                            body: self.body(&definition.parameters, definition.body),
+                            ..definition
+                        })
+                        .collect(),
+                )
+            }
+            // Shown as it was typed, it's simplified when it runs
+            Stmt::Simplify(expr) => Stmt::Simplify(expr),
+        }
+    }
+
+    fn body(&mut self, parameters: &[Pattern], body: Expr) -> Expr {
+        let len = self.bound.len();
+        self.bound.extend(
+            parameters
+                .iter()
+                .flat_map(Pattern::names)
+                .map(str::to_string),
+        );
+        let body = self.neutral(body);
+        self.bound.truncate(len);
+        body
+    }
+
+    // Where nothing binds tighter, eg. an argument or the body of a function, so any grouping
+    // can go
+    fn neutral(&mut self, expr: Expr) -> Expr {
+        match self.expr(expr) {
+            Expr::Grouping(inner) => *inner,
+            expr => expr,
+        }
+    }
+
+    // Next to an operator, where a grouping is only dropped if it has a single value inside
+    fn operand(&mut self, expr: Expr) -> Expr {
+        match self.expr(expr) {
+            Expr::Grouping(inner) if atom(&inner) => *inner,
+            expr => expr,
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Var(name) if self.fold && !self.bound.contains(&name) => self
+                .interpreter
+                .constant(&name)
+                .and_then(|value| self.literal(value))
+                .unwrap_or(Expr::Var(name)),
+            Expr::Grouping(expr) => Expr::Grouping(Box::new(self.neutral(*expr))),
+            Expr::Binary(left, operator, right) => {
+                let left = self.operand(*left);
+                let right = self.operand(*right);
+                self.fold(Expr::Binary(Box::new(left), operator, Box::new(right)))
+            }
+            Expr::Unary(expr, operator) => {
+                let expr = self.operand(*expr);
+                self.fold(Expr::Unary(Box::new(expr), operator))
+            }
+            // Units can be variables, so these are never constant
+            Expr::Quantity(expr, unit) => Expr::Quantity(Box::new(self.operand(*expr)), unit),
+            Expr::Convert(expr, unit) => Expr::Convert(Box::new(self.operand(*expr)), unit),
+            Expr::Call(callee, args) => Expr::Call(
+                Box::new(self.operand(*callee)),
+                args.into_iter().map(|arg| self.neutral(arg)).collect(),
+            ),
+            Expr::Func(func, args) => {
+                let args = args.into_iter().map(|arg| self.neutral(arg)).collect();
+                let expr = Expr::Func(func, args);
+                if matches!(expr, Expr::Func(Func::Now | Func::Today, _)) {
+                    expr
+                } else {
+                    self.fold(expr)
+                }
+            }
+            Expr::Fun(params, body) => {
+                let body = self.body(&params, *body);
+                Expr::Fun(params, Box::new(body))
+            }
+            Expr::If(cond, then, else_expr) => {
+                let cond = self.neutral(*cond);
+                let then = self.neutral(*then);
+                let else_expr = self.neutral(*else_expr);
+                // Only the branch that would run is kept
+                if self.fold && constant(&cond) {
+                    if let Ok(cond) = self.interpreter.interpret_expr(&cond) {
+                        return group(if cond.truthy() { then } else { else_expr });
+                    }
+                }
+                Expr::If(Box::new(cond), Box::new(then), Box::new(else_expr))
+            }
+            Expr::List(elems) => {
+                Expr::List(elems.into_iter().map(|elem| self.neutral(elem)).collect())
+            }
+            Expr::Tuple(elems) => {
+                Expr::Tuple(elems.into_iter().map(|elem| self.neutral(elem)).collect())
+            }
+            Expr::Record(fields) => Expr::Record(self.fields(fields)),
+            Expr::Update(record, fields) => {
+                let record = self.neutral(*record);
+                Expr::Update(Box::new(record), self.fields(fields))
+            }
+            Expr::Field(record, name) => {
+                let record = self.operand(*record);
+                self.fold(Expr::Field(Box::new(record), name))
+            }
+            Expr::Index(expr, index) => {
+                let expr = self.operand(*expr);
+                let index = self.neutral(*index);
+                self.fold(Expr::Index(Box::new(expr), Box::new(index)))
+            }
+            Expr::Interpolated(parts) => {
+                let parts = parts
+                    .into_iter()
+                    .map(|part| match part {
+                        Part::Expr(expr, spec) => Part::Expr(self.neutral(expr), spec),
+                        text => text,
+                    })
+                    .collect();
+                self.fold(Expr::Interpolated(parts))
+            }
+            Expr::Slice(expr, bounds) => {
+                let expr = self.operand(*expr);
+                let bounds = bounds.map(|bound| bound.map(|bound| Box::new(self.neutral(*bound))));
+                self.fold(Expr::Slice(Box::new(expr), bounds))
+            }
+            // Anything defined or configured in a block is gone once it ends
+            Expr::Block(stmts) => {
+                let (len, fold) = (self.bound.len(), self.fold);
+                let stmts = stmts.into_iter().map(|stmt| self.stmt(stmt)).collect();
+                self.bound.truncate(len);
+                self.fold = fold;
+                Expr::Block(stmts)
+            }
+            Expr::Match(subject, arms) => {
+                let subject = self.neutral(*subject);
+                let arms = arms
+                    .into_iter()
+                    .map(|arm| {
+                        let len = self.bound.len();
+                        self.bound
+                            .extend(arm.pattern.names().into_iter().map(str::to_string));
+                        let guard = arm.guard.map(|guard| self.neutral(guard));
+                        let body = self.neutral(arm.body);
+                        self.bound.truncate(len);
+                        Arm { guard, body, ..arm }
+                    })
+                    .collect();
+                Expr::Match(Box::new(subject), arms)
+            }
+            Expr::Let(bindings, body) => {
+                let len = self.bound.len();
+                let bindings = bindings
+                    .into_iter()
+                    .map(|(name, expr)| {
+                        let expr = self.neutral(expr);
+                        self.bound.push(name.clone());
+                        (name, expr)
+                    })
+                    .collect();
+                let body = self.neutral(*body);
+                self.bound.truncate(len);
+                Expr::Let(bindings, Box::new(body))
+            }
+            literal => literal,
+        }
+    }
+
+    fn fields(&mut self, fields: Vec<(String, Expr)>) -> Vec<(String, Expr)> {
+        fields
+            .into_iter()
+            .map(|(name, expr)| (name, self.neutral(expr)))
+            .collect()
+    }
+
+    // Replaces an operation on constants by its result. Errors are left for when it runs, in
+    // case it never does
+    fn fold(&self, expr: Expr) -> Expr {
+        let operands = match &expr {
+            Expr::Binary(left, _, right) => vec![left.as_ref(), right.as_ref()],
+            Expr::Unary(expr, _) => vec![expr.as_ref()],
+            Expr::Func(_, args) => args.iter().collect(),
+            Expr::Field(record, _) => vec![record.as_ref()],
+            Expr::Index(expr, index) => vec![expr.as_ref(), index.as_ref()],
+            Expr::Slice(expr, bounds) => std::iter::once(expr.as_ref())
+                .chain(bounds.iter().flatten().map(AsRef::as_ref))
+                .collect(),
+            Expr::Interpolated(parts) => parts.iter().filter_map(Part::expr).collect(),
+            _ => vec![],
+        };
+        if !self.fold || !operands.into_iter().all(constant) {
+            return expr;
+        }
+        self.interpreter
+            .interpret_expr(&expr)
+            .ok()
+            .and_then(|value| self.literal(value))
+            .unwrap_or(expr)
+    }
+
+    // A literal which gives back the same value, if there is one. Lists aren't turned back into
+    // literals since a call like range can make them very long
+    fn literal(&self, value: Value) -> Option<Expr> {
+        let (expr, negative) = match value {
+            Value::Int(int) => (Expr::Int(int), int < 0),
+            Value::BigInt(int) => (Expr::BigInt(int.clone()), int.is_negative()),
+            // Negative literals would be out of range before being negated
+            Value::Fixed(int, ty) if !int.is_negative() => (Expr::Fixed(int, ty), false),
+            // Float literals are read as decimals when the decimal setting is on, and whole ones
+            // would be read back as integers
+            Value::Float(float)
+                if !self.interpreter.settings().decimal && float.to_string().contains('.') =>
+            {
+                (Expr::Float(float), float < 0.0)
+            }
+            Value::Decimal(decimal) => (Expr::Decimal(decimal), decimal.is_sign_negative()),
+            Value::Complex(complex) if complex.re == 0.0 && complex.im > 0.0 => {
+                (Expr::Imaginary(complex.im), false)
+            }
+            Value::String(string) => (Expr::String(string), false),
+            Value::Bool(bool) => (Expr::Bool(bool), false),
+            Value::Nil => (Expr::Nil, false),
+            Value::NaN => (Expr::Nan, false),
+            _ => return None,
+        };
+        // Kept in parentheses so that it isn't read as a subtraction, they're dropped where
+        // they aren't needed
+        Some(if negative {
+            Expr::Grouping(Box::new(expr))
+        } else {
+            expr
+        })
+    }
+}
+
+fn constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Grouping(expr) => constant(expr),
+        Expr::List(elems) | Expr::Tuple(elems) => elems.iter().all(constant),
+        Expr::Record(fields) => fields.iter().all(|(_, expr)| constant(expr)),
+        Expr::Int(_)
+        | Expr::BigInt(_)
+        | Expr::Fixed(_, _)
+        | Expr::Float(_)
+        | Expr::Imaginary(_)
+        | Expr::Decimal(_)
+        | Expr::Time(_)
+        | Expr::String(_)
+        | Expr::Bool(_)
+        | Expr::Nil
+        | Expr::Nan => true,
+        _ => false,
+    }
+}
+
+// Expressions which never need parentheses around them
+fn atom(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int(int) => *int >= 0,
+        Expr::BigInt(int) | Expr::Fixed(int, _) => !int.is_negative(),
+        Expr::Float(float) | Expr::Imaginary(float) => float.is_sign_positive(),
+        Expr::Decimal(decimal) => !decimal.is_sign_negative(),
+        Expr::Var(_)
+        | Expr::Call(_, _)
+        | Expr::Func(_, _)
+        | Expr::List(_)
+        | Expr::Tuple(_)
+        | Expr::Record(_)
+        | Expr::Update(_, _)
+        | Expr::Field(_, _)
+        | Expr::Index(_, _)
+        | Expr::Slice(_, _)
+        | Expr::Interpolated(_)
+        | Expr::Match(_, _)
+        | Expr::Block(_)
+        | Expr::Grouping(_)
+        | Expr::String(_)
+        | Expr::Bool(_)
+        | Expr::Nil
+        | Expr::Nan => true,
+        _ => false,
+    }
+}
+
+fn group(expr: Expr) -> Expr {
+    if atom(&expr) {
+        expr
+    } else {
+        Expr::Grouping(Box::new(expr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    use crate::{parse::Parser, Tokenizer};
+
+    fn run(interpreter: &mut Interpreter, input: &str) -> Value {
+        let stmt = Parser::new(Tokenizer::new(input)).unwrap().parse().unwrap();
+        interpreter.interpret(stmt).unwrap()
+    }
+
+    fn check(interpreter: &mut Interpreter, input: &str, simplified: &str) {
+        assert_eq!(
+            run(interpreter, &format!("simplify({})", input)),
+            Value::String(simplified.to_string()),
+            "simplify({})",
+            input
+        );
+    }
+
+    #[test]
+    fn fold_constants() {
+        let mut interpreter = Interpreter::new();
+        check(&mut interpreter, "pi * (2 + 3) * x", "15.707963267948966*x");
+        check(&mut interpreter, "x - (2 * -3)", "x-(-6)");
+        check(&mut interpreter, "[1 + 1, -(2)]", "[2, -2]");
+        check(&mut interpreter, "2i * 3 + x", "6i+x");
+        check(
+            &mut interpreter,
+            "(if 1 < 2 then a + b else c) * 2",
+            "(a+b)*2",
+        );
+        // Not constant, or not the same when read back
+        check(&mut interpreter, "sqrt(16) + 1 / 2", "sqrt(16)+1/2");
+        check(&mut interpreter, "now() + x", "now()+x");
+        check(&mut interpreter, "\"a\" - 1 + x", "\"a\"-1+x");
+    }
+
+    #[test]
+    fn strip_groupings() {
+        let mut interpreter = Interpreter::new();
+        check(&mut interpreter, "((x)) + (y * 2)", "x+(y*2)");
+        check(&mut interpreter, "((1 + x)) * f((y))", "(1+x)*f(y)");
+        check(&mut interpreter, "|x| (x + 1)", "|x| x+1");
+    }
+
+    #[test]
+    fn inline_constants() {
+        let mut interpreter = Interpreter::new();
+        check(
+            &mut interpreter,
+            "let pi = 3 in pi * e",
+            "let pi = 3 in pi*2.718281828459045",
+        );
+        check(&mut interpreter, "|e| e + pi", "|e| e+3.141592653589793");
+        check(&mut interpreter, "{ undef(pi); pi }", "{ undef(pi); pi }");
+        run(&mut interpreter, "let e = 3");
+        check(&mut interpreter, "e", "e");
+        // Float literals would be read as decimals
+        run(&mut interpreter, "config decimal = true");
+        check(&mut interpreter, "pi + 0.1", "pi+0.1");
+        check(&mut interpreter, "0.1 + 0.2", "0.3d");
+    }
+
+    #[test]
+    fn simplified_functions() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "let area = |r| (pi * r ** 2)");
+        let Some(Value::Fn(area)) = interpreter.env().get("area") else {
+            panic!("area isn't a function");
+        };
+        assert_eq!(area.to_string(), "|r| 3.141592653589793*r**2");
+        assert_eq!(run(&mut interpreter, "area(2)"), Value::Float(PI * 4.0));
+        // Configured after the constants, so nothing after it is worked out
+        check(
+            &mut interpreter,
+            "{ config decimal = true; 0.1 + 0.2 }",
+            "{ config decimal = true; 0.1+0.2 }",
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    interpreter::{InterpretError, Value},
+    token::{Token, Tokenizer},
+};
+
+// Fills the {} in a pattern with values like Rust's format!, eg. format("{:.2}", pi) == "3.14".
+// A placeholder can have an alignment, width and number of decimal places, eg. {:>8.2}, and
+// braces are written as {{ and }}
+pub fn format(pattern: &str, values: &[Value]) -> Result<String, InterpretError> {
+    let invalid =
+        |msg: &str| InterpretError::InvalidArgument(format!("{} in \"{}\"", msg, pattern));
+    let mut formatted = String::new();
+    let mut values = values.iter();
+    let mut used = 0;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => formatted.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => formatted.push('}'),
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err(invalid("Unclosed {")),
+                    }
+                }
+                let spec = match spec.strip_prefix(':') {
+                    Some(spec) => {
+                        Spec::parse(spec).ok_or_else(|| invalid("Invalid placeholder"))?
+                    }
+                    None if spec.is_empty() => Spec::default(),
+                    None => return Err(invalid("Invalid placeholder")),
+                };
+                used += 1;
+                let value = values.next().ok_or_else(|| {
+                    InterpretError::InvalidArgument(format!(
+                        "\"{}\" has more placeholders than values",
+                        pattern
+                    ))
+                })?;
+                formatted.push_str(&spec.apply(value)?);
+            }
+            '}' => return Err(invalid("Unmatched }")),
+            c => formatted.push(c),
+        }
+    }
+    if values.next().is_some() {
+        return Err(InterpretError::InvalidArgument(format!(
+            "\"{}\" has {} placeholders but there were more values",
+            pattern, used
+        )));
+    }
+    Ok(formatted)
+}
+
+// Whether the part of a placeholder after the : is one format understands
+pub fn valid_spec(spec: &str) -> bool {
+    Spec::parse(spec).is_some()
+}
+
+// Widths and precisions go up to u16::MAX, like in Rust, which also keeps huge ones from
+// using up all the memory
+#[derive(Default)]
+struct Spec {
+    align: Option<char>,
+    width: u16,
+    precision: Option<u16>,
+}
+
+impl Spec {
+    // [<^>][WIDTH][.PRECISION]
+    fn parse(spec: &str) -> Option<Self> {
+        let (align, spec) = match spec.chars().next() {
+            Some(c @ ('<' | '^' | '>')) => (Some(c), &spec[1..]),
+            _ => (None, spec),
+        };
+        let (width, precision) = match spec.split_once('.') {
+            Some((width, precision)) => (width, Some(precision.parse().ok()?)),
+            None => (spec, None),
+        };
+        let width = if width.is_empty() {
+            0
+        } else {
+            width.parse().ok()?
+        };
+        Some(Self {
+            align,
+            width,
+            precision,
+        })
+    }
+
+    fn apply(&self, value: &Value) -> Result<String, InterpretError> {
+        let text = match (self.precision, value) {
+            (None, value) => value.to_string(),
+            (Some(precision), Value::Decimal(decimal)) => format!(
+                "{:.*}",
+                usize::from(precision),
+                decimal.round_dp(u32::from(precision))
+            ),
+            (Some(precision), value) => {
+                format!("{:.*}", usize::from(precision), value.to_float()?)
+            }
+        };
+        let padding = usize::from(self.width).saturating_sub(text.chars().count());
+        // Numbers line up on the right by default, like in Rust
+        let align = self
+            .align
+            .unwrap_or(if value.is_numeric() { '>' } else { '<' });
+        let (left, right) = match align {
+            '>' => (padding, 0),
+            '^' => (padding / 2, padding - padding / 2),
+            _ => (0, padding),
+        };
+        Ok(format!("{}{}{}", " ".repeat(left), text, " ".repeat(right)))
+    }
+}
+
+// A number written the way it would be in an expression, with an optional sign, eg. "-2.5",
+// "1_000" or "0xff"
+pub fn parse_number(string: &str) -> Option<Value> {
+    let string = string.trim();
+    let (negative, digits) = match string.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, string.strip_prefix('+').unwrap_or(string)),
+    };
+    let mut tokens = Tokenizer::new(digits);
+    let value = match tokens.next()?.ok()? {
+        (Token::Int(int), _) => Value::Int(int),
+        (Token::BigInt(int), _) => Value::from(int),
+        (Token::Float(float), _) => Value::Float(float),
+        (Token::Decimal(decimal), _) => Value::Decimal(decimal),
+        (Token::Fixed(int, ty), _) if !negative => Value::Fixed(int, ty),
+        _ => return None,
+    };
+    if tokens.next().is_some() {
+        return None;
+    }
+    if negative { -value } else { Ok(value) }.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_values() {
+        let check = |pattern: &str, values: &[Value], expected: &str| {
+            assert_eq!(
+                format(pattern, values),
+                Ok(expected.to_string()),
+                "{}",
+                pattern
+            );
+        };
+        check("{:.2}", &[Value::Float(std::f64::consts::PI)], "3.14");
+        check(
+            "{} + {} = {}",
+            &[Value::Int(1), Value::Int(2), Value::Int(3)],
+            "1 + 2 = 3",
+        );
+        check(
+            "[{:5}|{:<5}]",
+            &[Value::Int(1), Value::Int(2)],
+            "[    1|2    ]",
+        );
+        check("[{:^6}]", &[Value::String("ab".to_string())], "[  ab  ]");
+        check("{{{}}}", &[Value::String("a".to_string())], "{a}");
+        for pattern in [
+            "{",
+            "}",
+            "{:x}",
+            "{} {}",
+            "{1}",
+            "{:.70000}",
+            "{:>99999999999}",
+        ] {
+            assert!(format(pattern, &[Value::Int(1)]).is_err(), "{}", pattern);
+        }
+        assert!(format("", &[Value::Int(1)]).is_err());
+    }
+
+    #[test]
+    fn parse_numbers() {
+        assert_eq!(parse_number(" 42 "), Some(Value::Int(42)));
+        assert_eq!(parse_number("-2.5"), Some(Value::Float(-2.5)));
+        assert_eq!(parse_number("0xff"), Some(Value::Int(255)));
+        assert_eq!(parse_number("1_000"), Some(Value::Int(1000)));
+        assert_eq!(parse_number("12abc"), None);
+        assert_eq!(parse_number("1 2"), None);
+        assert_eq!(parse_number(""), None);
+    }
+}
@@ -1,7 +1,10 @@
-use core::iter::Peekable;
-use std::str::Chars;
+use std::{error::Error, fmt::Display, str::Chars};
 
-use crate::inner_write;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::{datetime, fixed::IntType, inner_write, strings};
 
 const LET: &str = "let";
 const UNDEF: &str = "undef";
@@ -14,17 +17,39 @@ const NIL: &str = "nil";
 const NAN: &str = "NaN";
 const OR: &str = "or";
 const AND: &str = "and";
+const CONFIG: &str = "config";
+const TO: &str = "to";
+const IN: &str = "in";
+const WHERE: &str = "where";
+const FN: &str = "fn";
+const SIMPLIFY: &str = "simplify";
+const MATCH: &str = "match";
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token {
     Float(f64),
     Int(i64),
+    BigInt(BigInt),
+    Fixed(BigInt, IntType),
+    Imaginary(f64),
+    Decimal(Decimal),
+    Time(datetime::Literal),
     Comma,
+    Colon,
+    Dot,
     Ident(String),
     Let,
+    In,
+    Where,
+    Fn,
     Undef,
+    Config,
+    Simplify,
+    To,
     Assign,
     Pipe,
+    // Passes a value to a function as its first argument, eg. xs |> map(sq)
+    Pipeline,
     Mod,
     Div,
     Mult,
@@ -53,12 +78,22 @@ pub enum Token {
     Or,
     And,
     If,
+    Match,
+    // Between a pattern and the value of its arm, eg. 0 => "none"
+    Arrow,
     Then,
     Else,
     String(String),
+    // f"..", with its expressions already lexed
+    Interpolated(Vec<Fragment>),
     Nil,
     NaN,
     UnderScore,
+    Semicolon,
+    // Ends a statement like ; unless the parser finds that it continues on the next line
+    Newline,
+    // Only produced by the parser once all tokens have been used up
+    Eof,
 }
 
 impl std::fmt::Display for Token {
@@ -66,11 +101,25 @@ impl std::fmt::Display for Token {
         match self {
             Token::Float(float) => inner_write(float, f),
             Token::Int(int) => inner_write(int, f),
-            Token::String(string) => inner_write(format!("\"{}\"", string), f),
+            Token::BigInt(int) => inner_write(int, f),
+            Token::Fixed(int, ty) => inner_write(format!("{}{}", int, ty.name()), f),
+            Token::Imaginary(im) => inner_write(format!("{}i", im), f),
+            Token::Decimal(decimal) => inner_write(format!("{}d", decimal), f),
+            Token::Time(literal) => inner_write(literal, f),
+            Token::String(string) => inner_write(format!("\"{}\"", escape(string)), f),
+            Token::Interpolated(_) => inner_write("interpolated string", f),
             Token::Undef => inner_write(UNDEF, f),
+            Token::Config => inner_write(CONFIG, f),
+            Token::Simplify => inner_write(SIMPLIFY, f),
+            Token::To => inner_write(" to ", f),
             Token::Comma => inner_write(',', f),
+            Token::Colon => inner_write(':', f),
+            Token::Dot => inner_write('.', f),
             Token::Ident(ident) => inner_write(ident, f),
             Token::Let => inner_write(LET, f),
+            Token::In => inner_write(IN, f),
+            Token::Where => inner_write(WHERE, f),
+            Token::Fn => inner_write(FN, f),
             Token::Assign => inner_write('=', f),
             Token::UnderScore => inner_write('_', f),
             Token::Mod => inner_write('%', f),
@@ -81,6 +130,7 @@ impl std::fmt::Display for Token {
             Token::Pow => inner_write("**", f),
             Token::Not => inner_write('!', f),
             Token::Pipe => inner_write('|', f),
+            Token::Pipeline => inner_write("|>", f),
             Token::BitAnd => inner_write('&', f),
             Token::BitXor => inner_write('^', f),
             Token::LParen => inner_write('(', f),
@@ -102,37 +152,312 @@ impl std::fmt::Display for Token {
             Token::Or => inner_write(" or ", f),
             Token::And => inner_write(" and ", f),
             Token::If => inner_write(IF, f),
+            Token::Match => inner_write(MATCH, f),
+            Token::Arrow => inner_write(" => ", f),
             Token::Then => inner_write(THEN, f),
             Token::Else => inner_write(ELSE, f),
             Token::Nil => inner_write(NIL, f),
             Token::NaN => inner_write(NAN, f),
+            Token::Semicolon => inner_write(';', f),
+            Token::Newline => inner_write("end of line", f),
+            Token::Eof => inner_write("end of input", f),
         }
     }
 }
 
+// Text or an expression in an interpolated string
+#[derive(PartialEq, Debug, Clone)]
+pub enum Fragment {
+    Text(String),
+    // With the format spec after the :, eg. .2 in {x:.2}
+    Code(Vec<(Token, Span)>, Option<String>),
+}
+
+// Byte offsets of a token in the input
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+// Input which couldn't be turned into a token, eg. `$` or an unterminated string
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub msg: &'static str,
+    pub text: String,
+    pub span: Span,
+}
+
+impl Error for LexError {}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, got: {}", self.msg, self.text)
+    }
+}
+
+// Like a Peekable<Chars> but keeps track of the byte offset
+#[derive(Debug, Clone)]
+struct Cursor<'a> {
+    chars: Chars<'a>,
+    current: Option<char>,
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        let mut chars = input.chars();
+        Self {
+            current: chars.next(),
+            chars,
+            offset: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&char> {
+        self.current.as_ref()
+    }
+
+    fn next_if(&mut self, func: impl FnOnce(&char) -> bool) -> Option<char> {
+        if self.current.as_ref().is_some_and(func) {
+            self.next()
+        } else {
+            None
+        }
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|c| c == expected)
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.current?;
+        self.offset += next.len_utf8();
+        self.current = self.chars.next();
+        Some(next)
+    }
+}
+
 #[derive(Debug)]
 pub struct Tokenizer<'a> {
-    input: Peekable<Chars<'a>>,
+    source: &'a str,
+    input: Cursor<'a>,
 }
 
 impl<'a> Tokenizer<'a> {
-    pub fn new(input: Peekable<Chars<'a>>) -> Self {
-        Self { input }
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            input: Cursor::new(source),
+        }
+    }
+
+    // Numeric literals, eg. 1_000, 0o755, .5, 6.02e23, 4.7k, 0xffu8 or 2.5i
+    fn number(&mut self, first: char) -> Result<Token, &'static str> {
+        let mut word = first.to_string();
+        let mut ahead = self.input.clone();
+        while let Some(c) = ahead.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.')) {
+            word.push(c);
+            // Exponents can be signed, eg. 1e-9
+            if matches!(c, 'e' | 'E') {
+                let mut sign = ahead.clone();
+                if sign.next_if(|c| matches!(c, '+' | '-')).is_some()
+                    && sign.peek().is_some_and(char::is_ascii_digit)
+                {
+                    word.extend(ahead.next());
+                }
+            }
+        }
+        // Malformed numbers are skipped entirely. The first character has already been consumed
+        let (token, len) = number_literal(&word).inspect_err(|_| {
+            for _ in 1..word.len() {
+                self.input.next();
+            }
+        })?;
+        for _ in 1..len {
+            self.input.next();
+        }
+        Ok(self.percent(token))
     }
-}
 
-impl<'a> Iterator for Tokenizer<'a> {
-    type Item = Token;
+    // A % directly after a number makes it a percentage, unless an operand follows as in 5%2
+    fn percent(&mut self, token: Token) -> Token {
+        let mut ahead = self.input.clone();
+        if ahead.next_if_eq(&'%').is_none() {
+            return token;
+        }
+        while ahead.next_if(|c| c.is_whitespace()).is_some() {}
+        if ahead.peek().is_some_and(|c| {
+            c.is_alphanumeric() || matches!(c, '(' | '[' | '.' | '_' | '"' | '\'' | '@')
+        }) {
+            return token;
+        }
+        let percent = match token {
+            Token::Int(int) => Token::Float(int as f64 / 100.0),
+            Token::BigInt(int) => Token::Float(int.to_f64().unwrap_or(f64::NAN) / 100.0),
+            Token::Float(float) => Token::Float(float / 100.0),
+            Token::Decimal(decimal) => Token::Decimal(decimal / Decimal::ONE_HUNDRED),
+            token => return token,
+        };
+        self.input.next();
+        percent
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let next = self.input.next()?;
-        Some(match next {
-            ' ' => {
-                while self.input.peek().is_some_and(|c| c.is_whitespace()) {
-                    self.input.next();
+    // Up to the closing quote, decoding escapes such as \n, \" or \u{1F600}
+    fn string(&mut self, quote: char) -> Result<Token, &'static str> {
+        let mut string = String::new();
+        // Reported once the whole string has been read, so the rest of it isn't lexed as code
+        let mut invalid = None;
+        loop {
+            match self.input.next() {
+                None => return Err("Unterminated string"),
+                Some(c) if c == quote => break,
+                Some('\\') => match self.escape() {
+                    Ok(c) => string.push(c),
+                    Err(msg) => invalid = invalid.or(Some(msg)),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+        match invalid {
+            Some(msg) => Err(msg),
+            None => Ok(Token::String(string)),
+        }
+    }
+
+    // f"total {x * 1.2:.2} EUR", where {{ and }} are braces
+    fn interpolated(&mut self, quote: char) -> Result<Token, &'static str> {
+        let mut fragments = vec![];
+        let mut text = String::new();
+        let mut invalid = None;
+        loop {
+            match self.input.next() {
+                None => return Err("Unterminated string"),
+                Some(c) if c == quote => break,
+                Some('\\') => match self.escape() {
+                    Ok(c) => text.push(c),
+                    Err(msg) => invalid = invalid.or(Some(msg)),
+                },
+                Some('{') if self.input.next_if_eq(&'{').is_some() => text.push('{'),
+                Some('}') if self.input.next_if_eq(&'}').is_some() => text.push('}'),
+                Some('{') => {
+                    if !text.is_empty() {
+                        fragments.push(Fragment::Text(std::mem::take(&mut text)));
+                    }
+                    match self.code() {
+                        Ok(fragment) => fragments.push(fragment),
+                        Err(msg) => invalid = invalid.or(Some(msg)),
+                    }
                 }
-                return self.next();
+                Some('}') => {
+                    invalid = invalid.or(Some("Unmatched } in string, use }} for a brace"))
+                }
+                Some(c) => text.push(c),
             }
+        }
+        if !text.is_empty() {
+            fragments.push(Fragment::Text(text));
+        }
+        match invalid {
+            Some(msg) => Err(msg),
+            None => Ok(Token::Interpolated(fragments)),
+        }
+    }
+
+    // An expression up to the closing }, or up to a : that isn't nested which starts the spec
+    fn code(&mut self) -> Result<Fragment, &'static str> {
+        let start = self.input.offset;
+        let mut depth = 0usize;
+        let end = loop {
+            let offset = self.input.offset;
+            match self.input.next().ok_or("Unterminated string")? {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' => depth = depth.saturating_sub(1),
+                '}' | ':' if depth == 0 => break offset,
+                '}' => depth -= 1,
+                // Strings inside can contain braces and quotes
+                quote @ ('"' | '\'') => loop {
+                    match self.input.next().ok_or("Unterminated string")? {
+                        '\\' => {
+                            self.input.next();
+                        }
+                        c if c == quote => break,
+                        _ => (),
+                    }
+                },
+                _ => (),
+            }
+        };
+        let spec = if self.source[end..].starts_with(':') {
+            let mut spec = String::new();
+            loop {
+                match self.input.next().ok_or("Unterminated string")? {
+                    '}' => break,
+                    c => spec.push(c),
+                }
+            }
+            if !strings::valid_spec(&spec) {
+                return Err("Invalid format spec, expected eg. {x:.2} or {x:>8}");
+            }
+            Some(spec)
+        } else {
+            None
+        };
+        if self.source[start..end].trim().is_empty() {
+            return Err("Expected expression between { and }");
+        }
+        // Lexed from where it is in the input so that the spans are right
+        let mut input = Cursor::new(&self.source[..end]);
+        while input.offset < start {
+            input.next();
+        }
+        let tokenizer = Tokenizer {
+            source: &self.source[..end],
+            input,
+        };
+        let tokens = tokenizer
+            .filter(|token| !matches!(token, Ok((Token::Newline, _))))
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.msg)?;
+        Ok(Fragment::Code(tokens, spec))
+    }
+
+    fn escape(&mut self) -> Result<char, &'static str> {
+        Ok(match self.input.next().ok_or("Unterminated string")? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            'u' => {
+                let invalid = "Invalid unicode escape, expected eg. \\u{e9}";
+                self.input.next_if_eq(&'{').ok_or(invalid)?;
+                let mut hex = String::new();
+                while let Some(c) = self.input.next_if(char::is_ascii_hexdigit) {
+                    hex.push(c);
+                }
+                self.input.next_if_eq(&'}').ok_or(invalid)?;
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(invalid)?
+            }
+            c @ ('\\' | '"' | '\'') => c,
+            _ => return Err("Unknown escape sequence"),
+        })
+    }
+
+    fn token(&mut self, next: char) -> Result<Token, &'static str> {
+        Ok(match next {
             '>' => match self.input.peek() {
                 Some('>') => {
                     self.input.next();
@@ -160,6 +485,10 @@ impl<'a> Iterator for Tokenizer<'a> {
                     self.input.next();
                     Token::Eq
                 }
+                Some('>') => {
+                    self.input.next();
+                    Token::Arrow
+                }
                 _ => Token::Assign,
             },
             '!' => match self.input.peek() {
@@ -170,15 +499,17 @@ impl<'a> Iterator for Tokenizer<'a> {
                 _ => Token::Not,
             },
             '_' => {
-                while self.input.peek().is_some_and(|c| c.is_alphanumeric()) {
-                    self.next();
-                }
+                while self.input.next_if(|c| c.is_alphanumeric()).is_some() {}
                 Token::UnderScore
             }
             '&' => Token::BitAnd,
             '^' => Token::BitXor,
+            '|' if self.input.next_if_eq(&'>').is_some() => Token::Pipeline,
             '|' => Token::Pipe,
             ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '\n' => Token::Newline,
             '/' => Token::Div,
             '+' => Token::Plus,
             '-' => Token::Minus,
@@ -197,99 +528,25 @@ impl<'a> Iterator for Tokenizer<'a> {
             ']' => Token::RBracket,
             '{' => Token::LCurly,
             '}' => Token::RCurly,
-            '0'..='9' => {
-                // Check if it's hex
-                if next == '0'
-                    && self
-                        .input
-                        .peek()
-                        .is_some_and(|c| matches!(c, 'x' | 'X' | 'b' | 'B'))
-                {
-                    match self.input.next().unwrap() {
-                        'x' | 'X' => {
-                            let mut hex = String::new();
-                            while self.input.peek().is_some_and(|c| {
-                                c.is_numeric() || matches!(c, 'a'..='f' | 'A'..='F')
-                            }) {
-                                hex.push(self.input.next().unwrap());
-                            }
-                            if hex.is_empty() {
-                                Token::Int(0)
-                            } else {
-                                Token::Int(i64::from_str_radix(&hex, 16).unwrap())
-                            }
-                        }
-                        'b' | 'B' => {
-                            let mut hex = String::new();
-                            while self.input.peek().is_some_and(|c| matches!(c, '0' | '1')) {
-                                hex.push(self.input.next().unwrap());
-                            }
-                            if hex.is_empty() {
-                                Token::Int(0)
-                            } else {
-                                Token::Int(i64::from_str_radix(&hex, 2).unwrap())
-                            }
-                        }
-                        _ => unreachable!(),
-                    }
-                } else {
-                    let mut num = next.to_string();
-                    while self.input.peek().is_some_and(|c| c.is_ascii_digit()) {
-                        num.push(self.input.next().unwrap());
-                    }
-                    if self.input.peek().is_some_and(|c| *c == '.') {
-                        num.push(self.input.next().unwrap());
-                        while self.input.peek().is_some_and(|c| c.is_ascii_digit()) {
-                            num.push(self.input.next().unwrap());
-                        }
-                        Token::Float(num.parse().unwrap())
-                    } else {
-                        Token::Int(num.parse().unwrap())
-                    }
-                }
-            }
-            '"' => {
-                let mut string = String::new();
-                while self.input.peek().is_some_and(|c| *c != '"') {
-                    let next = self.input.next().unwrap();
-                    if next == '\\' {
-                        if let Some(char) = self.input.next() {
-                            string.push('\\');
-                            string.push(char);
-                        } else {
-                            return None;
-                        }
-                    } else {
-                        string.push(next);
+            '0'..='9' => self.number(next)?,
+            '.' if self.input.peek().is_some_and(char::is_ascii_digit) => self.number(next)?,
+            '.' => Token::Dot,
+            '@' => {
+                let mut literal = String::new();
+                while let Some(c) = self.input.peek().copied() {
+                    // Durations can't contain signs so that `@P1D-@PT1H` is a subtraction
+                    let sign = matches!(c, '-' | '+') && !literal.starts_with('P');
+                    if !(c.is_ascii_alphanumeric() || matches!(c, ':' | '.') || sign) {
+                        break;
                     }
+                    literal.push(c);
+                    self.input.next();
                 }
-                if let Some('"') = self.input.next() {
-                    Token::String(string)
-                } else {
-                    return None;
-                }
-            }
-            '\'' => {
-                let mut string = String::new();
-                while self.input.peek().is_some_and(|c| *c != '\'') {
-                    let next = self.input.next().unwrap();
-                    if next == '\\' {
-                        if let Some(char) = self.input.next() {
-                            string.push('\\');
-                            string.push(char);
-                        } else {
-                            return None;
-                        }
-                    } else {
-                        string.push(next);
-                    }
-                }
-                if let Some('\'') = self.input.next() {
-                    Token::String(string)
-                } else {
-                    return None;
-                }
+                Token::Time(datetime::parse(&literal).ok_or("Invalid date, time or duration")?)
             }
+            '"' | '\'' => self.string(next)?,
+            'f' if self.input.next_if_eq(&'"').is_some() => self.interpolated('"')?,
+            'f' if self.input.next_if_eq(&'\'').is_some() => self.interpolated('\'')?,
             'A'..='Z' | 'a'..='z' => {
                 let mut ident = next.to_string();
                 while self
@@ -301,10 +558,17 @@ impl<'a> Iterator for Tokenizer<'a> {
                 }
                 match ident.as_str() {
                     LET => Token::Let,
+                    IN => Token::In,
+                    WHERE => Token::Where,
+                    FN => Token::Fn,
                     UNDEF => Token::Undef,
+                    CONFIG => Token::Config,
+                    SIMPLIFY => Token::Simplify,
+                    TO => Token::To,
                     TRUE => Token::True,
                     FALSE => Token::False,
                     IF => Token::If,
+                    MATCH => Token::Match,
                     THEN => Token::Then,
                     ELSE => Token::Else,
                     NIL => Token::Nil,
@@ -314,21 +578,223 @@ impl<'a> Iterator for Tokenizer<'a> {
                     _ => Token::Ident(ident),
                 }
             }
-            _ => return None, // Unknown chars just end the parsing. Not sure if good or
-                              // bad... In any case it's probably a bit confusing for the user. Definitely
-                              // confused me lol.
+            _ => return Err("Unexpected character"),
         })
     }
 }
 
+impl Iterator for Tokenizer<'_> {
+    type Item = Result<(Token, Span), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self
+                .input
+                .next_if(|c| c.is_whitespace() && *c != '\n')
+                .is_some()
+            {}
+            // Comments run until the end of the line
+            if self.input.next_if_eq(&'#').is_none() {
+                break;
+            }
+            while self.input.next_if(|c| *c != '\n').is_some() {}
+        }
+        let start = self.input.offset;
+        let next = self.input.next()?;
+        let token = self.token(next);
+        let span = Span::new(start, self.input.offset);
+        Some(match token {
+            Ok(token) => Ok((token, span)),
+            Err(msg) => Err(LexError {
+                msg,
+                text: self.source[start..span.end].to_string(),
+                span,
+            }),
+        })
+    }
+}
+
+// A string as it would be written in quotes, the reverse of reading a string literal
+pub fn escape(string: &str) -> String {
+    let mut escaped = String::new();
+    for c in string.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' | '"' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// SI prefixes that can directly follow a number, eg. 4.7k or 3n. Milli is left out so that 5m is
+// read as metres
+const SI_PREFIXES: [(&str, i32); 9] = [
+    ("f", -15),
+    ("p", -12),
+    ("n", -9),
+    ("u", -6),
+    ("k", 3),
+    ("M", 6),
+    ("G", 9),
+    ("T", 12),
+    ("P", 15),
+];
+
+// Parses the number at the start of word, returning it and its length. Whatever follows, eg. a
+// unit, is left for the next token
+fn number_literal(word: &str) -> Result<(Token, usize), &'static str> {
+    let radix = match word.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let (int, len) = digits(&word[2..], radix)?;
+        if int.is_empty() {
+            return Err("Expected digits after the radix prefix");
+        }
+        let end = 2 + len;
+        let suffix = suffix(&word[end..]);
+        return match IntType::from_name(suffix) {
+            Some(ty) => Ok((fixed_token(&int, radix, ty)?, end + suffix.len())),
+            None if suffix.is_empty() && !word[end..].starts_with('_') => {
+                Ok((int_token(&int, radix)?, end))
+            }
+            None => Err("Invalid digit in number"),
+        };
+    }
+
+    let (int, mut end) = digits(word, 10)?;
+    let mut mantissa = int.clone();
+    // A dot followed by a letter is left alone, eg. for methods
+    let is_fract = word[end..].starts_with('.')
+        && !word[end + 1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    if is_fract {
+        let (fract, len) = digits(&word[end + 1..], 10)?;
+        mantissa = format!("{}.{}", int, fract);
+        end += 1 + len;
+    }
+    let mut exp = None;
+    let rest = &word[end..];
+    if rest.starts_with(['e', 'E']) {
+        let sign = usize::from(rest[1..].starts_with(['+', '-']));
+        let (digits, len) = digits(&rest[1 + sign..], 10)?;
+        if !digits.is_empty() {
+            let exponent = format!("{}{}", &rest[1..1 + sign], digits);
+            exp = Some(exponent.parse::<i32>().map_err(|_| "Exponent too large")?);
+            end += 1 + sign + len;
+        }
+    }
+    if word[end..].starts_with('.') && word[end + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+        return Err("Unexpected decimal point in number");
+    }
+
+    let is_int = !is_fract && exp.is_none();
+    let scientific = |shift: i32| format!("{}e{}", mantissa, exp.unwrap_or_default() + shift);
+    let suffix = suffix(&word[end..]);
+    let token = match suffix {
+        "i" => Token::Imaginary(float(&scientific(0))?),
+        "d" if exp.is_some() => Token::Decimal(
+            Decimal::from_scientific(&scientific(0)).map_err(|_| "Decimal out of range")?,
+        ),
+        "d" => Token::Decimal(mantissa.parse().map_err(|_| "Decimal out of range")?),
+        _ => match (
+            IntType::from_name(suffix),
+            SI_PREFIXES.iter().find(|(prefix, _)| *prefix == suffix),
+        ) {
+            (Some(ty), _) if is_int => fixed_token(&int, 10, ty)?,
+            (Some(_), _) => return Err("Fixed width integers can't have a fraction or exponent"),
+            // Whole multiples stay exact, eg. 10M
+            (_, Some((_, power))) if is_int && *power > 0 => {
+                int_token(&format!("{}{}", int, "0".repeat(*power as usize)), 10)?
+            }
+            (_, Some((_, power))) => Token::Float(float(&scientific(*power))?),
+            (None, None) if is_int => return Ok((int_token(&int, 10)?, end)),
+            (None, None) => return Ok((Token::Float(float(&scientific(0))?), end)),
+        },
+    };
+    Ok((token, end + suffix.len()))
+}
+
+// Digits in the radix, separated by single underscores, and how many characters they took up
+fn digits(word: &str, radix: u32) -> Result<(String, usize), &'static str> {
+    let mut digits = String::new();
+    let mut chars = word.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_digit(radix) {
+            digits.push(c);
+        } else if c == '_' {
+            let next_is_digit = chars.peek().is_some_and(|(_, c)| c.is_digit(radix));
+            if digits.is_empty() || !next_is_digit {
+                return Err("Digit separators must be between digits");
+            }
+        } else {
+            return Ok((digits, i));
+        }
+    }
+    Ok((digits, word.len()))
+}
+
+// The letters directly after a number
+fn suffix(rest: &str) -> &str {
+    let len = rest
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(rest.len());
+    &rest[..len]
+}
+
+fn float(literal: &str) -> Result<f64, &'static str> {
+    literal
+        .parse::<f64>()
+        .ok()
+        .filter(|float| float.is_finite())
+        .ok_or("Number too large")
+}
+
+// Integer literals that don't fit into an i64 are promoted to a BigInt
+fn int_token(digits: &str, radix: u32) -> Result<Token, &'static str> {
+    match i64::from_str_radix(digits, radix) {
+        Ok(int) => Ok(Token::Int(int)),
+        Err(_) => BigInt::parse_bytes(digits.as_bytes(), radix)
+            .map(Token::BigInt)
+            .ok_or("Invalid digit in number"),
+    }
+}
+
+// Out of range literals are an error rather than silently wrapping. The magnitude of the minimum is
+// allowed so that eg. -128i8 can be written
+fn fixed_token(digits: &str, radix: u32, ty: IntType) -> Result<Token, &'static str> {
+    let int = BigInt::parse_bytes(digits.as_bytes(), radix).ok_or("Invalid digit in number")?;
+    if ty.contains(&int) || ty.contains(&-&int) {
+        Ok(Token::Fixed(int, ty))
+    } else {
+        Err("Integer literal out of range for its type")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn lex(str: &str) -> Vec<Token> {
+        Tokenizer::new(str)
+            .map(|token| token.map(|(token, _)| token))
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
     #[test]
     fn test_add() {
-        let str = "1.3+3.2".chars().peekable();
-        let tokenizer = Tokenizer::new(str).collect::<Vec<_>>();
+        let tokenizer = lex("1.3+3.2");
         assert_eq!(
             tokenizer,
             vec![Token::Float(1.3), Token::Plus, Token::Float(3.2),]
@@ -337,12 +803,10 @@ mod tests {
 
     #[test]
     fn divide() {
-        let str = "13/32".chars().peekable();
-        let tokenizer = Tokenizer::new(str).collect::<Vec<_>>();
+        let tokenizer = lex("13/32");
         assert_eq!(tokenizer, vec![Token::Int(13), Token::Div, Token::Int(32),]);
 
-        let str = "13.5/32.2".chars().peekable();
-        let tokenizer = Tokenizer::new(str).collect::<Vec<_>>();
+        let tokenizer = lex("13.5/32.2");
         assert_eq!(
             tokenizer,
             vec![Token::Float(13.5), Token::Div, Token::Float(32.2),]
@@ -351,8 +815,7 @@ mod tests {
 
     #[test]
     fn test_space() {
-        let str = "13.5    + 12.5 *     30.5*10".chars().peekable();
-        let tokens = Tokenizer::new(str).collect::<Vec<_>>();
+        let tokens = lex("13.5    + 12.5 *     30.5*10");
         assert_eq!(
             tokens,
             vec![
@@ -370,51 +833,239 @@ mod tests {
     #[test]
     fn test_tokenizer() {
         let str = "10 + 5";
-        let mut tokenizer = Tokenizer::new(str.chars().peekable());
-        assert_eq!(tokenizer.next(), Some(Token::Int(10)));
+        let mut tokenizer = Tokenizer::new(str);
+        assert_eq!(
+            tokenizer.next(),
+            Some(Ok((Token::Int(10), Span::new(0, 2))))
+        );
     }
 
     #[test]
     fn test_hex() {
         let str = "0x1ff";
 
-        let mut tokenizer = Tokenizer::new(str.chars().peekable());
-        assert_eq!(tokenizer.next(), Some(Token::Int(511)));
+        let mut tokenizer = Tokenizer::new(str);
+        assert_eq!(
+            tokenizer.next(),
+            Some(Ok((Token::Int(511), Span::new(0, 5))))
+        );
     }
 
     #[test]
     fn test_bin() {
         let str = "0b1100";
 
-        let mut tokenizer = Tokenizer::new(str.chars().peekable());
-        assert_eq!(tokenizer.next(), Some(Token::Int(12)));
+        let mut tokenizer = Tokenizer::new(str);
+        assert_eq!(
+            tokenizer.next(),
+            Some(Ok((Token::Int(12), Span::new(0, 6))))
+        );
     }
 
     #[test]
-    fn alpha_underscore_idents() {
-        let str = "foo_bar1337";
+    fn test_big_literals() {
+        let str = "0xffffffffffffffffff 99999999999999999999";
 
-        let mut tokenizer = Tokenizer::new(str.chars().peekable());
+        let tokens = lex(str);
         assert_eq!(
-            tokenizer.next(),
-            Some(Token::Ident("foo_bar1337".to_string()))
+            tokens,
+            vec![
+                Token::BigInt(BigInt::parse_bytes(b"ffffffffffffffffff", 16).unwrap()),
+                Token::BigInt(BigInt::parse_bytes(b"99999999999999999999", 10).unwrap()),
+            ]
         );
     }
 
     #[test]
-    fn test_underscore() {
-        let str = "_foobar";
+    fn test_imaginary() {
+        let str = "3 + 2.5i * 1i";
 
-        let mut tokenizer = Tokenizer::new(str.chars().peekable());
-        assert_eq!(tokenizer.next(), Some(Token::UnderScore),);
+        let tokens = lex(str);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Int(3),
+                Token::Plus,
+                Token::Imaginary(2.5),
+                Token::Mult,
+                Token::Imaginary(1.0),
+            ]
+        );
     }
 
     #[test]
-    fn test_if_else() {
-        let str = "if 1 == 3 then 3 % 3 else \"lol\"".chars().peekable();
-        let tokens = Tokenizer::new(str).collect::<Vec<_>>();
+    fn test_decimal() {
+        let str = "0.1d + 2d * dx";
+
+        let tokens = lex(str);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Decimal(Decimal::new(1, 1)),
+                Token::Plus,
+                Token::Decimal(Decimal::new(2, 0)),
+                Token::Mult,
+                Token::Ident("dx".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_time() {
+        let str = "@2024-01-15T09:30+02:00 - @P1DT2H";
+
+        let tokens = lex(str);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Time(datetime::parse("2024-01-15T09:30:00+02:00").unwrap()),
+                Token::Minus,
+                Token::Time(datetime::Literal::Duration(chrono::TimeDelta::hours(26))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_int_types() {
+        let str = "0xffu8 + 5i16 - 0b1u64 + 1u9";
+
+        let tokens = lex(str);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Fixed(BigInt::from(255), IntType::U8),
+                Token::Plus,
+                Token::Fixed(BigInt::from(5), IntType::I16),
+                Token::Minus,
+                Token::Fixed(BigInt::from(1), IntType::U64),
+                Token::Plus,
+                Token::Int(1),
+                Token::Ident("u9".to_string()),
+            ]
+        );
+        assert_eq!(
+            Tokenizer::new("256u8").next(),
+            Some(Err(LexError {
+                msg: "Integer literal out of range for its type",
+                text: "256u8".to_string(),
+                span: Span::new(0, 5),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let str = "1e-9 + 6.02E23 * 0o755 - 1_000_000 / .5 + 0xff_ff";
+        let tokens = lex(str);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Float(1e-9),
+                Token::Plus,
+                Token::Float(6.02e23),
+                Token::Mult,
+                Token::Int(0o755),
+                Token::Minus,
+                Token::Int(1_000_000),
+                Token::Div,
+                Token::Float(0.5),
+                Token::Plus,
+                Token::Int(0xffff),
+            ]
+        );
+
+        // SI prefixes, units are separate tokens
+        let str = "4.7k 10M 3n 5ms 2e";
+        let tokens = lex(str);
         assert_eq!(
             tokens,
+            vec![
+                Token::Float(4700.0),
+                Token::Int(10_000_000),
+                Token::Float(3e-9),
+                Token::Int(5),
+                Token::Ident("ms".to_string()),
+                Token::Int(2),
+                Token::Ident("e".to_string()),
+            ]
+        );
+
+        let str = "5% * 200 + 12.5% - 10%3";
+        let tokens = lex(str);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Float(0.05),
+                Token::Mult,
+                Token::Int(200),
+                Token::Plus,
+                Token::Float(0.125),
+                Token::Minus,
+                Token::Int(10),
+                Token::Mod,
+                Token::Int(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_numbers() {
+        let malformed = |str: &str| match Tokenizer::new(str).next() {
+            Some(Err(err)) => (err.text, err.msg),
+            token => panic!("Expected a malformed number, got {:?}", token),
+        };
+        assert_eq!(
+            malformed("0x + 1"),
+            ("0x".to_string(), "Expected digits after the radix prefix")
+        );
+        assert_eq!(
+            malformed("0b102"),
+            ("0b102".to_string(), "Invalid digit in number")
+        );
+        assert_eq!(
+            malformed("1__000"),
+            (
+                "1__000".to_string(),
+                "Digit separators must be between digits"
+            )
+        );
+        assert_eq!(
+            malformed("1.2.3"),
+            ("1.2.3".to_string(), "Unexpected decimal point in number")
+        );
+        assert_eq!(
+            malformed("1e999"),
+            ("1e999".to_string(), "Number too large")
+        );
+        assert_eq!(
+            lex("0xffffffffffffffffff"),
+            vec![Token::BigInt(BigInt::from(
+                u128::from(u64::MAX) << 8 | 0xff
+            ))]
+        );
+    }
+
+    #[test]
+    fn alpha_underscore_idents() {
+        assert_eq!(
+            lex("foo_bar1337"),
+            vec![Token::Ident("foo_bar1337".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_underscore() {
+        let mut tokenizer = Tokenizer::new("_foobar");
+        assert_eq!(
+            tokenizer.next(),
+            Some(Ok((Token::UnderScore, Span::new(0, 7))))
+        );
+    }
+
+    #[test]
+    fn test_if_else() {
+        assert_eq!(
+            lex("if 1 == 3 then 3 % 3 else \"lol\""),
             vec![
                 Token::If,
                 Token::Int(1),
@@ -432,8 +1083,190 @@ mod tests {
 
     #[test]
     fn test_nan_and_nil() {
-        let str = "NaN == nil".chars().peekable();
-        let tokens = Tokenizer::new(str).collect::<Vec<_>>();
-        assert_eq!(tokens, vec![Token::NaN, Token::Eq, Token::Nil,]);
+        assert_eq!(lex("NaN == nil"), vec![Token::NaN, Token::Eq, Token::Nil]);
+    }
+
+    #[test]
+    fn test_records() {
+        assert_eq!(
+            lex("{rate: .5}.rate"),
+            vec![
+                Token::LCurly,
+                Token::Ident("rate".to_string()),
+                Token::Colon,
+                Token::Float(0.5),
+                Token::RCurly,
+                Token::Dot,
+                Token::Ident("rate".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let input = r#""a\tb\n" 'it\'s' "\u{e9}\"\\\0""#;
+        assert_eq!(
+            lex(input),
+            vec![
+                Token::String("a\tb\n".to_string()),
+                Token::String("it's".to_string()),
+                Token::String("é\"\\\0".to_string()),
+            ]
+        );
+        // Escaping gives back something that lexes to the same string
+        for string in ["a\tb\n", "\"quoted\" \\ \u{7}", "é'"] {
+            let quoted = format!("\"{}\"", escape(string));
+            assert_eq!(lex(&quoted), vec![Token::String(string.to_string())]);
+        }
+        assert_eq!(escape("\u{7}"), "\\u{7}");
+    }
+
+    #[test]
+    fn test_pipeline() {
+        assert_eq!(
+            lex("xs |> f || |x|x"),
+            vec![
+                Token::Ident("xs".to_string()),
+                Token::Pipeline,
+                Token::Ident("f".to_string()),
+                Token::Pipe,
+                Token::Pipe,
+                Token::Pipe,
+                Token::Ident("x".to_string()),
+                Token::Pipe,
+                Token::Ident("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(
+            lex("match x { _ => x == 1 }"),
+            vec![
+                Token::Match,
+                Token::Ident("x".to_string()),
+                Token::LCurly,
+                Token::UnderScore,
+                Token::Arrow,
+                Token::Ident("x".to_string()),
+                Token::Eq,
+                Token::Int(1),
+                Token::RCurly,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interpolated() {
+        assert_eq!(
+            lex("f\"a {x:.2}}}\" + f'{f(\"}\")}'"),
+            vec![
+                Token::Interpolated(vec![
+                    Fragment::Text("a ".to_string()),
+                    Fragment::Code(
+                        vec![(Token::Ident("x".to_string()), Span::new(5, 6))],
+                        Some(".2".to_string())
+                    ),
+                    Fragment::Text("}".to_string()),
+                ]),
+                Token::Plus,
+                Token::Interpolated(vec![Fragment::Code(
+                    vec![
+                        (Token::Ident("f".to_string()), Span::new(19, 20)),
+                        (Token::LParen, Span::new(20, 21)),
+                        (Token::String("}".to_string()), Span::new(21, 24)),
+                        (Token::RParen, Span::new(24, 25)),
+                    ],
+                    None
+                )]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_separators_and_comments() {
+        assert_eq!(
+            lex("let a = 1; a # the answer\n\t# nothing here\n2"),
+            vec![
+                Token::Let,
+                Token::Ident("a".to_string()),
+                Token::Assign,
+                Token::Int(1),
+                Token::Semicolon,
+                Token::Ident("a".to_string()),
+                Token::Newline,
+                Token::Newline,
+                Token::Int(2),
+            ]
+        );
+        assert_eq!(lex("\"#1\""), vec![Token::String("#1".to_string())]);
+    }
+
+    #[test]
+    fn test_lex_errors() {
+        let errors = |str: &str| {
+            Tokenizer::new(str)
+                .filter_map(Result::err)
+                .map(|err| (err.msg, err.span))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("2 $ 3 + \"abc"),
+            vec![
+                ("Unexpected character", Span::new(2, 3)),
+                ("Unterminated string", Span::new(8, 12)),
+            ]
+        );
+        assert_eq!(
+            errors("€ + @2024-13-01"),
+            vec![
+                ("Unexpected character", Span::new(0, 3)),
+                ("Invalid date, time or duration", Span::new(6, 17)),
+            ]
+        );
+        assert_eq!(
+            errors(r#""\q" + "\u{d800}" + "\u{e9""#),
+            vec![
+                ("Unknown escape sequence", Span::new(0, 4)),
+                (
+                    "Invalid unicode escape, expected eg. \\u{e9}",
+                    Span::new(7, 17)
+                ),
+                (
+                    "Invalid unicode escape, expected eg. \\u{e9}",
+                    Span::new(20, 27)
+                ),
+            ]
+        );
+        assert_eq!(
+            errors("f\"{}\" f\"{1:x}\" f\"}\" f\"{1 $}\" f\"{1:>99999999999}\""),
+            vec![
+                ("Expected expression between { and }", Span::new(0, 5)),
+                (
+                    "Invalid format spec, expected eg. {x:.2} or {x:>8}",
+                    Span::new(6, 14)
+                ),
+                (
+                    "Unmatched } in string, use }} for a brace",
+                    Span::new(15, 19)
+                ),
+                ("Unexpected character", Span::new(20, 28)),
+                (
+                    "Invalid format spec, expected eg. {x:.2} or {x:>8}",
+                    Span::new(29, 48)
+                ),
+            ]
+        );
+        // Lexing carries on after an error
+        let spans = Tokenizer::new("1 ? 22")
+            .map(|token| match token {
+                Ok((_, span)) | Err(LexError { span, .. }) => span,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![Span::new(0, 1), Span::new(2, 3), Span::new(4, 6)]
+        );
     }
 }
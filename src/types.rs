@@ -0,0 +1,943 @@
+use std::{cell::OnceCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    interpreter::{InterpretError, Stmt, Value},
+    parse::{self, Definition, Expr, Func, Part, Pattern},
+    scope::Scope,
+    token::Token,
+};
+
+// What the checker knows about a value. Numbers of every kind, including quantities, are one
+// type since they mix freely
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Num,
+    Bool,
+    Str,
+    List(Box<Type>),
+    Tuple(Vec<Type>),
+    Fn(Vec<Type>, Box<Type>),
+    // Not known yet while inferring, in a signature it can be any type
+    Var(usize),
+    // Dates, records, nil and anything else the checker doesn't follow, which fits with every type
+    Any,
+}
+
+// The type of a function, inferred the first time it's needed and shared by clones
+#[derive(Debug, Clone, Default)]
+pub struct Signature(Rc<OnceCell<Type>>);
+
+impl Signature {
+    pub fn get(&self, infer: impl FnOnce() -> Type) -> Type {
+        self.0.get_or_init(infer).clone()
+    }
+}
+
+// Checks a statement before it runs, including the bodies of functions it defines which might
+// not run until much later
+pub fn check(stmt: &Stmt, scope: &Scope) -> Result<(), InterpretError> {
+    Checker::new(scope).stmt(stmt).map(|_| ())
+}
+
+// The type of a function given what it captured and the functions defined along with it.
+// One that doesn't check takes and gives anything, the interpreter reports the error if it's
+// ever reached
+pub fn signature(
+    parameters: &[Pattern],
+    body: &Expr,
+    closure: &Scope,
+    group: &[Definition],
+) -> Type {
+    let mut checker = Checker::new(closure);
+    let ty = checker
+        .group(group)
+        .and_then(|_| checker.function(parameters, body));
+    match ty {
+        Ok(ty) => rename(&checker.zonk(&ty), &mut HashMap::new()),
+        Err(_) => Type::Fn(vec![Type::Any; parameters.len()], Box::new(Type::Any)),
+    }
+}
+
+// Numbers the variables of a type in the order they appear, so they're shown as a, b, ..
+fn rename(ty: &Type, names: &mut HashMap<usize, usize>) -> Type {
+    match ty {
+        Type::Var(var) => {
+            let len = names.len();
+            Type::Var(*names.entry(*var).or_insert(len))
+        }
+        Type::List(elem) => Type::List(Box::new(rename(elem, names))),
+        Type::Tuple(elems) => Type::Tuple(elems.iter().map(|elem| rename(elem, names)).collect()),
+        Type::Fn(params, ret) => Type::Fn(
+            params.iter().map(|param| rename(param, names)).collect(),
+            Box::new(rename(ret, names)),
+        ),
+        ty => ty.clone(),
+    }
+}
+
+struct Checker<'a> {
+    // Looked up for names that aren't defined in the code being checked
+    scope: &'a Scope,
+    // What each type variable has turned out to be
+    subst: Vec<Option<Type>>,
+    // Names defined in the code being checked, innermost last, with the variables of their
+    // type that can be different at every use
+    locals: Vec<(String, Vec<usize>, Type)>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(scope: &'a Scope) -> Self {
+        Self {
+            scope,
+            subst: vec![],
+            locals: vec![],
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.subst.push(None);
+        Type::Var(self.subst.len() - 1)
+    }
+
+    // Follows variables until something other than a bound variable is found
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.subst[*var] {
+                Some(ty) => self.resolve(ty),
+                None => ty.clone(),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    // Replaces every bound variable, however deep
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::List(elem) => Type::List(Box::new(self.zonk(&elem))),
+            Type::Tuple(elems) => Type::Tuple(elems.iter().map(|elem| self.zonk(elem)).collect()),
+            Type::Fn(params, ret) => Type::Fn(
+                params.iter().map(|param| self.zonk(param)).collect(),
+                Box::new(self.zonk(&ret)),
+            ),
+            ty => ty,
+        }
+    }
+
+    fn occurs(&self, var: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => var == other,
+            Type::List(elem) => self.occurs(var, &elem),
+            Type::Tuple(elems) => elems.iter().any(|elem| self.occurs(var, elem)),
+            Type::Fn(params, ret) => {
+                params.iter().any(|param| self.occurs(var, param)) || self.occurs(var, &ret)
+            }
+            _ => false,
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), ()> {
+        match (self.resolve(a), self.resolve(b)) {
+            (Type::Any, _) | (_, Type::Any) => Ok(()),
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => {
+                // A function applied to itself would need an infinite type, which isn't
+                // followed rather than reported since it can still run
+                if !self.occurs(var, &ty) {
+                    self.subst[var] = Some(ty);
+                }
+                Ok(())
+            }
+            (Type::Num, Type::Num) | (Type::Bool, Type::Bool) | (Type::Str, Type::Str) => Ok(()),
+            (Type::List(a), Type::List(b)) => self.unify(&a, &b),
+            (Type::Tuple(a), Type::Tuple(b)) if a.len() == b.len() => a
+                .iter()
+                .zip(b.iter())
+                .try_for_each(|(a, b)| self.unify(a, b)),
+            (Type::Fn(a_params, a_ret), Type::Fn(b_params, b_ret))
+                if a_params.len() == b_params.len() =>
+            {
+                a_params
+                    .iter()
+                    .zip(b_params.iter())
+                    .try_for_each(|(a, b)| self.unify(a, b))?;
+                self.unify(&a_ret, &b_ret)
+            }
+            _ => Err(()),
+        }
+    }
+
+    // Whichever branch of an if or match is taken, the result has to be used the same way
+    fn branch(&mut self, expr: &Expr, expected: &Type, found: &Type) -> Result<(), InterpretError> {
+        self.unify(expected, found).map_err(|_| {
+            self.error(
+                expr,
+                format!(
+                    "Branches have different types, {} and {}",
+                    self.show(expected),
+                    self.show(found)
+                ),
+            )
+        })
+    }
+
+    // The type both are, or any if they differ, eg. for elements of a list
+    fn join(&mut self, a: Type, b: Type) -> Type {
+        let subst = self.subst.clone();
+        if self.unify(&a, &b).is_ok() {
+            a
+        } else {
+            self.subst = subst;
+            Type::Any
+        }
+    }
+
+    fn expect(
+        &mut self,
+        expr: &Expr,
+        what: impl Display,
+        expected: &Type,
+        found: &Type,
+    ) -> Result<(), InterpretError> {
+        self.unify(expected, found).map_err(|_| {
+            self.error(
+                expr,
+                format!(
+                    "Expected {} for {}, found {}",
+                    self.show(expected),
+                    what,
+                    self.show(found)
+                ),
+            )
+        })
+    }
+
+    fn error(&self, expr: &Expr, message: String) -> InterpretError {
+        InterpretError::TypeError(message, expr.clone())
+    }
+
+    fn show(&self, ty: &Type) -> String {
+        rename(&self.zonk(ty), &mut HashMap::new()).to_string()
+    }
+
+    // Gives a generic type new variables, so each use can fix them differently
+    fn instantiate(&mut self, vars: &[usize], ty: &Type) -> Type {
+        let fresh = vars
+            .iter()
+            .map(|var| (*var, self.fresh()))
+            .collect::<HashMap<_, _>>();
+        substitute(ty, &fresh)
+    }
+
+    fn free(&self, ty: &Type, vars: &mut Vec<usize>) {
+        match self.resolve(ty) {
+            Type::Var(var) if !vars.contains(&var) => vars.push(var),
+            Type::List(elem) => self.free(&elem, vars),
+            Type::Tuple(elems) => elems.iter().for_each(|elem| self.free(elem, vars)),
+            Type::Fn(params, ret) => {
+                params.iter().for_each(|param| self.free(param, vars));
+                self.free(&ret, vars);
+            }
+            _ => {}
+        }
+    }
+
+    // Defines a name with the variables of its type that nothing else refers to made generic
+    fn bind(&mut self, name: &str, ty: Type) {
+        let ty = self.zonk(&ty);
+        let mut fixed = vec![];
+        for (_, generic, local) in self.locals.iter() {
+            let mut vars = vec![];
+            self.free(local, &mut vars);
+            fixed.extend(vars.into_iter().filter(|var| !generic.contains(var)));
+        }
+        let mut vars = vec![];
+        self.free(&ty, &mut vars);
+        vars.retain(|var| !fixed.contains(var));
+        self.locals.push((name.to_string(), vars, ty));
+    }
+
+    fn lookup(&mut self, name: &str) -> Type {
+        if let Some((_, vars, ty)) = self.locals.iter().rev().find(|(local, _, _)| local == name) {
+            let (vars, ty) = (vars.clone(), ty.clone());
+            return self.instantiate(&vars, &ty);
+        }
+        // Unknown names are reported when they're looked up
+        let scope = self.scope;
+        match (scope.get(name), parse::builtin(name)) {
+            (Some(value), _) => self.type_of(value),
+            (None, Some(func)) => {
+                let (params, ret) = self.builtin_signature(&func);
+                Type::Fn(params, Box::new(ret))
+            }
+            (None, None) => Type::Any,
+        }
+    }
+
+    fn type_of(&mut self, value: &Value) -> Type {
+        match value {
+            Value::Int(_)
+            | Value::Float(_)
+            | Value::BigInt(_)
+            | Value::Fixed(_, _)
+            | Value::Rational(_)
+            | Value::Complex(_)
+            | Value::Decimal(_)
+            | Value::Quantity(_, _)
+            | Value::NaN => Type::Num,
+            Value::Bool(_) => Type::Bool,
+            Value::String(_) => Type::Str,
+            Value::List(elems) => {
+                let mut ty = self.fresh();
+                for elem in elems {
+                    let elem = self.type_of(elem);
+                    ty = self.join(ty, elem);
+                }
+                Type::List(Box::new(ty))
+            }
+            Value::Tuple(elems) => {
+                Type::Tuple(elems.iter().map(|elem| self.type_of(elem)).collect())
+            }
+            Value::Fn(function) => {
+                // Its variables belong to the signature rather than this checker
+                let signature = function.signature();
+                let mut vars = vec![];
+                variables(&signature, &mut vars);
+                self.instantiate(&vars, &signature)
+            }
+            Value::Date(_)
+            | Value::DateTime(_)
+            | Value::Duration(_)
+            | Value::Record(_)
+            | Value::Unit
+            | Value::Nil => Type::Any,
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<Type, InterpretError> {
+        match stmt {
+            Stmt::Expr(expr) | Stmt::Config(_, expr) => self.infer(expr),
+            Stmt::Assign(name, expr) => {
+                let ty = self.infer(expr)?;
+                self.bind(name, ty.clone());
+                Ok(ty)
+            }
+            Stmt::Unpack(pattern, expr) => {
+                let ty = self.infer(expr)?;
+                self.pattern(pattern, &ty)?;
+                Ok(ty)
+            }
+            Stmt::Undef(names) => {
+                names.iter().for_each(|name| self.bind(name, Type::Any));
+                Ok(Type::Any)
+            }
+            Stmt::Seq(stmts) => stmts.iter().try_fold(Type::Any, |_, stmt| self.stmt(stmt)),
+            Stmt::Fn(definitions) => self.group(definitions),
+            Stmt::Simplify(_) => Ok(Type::Str),
+        }
+    }
+
+    // Functions defined together, which have one type each inside their bodies since they can
+    // call each other. Gives the type of the last one
+    fn group(&mut self, definitions: &[Definition]) -> Result<Type, InterpretError> {
+        let len = self.locals.len();
+        let types = definitions
+            .iter()
+            .map(|definition| {
+                let ty = self.fresh();
+                self.locals
+                    .push((definition.name.clone(), vec![], ty.clone()));
+                ty
+            })
+            .collect::<Vec<_>>();
+        for (definition, ty) in definitions.iter().zip(types.iter()) {
+            let found = self.function(&definition.parameters, &definition.body)?;
+            self.expect(&definition.body, &definition.name, ty, &found)?;
+        }
+        self.locals.truncate(len);
+        let mut last = Type::Any;
+        for (definition, ty) in definitions.iter().zip(types) {
+            self.bind(&definition.name, ty.clone());
+            last = ty;
+        }
+        Ok(last)
+    }
+
+    fn function(&mut self, parameters: &[Pattern], body: &Expr) -> Result<Type, InterpretError> {
+        let len = self.locals.len();
+        let params = parameters
+            .iter()
+            .map(|param| {
+                let ty = self.shape(param);
+                self.pattern(param, &ty)?;
+                Ok(ty)
+            })
+            .collect::<Result<_, _>>();
+        let ret = params.and_then(|params| Ok((params, self.infer(body)?)));
+        self.locals.truncate(len);
+        let (params, ret) = ret?;
+        Ok(Type::Fn(params, Box::new(ret)))
+    }
+
+    // A type with the shape of a parameter, eg. [a] for |[h, ..t]|. One taken apart with {}
+    // can be given a list or a tuple, so it's any
+    fn shape(&mut self, pattern: &Pattern) -> Type {
+        match pattern {
+            Pattern::List(_) => Type::List(Box::new(self.fresh())),
+            Pattern::Tuple(_) => Type::Any,
+            _ => self.fresh(),
+        }
+    }
+
+    fn infer(&mut self, expr: &Expr) -> Result<Type, InterpretError> {
+        Ok(match expr {
+            Expr::Int(_)
+            | Expr::Float(_)
+            | Expr::BigInt(_)
+            | Expr::Fixed(_, _)
+            | Expr::Imaginary(_)
+            | Expr::Decimal(_)
+            | Expr::Nan => Type::Num,
+            Expr::Bool(_) => Type::Bool,
+            Expr::String(_) => Type::Str,
+            Expr::Time(_) | Expr::Nil => Type::Any,
+            Expr::Var(name) => self.lookup(name),
+            Expr::Grouping(expr) => self.infer(expr)?,
+            Expr::Quantity(inner, _) => {
+                let ty = self.infer(inner)?;
+                self.expect(expr, "units", &Type::Num, &ty)?;
+                Type::Num
+            }
+            // Durations can be converted too
+            Expr::Convert(inner, _) => {
+                let ty = self.infer(inner)?;
+                self.expect(expr, Token::To, &Type::Num, &ty)?;
+                Type::Num
+            }
+            Expr::Binary(left, operator, right) => self.binary(expr, left, operator, right)?,
+            Expr::Unary(inner, operator) => {
+                let ty = self.infer(inner)?;
+                match (operator, self.resolve(&ty)) {
+                    (_, Type::Any) => Type::Any,
+                    // Bitwise for numbers
+                    (Token::Not, Type::Bool | Type::Num | Type::Var(_)) => ty,
+                    (Token::Not, found) => {
+                        return Err(self.error(
+                            expr,
+                            format!("Expected num or bool for !, found {}", self.show(&found)),
+                        ))
+                    }
+                    _ => {
+                        self.expect(expr, operator, &Type::Num, &ty)?;
+                        Type::Num
+                    }
+                }
+            }
+            Expr::Call(callee, args) => self.call(expr, callee, args)?,
+            Expr::Func(func, args) => self.builtin(expr, func, args)?,
+            Expr::Fun(parameters, body) => self.function(parameters, body)?,
+            Expr::If(cond, then, else_expr) => {
+                self.infer(cond)?;
+                let ty = self.infer(then)?;
+                let else_ty = self.infer(else_expr)?;
+                self.branch(else_expr, &ty, &else_ty)?;
+                ty
+            }
+            Expr::List(elems) => {
+                let mut ty = self.fresh();
+                for elem in elems {
+                    let elem = self.infer(elem)?;
+                    ty = self.join(ty, elem);
+                }
+                Type::List(Box::new(ty))
+            }
+            Expr::Tuple(elems) => Type::Tuple(
+                elems
+                    .iter()
+                    .map(|elem| self.infer(elem))
+                    .collect::<Result<_, _>>()?,
+            ),
+            // Only what the fields are made of is checked
+            Expr::Record(fields) => {
+                self.fields(fields)?;
+                Type::Any
+            }
+            Expr::Update(record, fields) => {
+                self.infer(record)?;
+                self.fields(fields)?;
+                Type::Any
+            }
+            Expr::Field(record, _) => {
+                self.infer(record)?;
+                Type::Any
+            }
+            Expr::Index(sequence, index) => {
+                let sequence = self.infer(sequence)?;
+                let index = self.infer(index)?;
+                self.element(expr, "an index", &sequence, &index)?
+            }
+            // Anything can be shown in a string
+            Expr::Interpolated(parts) => {
+                for expr in parts.iter().filter_map(Part::expr) {
+                    self.infer(expr)?;
+                }
+                Type::Str
+            }
+            Expr::Slice(sequence, bounds) => {
+                let ty = self.infer(sequence)?;
+                for bound in bounds.iter().flatten() {
+                    let bound = self.infer(bound)?;
+                    self.expect(expr, "a slice", &Type::Num, &bound)?;
+                }
+                match self.resolve(&ty) {
+                    Type::List(_) | Type::Str => ty,
+                    Type::Tuple(_) | Type::Var(_) | Type::Any => Type::Any,
+                    found => {
+                        return Err(self.error(
+                            expr,
+                            format!(
+                                "Expected a list, tuple or string for a slice, found {}",
+                                self.show(&found)
+                            ),
+                        ))
+                    }
+                }
+            }
+            Expr::Block(stmts) => {
+                let len = self.locals.len();
+                let ty = stmts.iter().try_fold(Type::Any, |_, stmt| self.stmt(stmt));
+                self.locals.truncate(len);
+                ty?
+            }
+            // The arms can give different types, eg. a number for [] and a list otherwise
+            Expr::Match(subject, arms) => {
+                let subject = self.infer(subject)?;
+                let ty = self.fresh();
+                for arm in arms {
+                    let len = self.locals.len();
+                    let body = self.pattern(&arm.pattern, &subject).and_then(|_| {
+                        if let Some(guard) = &arm.guard {
+                            self.infer(guard)?;
+                        }
+                        self.infer(&arm.body)
+                    });
+                    self.locals.truncate(len);
+                    self.branch(&arm.body, &ty, &body?)?;
+                }
+                ty
+            }
+            Expr::Let(bindings, body) => {
+                let len = self.locals.len();
+                let ty = bindings
+                    .iter()
+                    .try_for_each(|(name, expr)| {
+                        let ty = self.infer(expr)?;
+                        self.bind(name, ty);
+                        Ok(())
+                    })
+                    .and_then(|_| self.infer(body));
+                self.locals.truncate(len);
+                ty?
+            }
+        })
+    }
+
+    fn fields(&mut self, fields: &[(String, Expr)]) -> Result<(), InterpretError> {
+        fields
+            .iter()
+            .try_for_each(|(_, expr)| self.infer(expr).map(|_| ()))
+    }
+
+    fn binary(
+        &mut self,
+        expr: &Expr,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<Type, InterpretError> {
+        let left = self.infer(left)?;
+        let right = self.infer(right)?;
+        let (resolved_left, resolved_right) = (self.resolve(&left), self.resolve(&right));
+        Ok(match operator {
+            Token::And
+            | Token::Or
+            | Token::Eq
+            | Token::Ne
+            | Token::Gt
+            | Token::Gte
+            | Token::Lt
+            | Token::Lte => Type::Bool,
+            _ if resolved_left == Type::Any || resolved_right == Type::Any => Type::Any,
+            // Adding to a list appends to it, or joins them if it's another list
+            Token::Plus if matches!(resolved_left, Type::List(_)) => left,
+            Token::Plus if matches!(resolved_right, Type::List(_)) => {
+                self.expect(expr, operator, &right, &left)?;
+                right
+            }
+            Token::Plus if resolved_left == Type::Str || resolved_right == Type::Str => {
+                self.expect(expr, operator, &Type::Str, &left)?;
+                self.expect(expr, operator, &Type::Str, &right)?;
+                Type::Str
+            }
+            _ => {
+                self.expect(expr, operator, &Type::Num, &left)?;
+                self.expect(expr, operator, &Type::Num, &right)?;
+                Type::Num
+            }
+        })
+    }
+
+    fn call(&mut self, expr: &Expr, callee: &Expr, args: &[Expr]) -> Result<Type, InterpretError> {
+        let ty = self.infer(callee)?;
+        let args = args
+            .iter()
+            .map(|arg| self.infer(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        match self.resolve(&ty) {
+            Type::Any => Ok(Type::Any),
+            Type::Fn(params, _) if params.len() != args.len() => Err(self.error(
+                expr,
+                format!(
+                    "Function {} takes {} arguments but {} were provided",
+                    callee,
+                    params.len(),
+                    args.len()
+                ),
+            )),
+            Type::Fn(params, ret) => {
+                for (param, arg) in params.iter().zip(args.iter()) {
+                    self.expect(expr, callee, param, arg)?;
+                }
+                Ok(*ret)
+            }
+            Type::Var(_) => {
+                let ret = self.fresh();
+                self.unify(&ty, &Type::Fn(args, Box::new(ret.clone())))
+                    .expect("A variable unifies with anything");
+                Ok(ret)
+            }
+            found => Err(self.error(
+                expr,
+                format!(
+                    "Expected a function for {}, found {}",
+                    callee,
+                    self.show(&found)
+                ),
+            )),
+        }
+    }
+
+    fn builtin(&mut self, expr: &Expr, func: &Func, args: &[Expr]) -> Result<Type, InterpretError> {
+        let args = args
+            .iter()
+            .map(|arg| self.infer(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Reported when it's called
+        if args.len() != func.arity() {
+            return Ok(Type::Any);
+        }
+        // Take lists and tuples, which would need to know the index to give a type
+        if *func == Func::Elem {
+            return self.element(expr, func, &args[0], &args[1]);
+        }
+        if *func == Func::Set {
+            self.expect(expr, func, &Type::Num, &args[1])?;
+            return match self.resolve(&args[0]) {
+                Type::List(elem) => {
+                    self.expect(expr, func, &elem, &args[2])?;
+                    Ok(args[0].clone())
+                }
+                Type::Tuple(_) | Type::Var(_) | Type::Any => Ok(Type::Any),
+                found => Err(self.error(
+                    expr,
+                    format!(
+                        "Expected a list or tuple for {}, found {}",
+                        func,
+                        self.show(&found)
+                    ),
+                )),
+            };
+        }
+        let (params, ret) = self.builtin_signature(func);
+        for (param, arg) in params.iter().zip(args.iter()) {
+            self.expect(expr, func, param, arg)?;
+        }
+        Ok(ret)
+    }
+
+    // An element of a list, tuple or string
+    fn element(
+        &mut self,
+        expr: &Expr,
+        what: impl Display,
+        sequence: &Type,
+        index: &Type,
+    ) -> Result<Type, InterpretError> {
+        self.expect(expr, &what, &Type::Num, index)?;
+        match self.resolve(sequence) {
+            Type::List(elem) => Ok(*elem),
+            Type::Str => Ok(Type::Str),
+            Type::Tuple(_) | Type::Var(_) | Type::Any => Ok(Type::Any),
+            found => Err(self.error(
+                expr,
+                format!(
+                    "Expected a list, tuple or string for {}, found {}",
+                    what,
+                    self.show(&found)
+                ),
+            )),
+        }
+    }
+
+    // Binds the names in a pattern to the types of what they'd match in a value of type ty. The
+    // value can be of a different shape than the pattern, which then doesn't match
+    fn pattern(&mut self, pattern: &Pattern, ty: &Type) -> Result<(), InterpretError> {
+        let (patterns, elems, rest) = match (pattern, self.resolve(ty)) {
+            // Not generic, like parameters, since the value came from outside
+            (Pattern::Bind(name), _) => {
+                self.locals.push((name.clone(), vec![], ty.clone()));
+                return Ok(());
+            }
+            (Pattern::Literal(expr), _) => return self.infer(expr).map(|_| ()),
+            (Pattern::List(patterns), Type::List(elem)) => (
+                patterns,
+                vec![*elem.clone(); patterns.len()],
+                Type::List(elem),
+            ),
+            (Pattern::Tuple(patterns), Type::Tuple(elems)) if elems.len() == patterns.len() => {
+                (patterns, elems, Type::Any)
+            }
+            (Pattern::List(patterns) | Pattern::Tuple(patterns), _) => {
+                (patterns, vec![Type::Any; patterns.len()], Type::Any)
+            }
+            (Pattern::Wildcard | Pattern::Rest(_), _) => return Ok(()),
+        };
+        for (pattern, elem) in patterns.iter().zip(elems) {
+            match pattern {
+                Pattern::Rest(Some(name)) => self.locals.push((name.clone(), vec![], rest.clone())),
+                pattern => self.pattern(pattern, &elem)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn builtin_signature(&mut self, func: &Func) -> (Vec<Type>, Type) {
+        let num = || Type::Num;
+        let list = |ty: &Type| Type::List(Box::new(ty.clone()));
+        let function = |params: &[&Type], ret: &Type| {
+            Type::Fn(
+                params.iter().map(|&param| param.clone()).collect(),
+                Box::new(ret.clone()),
+            )
+        };
+        let (a, b) = (self.fresh(), self.fresh());
+        match func {
+            Func::Log | Func::Rotl | Func::Rotr => (vec![num(), num()], num()),
+            Func::Quadr => (vec![num(), num(), num()], list(&num())),
+            Func::Map => (vec![list(&a), function(&[&a], &b)], list(&b)),
+            Func::Sum => (vec![list(&num())], num()),
+            Func::Fold => (vec![list(&a), function(&[&b, &a], &b), b.clone()], b),
+            // Anything can be used as a condition
+            Func::Filter => (vec![list(&a), function(&[&a], &b)], list(&a)),
+            Func::Odd | Func::Even => (vec![num()], Type::Bool),
+            Func::Range => (vec![num(), num()], list(&num())),
+            Func::Min | Func::Max => (vec![list(&a)], a),
+            Func::Now | Func::Today => (vec![], Type::Any),
+            Func::Weekday => (vec![Type::Any], Type::Str),
+            Func::AddBusinessDays => (vec![Type::Any, num()], Type::Any),
+            Func::Strftime => (vec![Type::Any, Type::Str], Type::Str),
+            Func::Tz => (vec![Type::Any, Type::Str], Type::Any),
+            Func::Cast => (vec![num(), Type::Str], num()),
+            Func::Keys => (vec![Type::Any], list(&Type::Str)),
+            Func::Values => (vec![Type::Any], list(&Type::Any)),
+            Func::Entries => (
+                vec![Type::Any],
+                list(&Type::Tuple(vec![Type::Str, Type::Any])),
+            ),
+            Func::Len => (vec![Type::Any], num()),
+            Func::ParseNum => (vec![Type::Str], num()),
+            Func::Upper | Func::Lower | Func::Trim => (vec![Type::Str], Type::Str),
+            Func::Split => (vec![Type::Str, Type::Str], list(&Type::Str)),
+            Func::Join => (vec![list(&a), Type::Str], Type::Str),
+            Func::Replace => (vec![Type::Str, Type::Str, Type::Str], Type::Str),
+            Func::Contains => (vec![Type::Any, Type::Any], Type::Bool),
+            Func::StartsWith => (vec![Type::Str, Type::Str], Type::Bool),
+            Func::Substr => (vec![Type::Str, num(), num()], Type::Str),
+            Func::Chars => (vec![Type::Str], list(&Type::Str)),
+            Func::ToStr => (vec![Type::Any], Type::Str),
+            Func::Format => (vec![Type::Str, Type::Any], Type::Str),
+            _ => (vec![num()], num()),
+        }
+    }
+}
+
+fn variables(ty: &Type, vars: &mut Vec<usize>) {
+    match ty {
+        Type::Var(var) if !vars.contains(var) => vars.push(*var),
+        Type::List(elem) => variables(elem, vars),
+        Type::Tuple(elems) => elems.iter().for_each(|elem| variables(elem, vars)),
+        Type::Fn(params, ret) => {
+            params.iter().for_each(|param| variables(param, vars));
+            variables(ret, vars);
+        }
+        _ => {}
+    }
+}
+
+fn substitute(ty: &Type, vars: &HashMap<usize, Type>) -> Type {
+    match ty {
+        Type::Var(var) => vars.get(var).cloned().unwrap_or(Type::Var(*var)),
+        Type::List(elem) => Type::List(Box::new(substitute(elem, vars))),
+        Type::Tuple(elems) => {
+            Type::Tuple(elems.iter().map(|elem| substitute(elem, vars)).collect())
+        }
+        Type::Fn(params, ret) => Type::Fn(
+            params.iter().map(|param| substitute(param, vars)).collect(),
+            Box::new(substitute(ret, vars)),
+        ),
+        ty => ty.clone(),
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Num => write!(f, "num"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::List(elem) => write!(f, "[{}]", elem),
+            Type::Tuple(elems) => write!(
+                f,
+                "{{{}}}",
+                elems
+                    .iter()
+                    .map(|elem| elem.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Type::Fn(params, ret) => write!(
+                f,
+                "({}) -> {}",
+                params
+                    .iter()
+                    .map(|param| param.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                ret
+            ),
+            // a to z, then a1 and so on
+            Type::Var(var) => match var / 26 {
+                0 => write!(f, "{}", (b'a' + (var % 26) as u8) as char),
+                n => write!(f, "{}{}", (b'a' + (var % 26) as u8) as char, n),
+            },
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpreter::{InterpretError, Interpreter, Value},
+        parse::Parser,
+        Tokenizer,
+    };
+
+    fn run(interpreter: &mut Interpreter, input: &str) -> Result<Value, InterpretError> {
+        let stmt = Parser::new(Tokenizer::new(input)).unwrap().parse().unwrap();
+        interpreter.interpret(stmt)
+    }
+
+    fn signature(interpreter: &mut Interpreter, input: &str, name: &str) -> String {
+        run(interpreter, input).unwrap();
+        let Some(Value::Fn(function)) = interpreter.env().get(name) else {
+            panic!("{} isn't a function", name);
+        };
+        function.signature().to_string()
+    }
+
+    #[test]
+    fn infer_signatures() {
+        let mut interpreter = Interpreter::new();
+        for (input, expected) in [
+            ("let f = |a, b| a + b", "(num, num) -> num"),
+            ("let f = |s| s + \"!\"", "(str) -> str"),
+            ("let f = |x| x", "(a) -> a"),
+            ("let f = |xs, g| map(xs, g)", "([a], (a) -> b) -> [b]"),
+            ("let f = |x, y| if x * 2 > 0 then y else y", "(num, a) -> a"),
+            ("let f = |[h, ..t], n| h + n", "([num], num) -> num"),
+            ("let f = |{x, y}, _| x + y", "(any, a) -> any"),
+            (
+                "fn f(n) = if n <= 1 then 1 else n * f(n - 1)",
+                "(num) -> num",
+            ),
+        ] {
+            assert_eq!(
+                signature(&mut interpreter, input, "f"),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn report_type_errors() {
+        let mut interpreter = Interpreter::new();
+        for input in [
+            "let f = |x| sum([\"a\", x])",
+            "let f = |x| elem(x * 2, 0)",
+            "let f = |x| x + 1; let g = || f(\"a\")",
+            "let f = |x| x; let g = || f(1, 2)",
+            "fn f(n) = n + true",
+            "let f = |x| if x then 1 else \"a\"",
+            "let f = |x| match x + 1 { 0 => \"zero\", n => n }",
+            "let y = if true then 1 else \"a\"",
+            "1 + true",
+        ] {
+            let res = run(&mut interpreter, input);
+            assert!(
+                matches!(res, Err(InterpretError::TypeError(_, _))),
+                "{}: {:?}",
+                input,
+                res
+            );
+        }
+        // Nothing is defined when the check fails
+        assert!(run(&mut interpreter, "let h = |x| x + true; h").is_err());
+        assert!(!interpreter.env().contains_key("h"));
+    }
+
+    #[test]
+    fn signatures_dont_affect_equality() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "let f = |x| x + 1; let g = f").unwrap();
+        let g = interpreter.env().get("g").cloned();
+        // Inferring f's signature leaves it equal to the copy that hasn't inferred its own
+        signature(&mut interpreter, "let f = |x| x + 1", "f");
+        assert_eq!(interpreter.env().get("f").cloned(), g);
+        assert_ne!(
+            run(&mut interpreter, "|x| x + 2").ok(),
+            run(&mut interpreter, "|x| x + 1").ok()
+        );
+    }
+
+    #[test]
+    fn typecheck_setting() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "config typecheck = false").unwrap();
+        assert!(run(&mut interpreter, "let f = |x| x + true").is_ok());
+        assert!(run(&mut interpreter, "f(1)").is_err());
+    }
+
+    #[test]
+    fn report_spans() {
+        let mut interpreter = Interpreter::new();
+        let input = "let f = |x| sum([\"a\"])";
+        let err = run(&mut interpreter, input).unwrap_err();
+        assert!(
+            err.report(input).contains("at column 13\n"),
+            "{}",
+            err.report(input)
+        );
+    }
+}
@@ -5,14 +5,28 @@ use ratatui::{
     widgets::{Block, Borders, Clear, List, ListItem, Padding, Paragraph, Wrap},
 };
 
-use crate::app::{App, Popup};
+use crate::{
+    app::{App, Popup},
+    interpreter::Value,
+};
 
 pub fn render(app: &mut App, f: &mut Frame) {
+    // Syntax errors take up extra lines to underline where they are
+    let result_lines = match &app.err {
+        Some(err) => err.lines().count(),
+        None => {
+            1 + app
+                .warnings
+                .iter()
+                .map(|warning| warning.lines().count())
+                .sum::<usize>()
+        }
+    };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Min(1),
-            Constraint::Length(3),
+            Constraint::Length(result_lines as u16 + 2),
             Constraint::Length(3),
             Constraint::Length(3),
         ])
@@ -47,6 +61,11 @@ pub fn render(app: &mut App, f: &mut Frame) {
                 .env()
                 .iter()
                 .fold(vec![], |mut acc, (i, val)| {
+                    // Functions are shown by their type rather than their body
+                    let val = match val {
+                        Value::Fn(function) => function.signature().to_string(),
+                        val => val.to_string(),
+                    };
                     let list_item = ListItem::new(Line::from(Span::styled(
                         format!(" {}: {}", i, val),
                         Style::default().fg(Color::LightYellow),
@@ -103,8 +122,22 @@ pub fn render(app: &mut App, f: &mut Frame) {
             let (content, color, border_color) = if let Some(msg) = &app.err {
                 (format!("ERROR: {}", msg), Color::Red, Color::Red)
             } else if let Some(msg) = &app.output {
+                let approximation = msg
+                    .approximation()
+                    .map(|approx| format!(" ≈ {}", app.format.float(approx)))
+                    .unwrap_or_default();
+                let warnings = app
+                    .warnings
+                    .iter()
+                    .map(|warning| format!("\nWARNING: {}", warning))
+                    .collect::<String>();
                 (
-                    format!("Result: {}    (Ctrl + c to copy)", msg),
+                    format!(
+                        "Result: {}{}    (Ctrl + c to copy){}",
+                        app.format.value(msg),
+                        approximation,
+                        warnings
+                    ),
                     Color::Green,
                     Color::Green,
                 )
@@ -116,6 +149,7 @@ pub fn render(app: &mut App, f: &mut Frame) {
                 )
             };
             let output_block = Block::default()
+                .title(format!("Format: {} | (Ctrl o) change", app.format))
                 .borders(Borders::ALL)
                 .padding(Padding::horizontal(2))
                 .style(Style::default().fg(border_color));
@@ -171,13 +205,20 @@ log(_base_, _arg_) ln(_arg_)     cbrt(_arg_)   ceil(_arg_)      exp(_arg_)
 degs(_rads_)       rads(_degs_)  round(_arg_)  floor(_arg_)     exp2(_arg_)
 fract(_arg_)       recip(_arg_)  abs(_arg_)    factorial(_arg_) range(_arg_, _arg_)
 elem(_arg_)        min([_arg_])  max([_arg_])  quadr(_arg_, _arg_, _arg_)
+float(_arg_)       re(_arg_)     im(_arg_)     conj(_arg_)      arg(_arg_)
 
 Examples: \"log10(100)\", \"cos(pi)\"
 
+Without parentheses a built-in is a function value eg. map(xs, sqrt)
+[EXPR] |> f(_arg_) passes the value as the first argument eg. xs |> filter(odd) |> sum
+[EXPR].f(_arg_) does the same for built-ins eg. xs.map(sq).sum()
+
 Shortcuts
 ---------
 (Ctrl d) Reset variables
 (Ctrl x) Delete selected expression from history
+(Ctrl o) Cycle the result format (decimal, hex, binary, octal, scientific,
+         engineering, fixed point and thousands separators)
 "
             }
             Popup::Function => {
@@ -192,6 +233,21 @@ Syntax: let [NAME] = |[ARG]..| [BODY]
 Example: let myfun = |a, b| a + b ** 2
          let foo = cos(pi)
 
+Recursive: fn [NAME]([ARG]..) = [BODY], [NAME]([ARG]..) = [BODY]..
+- functions defined together can call themselves and each other
+- calls in tail position don't count towards the max_depth setting
+
+Example: fn fact(n) = if n < 2 then 1 else n * fact(n - 1)
+         fn count(n, acc) = if n == 0 then acc else count(n - 1, acc + 1)
+
+Unpacking: let {[NAME]..} = [EXPR], let [[NAME].., ..[REST]] = [EXPR]
+- takes a tuple or list apart, parameters can do the same
+- it's an error when the value has a different shape or length
+
+Example: let {x, y} = quadr(1, 1, -2)
+         let [a, b, ..rest] = xs
+         map(pairs, |{x, y}| x + y)
+
 Calling: [NAME]([ARG]...)
 - NAME: Name of the function
 - ARG: Argument (comma separated expression)
@@ -203,6 +259,10 @@ Examples:
 
 Undefining variables and functions: undef([ARG]..)
 
+Constant parts of an input, like pi * 2, are worked out before it
+runs, so in a function they're only worked out once when it's
+defined. simplify([EXPR]) shows what's left eg. simplify(|r| pi * r)
+
 If existing functions / variables are used in a custom function
 then a snapshot of them is taken such that even if they are changed
 or redefined, the custom function will use the old values
@@ -213,17 +273,75 @@ or redefined, the custom function will use the old values
 Language Details
 ----------------
 Types
-    - integers 10
-    - floats 12.0
+    - integers 10, 1_000_000, 0xff, 0o755, 0b1010
+    - rationals 1/3 (exact result of dividing integers)
+    - floats 12.0, .5, 6.02e23, 15% (0.15)
+    - SI prefixes 4.7k, 10M, 3n (f, p, n, u, k, M, G, T and P)
+    - decimals 0.1d (exact base 10, rounded to the precision setting)
+    - complex numbers 3 + 4i
+    - fixed width integers 0xffu8, -5i32 (u8 - u64 and i8 - i64)
+    - quantities 5 km/h (a number followed by a unit)
+    - dates @2024-01-15, times @2024-01-15T09:30+02:00 (UTC without an offset)
+    - durations @P1DT2H30M (ISO 8601 weeks, days, hours, minutes and seconds)
     - booleans true
     - strings \"Hello, World!\"
     - lists [1, true, \"Hi\", 2.0]
     - tuples {false, 1, 2}
+    - records {name: \"x\", rate: 0.2}
     - NaN, nil
+Units
+    [EXPR] to [UNIT]
+    eg. 90 km/h to m/s, 70 kg * 9.81 m/s^2 to N
+    - SI and imperial units eg. m, km, ft, mi, kg, lb, s, h, N, J, W, Pa, psi
+    - adding or converting quantities with different dimensions is an error
+    - custom units from variables eg. let furlong = 201.168 m
+Dates and times
+    date - date == duration, date + duration == date
+    eg. @2024-03-01 - @2024-01-01, @2024-01-01 + 36 h, @PT90M to h
+    now(), today()
+    weekday(_date_)
+    add_business_days(_date_, _days_)
+    strftime(_date_, _pattern_) eg. strftime(today(), \"%d %b %Y\")
+    tz(_time_, _offset_) eg. tz(now(), \"+05:30\")
+Fixed width integers
+    cast(_int_, _type_) eg. cast(300, \"u8\") == 44u8
+    rotl(_int_, _bits_), rotr(_int_, _bits_)
+    popcount(_int_), clz(_int_)
+    - mixing different integer types is an error
+Settings
+    config [NAME] = [VALUE]
+    - decimal: read float literals as decimals eg. config decimal = true
+    - precision: decimal places kept by decimals (0 - 28)
+    - rounding: half_even, half_up, half_down, up, down, ceiling or floor
+    - overflow: wrapping, checked or saturating fixed width integers
+    - max_depth: how deeply functions can recurse (1 - 100000)
+    - backend: \"tree\" to walk the syntax tree or \"vm\" to compile to bytecode, both give the same results
+    - typecheck: infer the types of every input, including the functions it defines, and report mistakes early
 Control flow
     if [BOOL_EXPR] then [THEN_EXPR] else [ELSE_EXPR]
     eg. if true then \"foo\" else \"bar\"
+    match [EXPR] { [PATTERN] if [GUARD] => [EXPR], .. } runs the first arm that fits
+    eg. match xs { [] => 0, [h, ..t] if h > 0 => h, _ => -1 }
+    - patterns can be literals, names, _, [lists] and {tuples}, ..rest takes what's left
+    - a warning is shown when an arm can't be reached or a value might not fit any arm
+Statements
+    [STMT]; [STMT] runs both and shows the last result
+    eg. let r = 2; pi * r ** 2
+    { [STMT]; .. [EXPR] } is a block, its variables are gone after it
+    eg. { let a = 1; a + 2 } == 3 ({1, 2} stays a tuple)
+    # starts a comment until the end of the line
+Local variables
+    let [NAME] = [EXPR], .. in [BODY]
+    eg. let a = 2, b = a + 1 in a * b == 6
+    [BODY] where [NAME] = [EXPR], ..
+    eg. let f = |x| y * y where y = x + 1
+    - the names are only visible in the body and are not saved
 Lists
+    [LIST][INDEX], [LIST][START:END:STEP] also work on tuples and strings
+    eg. [1, 2, 3][-1] == 3, [1, 2, 3, 4][::2] == [1, 3], \"hello\"[1:3] == \"el\"
+    - negative indexes count from the end, and any part of a slice can be left out
+    set(_list_, _index_, _value_)
+        eg. set([1, 2, 3], 0, 5) == [5, 2, 3]
     map(_list_, _callback_)
         eg. map([1, 2, 3], |x| x ** 2) == [1, 4, 9]
     filter(_list_, _callback_)
@@ -236,6 +354,27 @@ Lists
         eg. min([1, 2, 3]) == 1
     max(_list_)
         eg. max([1, 2, 3]) == 3
+Records
+    [RECORD].[NAME] gets a field eg. {rate: 0.2}.rate == 0.2
+    {..[RECORD], [NAME]: [EXPR]} is a copy with fields changed or added
+    eg. let r = {a: 1, b: 2}; {..r, b: 3} == {a: 1, b: 3}
+    keys(_record_), values(_record_), entries(_record_)
+        eg. entries({a: 1}) == [{\"a\", 1}]
+    - fields are kept in order of name, and compared that way
+Strings
+    \\n, \\t, \\r, \\0, \\\\, \\\", \\' and \\u{e9} are escapes
+    len, upper, lower, trim, chars, to_str, parse_num
+        eg. len(\"héllo\") == 5, parse_num(\"0xff\") == 255
+    split(_string_, _sep_), join(_list_, _sep_), contains(_haystack_, _needle_)
+        eg. join(split(\"a,b\", \",\"), \"-\") == \"a-b\"
+    replace(_string_, _from_, _to_), starts_with(_string_, _prefix_)
+    substr(_string_, _start_, _len_)
+        eg. substr(\"hello\", -3, 2) == \"ll\"
+    format(_pattern_, _value_) fills {}, {:.2} or {:>8} like Rust
+        eg. format(\"{} = {:.2}\", {\"pi\", pi}) == \"pi = 3.14\"
+    f\"..{[EXPR]:[SPEC]}..\" puts the values of expressions in a string
+        eg. let x = 10; f\"total {x * 1.2:.2} EUR\" == \"total 12.00 EUR\"
+    - the :[SPEC] is optional and the same as in format, {{ and }} are braces
 "
             }
         };
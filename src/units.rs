@@ -0,0 +1,292 @@
+use std::fmt::Display;
+
+use crate::interpreter::InterpretError;
+
+// Exponents of the SI base dimensions: m, kg, s, A, K, mol, cd
+pub type Dims = [i8; 7];
+
+const NONE: Dims = [0, 0, 0, 0, 0, 0, 0];
+const LENGTH: Dims = [1, 0, 0, 0, 0, 0, 0];
+const AREA: Dims = [2, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dims = [3, 0, 0, 0, 0, 0, 0];
+const MASS: Dims = [0, 1, 0, 0, 0, 0, 0];
+const TIME: Dims = [0, 0, 1, 0, 0, 0, 0];
+const CURRENT: Dims = [0, 0, 0, 1, 0, 0, 0];
+const TEMPERATURE: Dims = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dims = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOSITY: Dims = [0, 0, 0, 0, 0, 0, 1];
+const SPEED: Dims = [1, 0, -1, 0, 0, 0, 0];
+const FREQUENCY: Dims = [0, 0, -1, 0, 0, 0, 0];
+const FORCE: Dims = [1, 1, -2, 0, 0, 0, 0];
+const PRESSURE: Dims = [-1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dims = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dims = [2, 1, -3, 0, 0, 0, 0];
+const CHARGE: Dims = [0, 0, 1, 1, 0, 0, 0];
+const VOLTAGE: Dims = [2, 1, -3, -1, 0, 0, 0];
+const RESISTANCE: Dims = [2, 1, -3, -2, 0, 0, 0];
+
+// Symbol, size in SI base units, dimensions
+const UNITS: [(&str, f64, Dims); 60] = [
+    // Length
+    ("m", 1.0, LENGTH),
+    ("km", 1e3, LENGTH),
+    ("cm", 1e-2, LENGTH),
+    ("mm", 1e-3, LENGTH),
+    ("um", 1e-6, LENGTH),
+    ("nm", 1e-9, LENGTH),
+    ("inch", 0.0254, LENGTH),
+    ("ft", 0.3048, LENGTH),
+    ("yd", 0.9144, LENGTH),
+    ("mi", 1609.344, LENGTH),
+    ("nmi", 1852.0, LENGTH),
+    // Area and volume
+    ("ha", 1e4, AREA),
+    ("acre", 4046.8564224, AREA),
+    ("L", 1e-3, VOLUME),
+    ("mL", 1e-6, VOLUME),
+    ("gal", 3.785411784e-3, VOLUME),
+    // Mass
+    ("kg", 1.0, MASS),
+    ("g", 1e-3, MASS),
+    ("mg", 1e-6, MASS),
+    ("t", 1e3, MASS),
+    ("lb", 0.45359237, MASS),
+    ("oz", 0.028349523125, MASS),
+    // Time
+    ("s", 1.0, TIME),
+    ("ms", 1e-3, TIME),
+    ("us", 1e-6, TIME),
+    ("ns", 1e-9, TIME),
+    ("min", 60.0, TIME),
+    ("h", 3600.0, TIME),
+    ("day", 86400.0, TIME),
+    ("week", 604800.0, TIME),
+    ("yr", 31557600.0, TIME),
+    // Other base units. Temperatures are kelvin only as offsets can't be multiplied
+    ("A", 1.0, CURRENT),
+    ("mA", 1e-3, CURRENT),
+    ("K", 1.0, TEMPERATURE),
+    ("mol", 1.0, AMOUNT),
+    ("cd", 1.0, LUMINOSITY),
+    // Derived units
+    ("mph", 0.44704, SPEED),
+    ("kn", 1852.0 / 3600.0, SPEED),
+    ("Hz", 1.0, FREQUENCY),
+    ("kHz", 1e3, FREQUENCY),
+    ("MHz", 1e6, FREQUENCY),
+    ("GHz", 1e9, FREQUENCY),
+    ("N", 1.0, FORCE),
+    ("kN", 1e3, FORCE),
+    ("lbf", 4.4482216152605, FORCE),
+    ("Pa", 1.0, PRESSURE),
+    ("kPa", 1e3, PRESSURE),
+    ("bar", 1e5, PRESSURE),
+    ("atm", 101325.0, PRESSURE),
+    ("psi", 6894.757293168, PRESSURE),
+    ("J", 1.0, ENERGY),
+    ("kJ", 1e3, ENERGY),
+    ("cal", 4.184, ENERGY),
+    ("kcal", 4184.0, ENERGY),
+    ("W", 1.0, POWER),
+    ("kW", 1e3, POWER),
+    ("kWh", 3.6e6, ENERGY),
+    ("C", 1.0, CHARGE),
+    ("V", 1.0, VOLTAGE),
+    ("ohm", 1.0, RESISTANCE),
+];
+
+// A unit is a product of named units raised to integer powers, eg. km/h == [(km, 1), (h, -1)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    // Size of the unit in SI base units
+    pub factor: f64,
+    pub dims: Dims,
+    symbols: Vec<(String, i32)>,
+}
+
+impl Unit {
+    pub fn one() -> Self {
+        Self {
+            factor: 1.0,
+            dims: NONE,
+            symbols: vec![],
+        }
+    }
+
+    pub fn named(name: &str, factor: f64, dims: Dims) -> Self {
+        Self {
+            factor,
+            dims,
+            symbols: vec![(name.to_string(), 1)],
+        }
+    }
+
+    pub fn second() -> Self {
+        Self::named("s", 1.0, TIME)
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        UNITS
+            .iter()
+            .find(|(symbol, _, _)| *symbol == name)
+            .map(|(symbol, factor, dims)| Self::named(symbol, *factor, *dims))
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dims == NONE
+    }
+
+    pub fn mul(&self, rhs: &Self) -> Result<Self, InterpretError> {
+        let mut dims = self.dims;
+        for (lhs, rhs) in dims.iter_mut().zip(rhs.dims) {
+            *lhs = lhs.checked_add(rhs).ok_or_else(too_large)?;
+        }
+        let mut symbols = self.symbols.clone();
+        for (name, power) in &rhs.symbols {
+            match symbols.iter_mut().find(|(symbol, _)| symbol == name) {
+                Some((_, existing)) => {
+                    *existing = existing.checked_add(*power).ok_or_else(too_large)?
+                }
+                None => symbols.push((name.clone(), *power)),
+            }
+        }
+        symbols.retain(|(_, power)| *power != 0);
+        Ok(Self {
+            factor: self.factor * rhs.factor,
+            dims,
+            symbols,
+        })
+    }
+
+    pub fn div(&self, rhs: &Self) -> Result<Self, InterpretError> {
+        self.mul(&rhs.powi(-1)?)
+    }
+
+    pub fn powi(&self, exp: i32) -> Result<Self, InterpretError> {
+        let mut dims = self.dims;
+        for dim in dims.iter_mut() {
+            *dim = i32::from(*dim)
+                .checked_mul(exp)
+                .and_then(|dim| i8::try_from(dim).ok())
+                .ok_or_else(too_large)?;
+        }
+        let symbols = self
+            .symbols
+            .iter()
+            .map(|(name, power)| Some((name.clone(), power.checked_mul(exp)?)))
+            .collect::<Option<_>>()
+            .ok_or_else(too_large)?;
+        Ok(Self {
+            factor: self.factor.powi(exp),
+            dims,
+            symbols,
+        })
+    }
+
+    // Only when every power divides evenly, eg. the square root of m^2 but not of m*km
+    pub fn root(&self, n: i32) -> Option<Self> {
+        if self.dims.iter().any(|dim| i32::from(*dim) % n != 0)
+            || self.symbols.iter().any(|(_, power)| power % n != 0)
+        {
+            return None;
+        }
+        Some(Self {
+            factor: self.factor.powf(1.0 / f64::from(n)),
+            dims: self.dims.map(|dim| dim / n as i8),
+            symbols: self
+                .symbols
+                .iter()
+                .map(|(name, power)| (name.clone(), power / n))
+                .collect(),
+        })
+    }
+}
+
+// Dimensions only go up to the power of an i8, eg. m^127
+fn too_large() -> InterpretError {
+    InterpretError::RuntimeError("Unit power too large".to_string())
+}
+
+// Formats as it would be written, eg. kg*m/s^2
+pub fn format(symbols: &[(String, i32)]) -> String {
+    let symbol = |name: &str, power: i32| {
+        if power == 1 {
+            name.to_string()
+        } else {
+            format!("{}^{}", name, power)
+        }
+    };
+    let numerator = symbols
+        .iter()
+        .filter(|(_, power)| *power > 0)
+        .map(|(name, power)| symbol(name, *power))
+        .collect::<Vec<_>>();
+    let denominator = symbols.iter().filter(|(_, power)| *power < 0);
+    if numerator.is_empty() {
+        denominator
+            .map(|(name, power)| symbol(name, *power))
+            .collect::<Vec<_>>()
+            .join("*")
+    } else {
+        denominator.fold(numerator.join("*"), |acc, (name, power)| {
+            format!("{}/{}", acc, symbol(name, -power))
+        })
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format(&self.symbols))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combine_units() {
+        let km = Unit::builtin("km").unwrap();
+        let h = Unit::builtin("h").unwrap();
+        let speed = km.div(&h).unwrap();
+        assert_eq!(speed.dims, SPEED);
+        assert_eq!(speed.to_string(), "km/h");
+        assert!((speed.factor - 1.0 / 3.6).abs() < 1e-12);
+
+        let area = km.mul(&km).unwrap();
+        assert_eq!(area.to_string(), "km^2");
+        assert!(area.div(&km).unwrap().div(&km).unwrap().is_dimensionless());
+        assert_eq!(h.powi(-1).unwrap().to_string(), "h^-1");
+        assert_eq!(area.root(2), Some(km.clone()));
+        assert_eq!(
+            km.mul(&h)
+                .unwrap()
+                .powi(3)
+                .unwrap()
+                .root(3)
+                .unwrap()
+                .to_string(),
+            "km*h"
+        );
+        assert_eq!(km.root(2), None);
+        assert_eq!(km.mul(&Unit::builtin("m").unwrap()).unwrap().root(2), None);
+    }
+
+    #[test]
+    fn power_overflow() {
+        let m = Unit::builtin("m").unwrap();
+        let too_large = Err(too_large());
+        assert_eq!(m.powi(127).unwrap().dims[0], 127);
+        assert_eq!(m.powi(200), too_large);
+        assert_eq!(m.powi(i32::MIN), too_large);
+        assert_eq!(m.powi(2).unwrap().powi(100), too_large);
+        let big = m.powi(100).unwrap();
+        assert_eq!(big.mul(&big), too_large);
+        assert_eq!(big.div(&big.powi(-1).unwrap()), too_large);
+    }
+
+    #[test]
+    fn unknown_unit() {
+        assert_eq!(Unit::builtin("parsec"), None);
+    }
+}
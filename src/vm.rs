@@ -0,0 +1,384 @@
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    mem,
+    ops::{Neg, Not},
+    rc::Rc,
+};
+
+use crate::{
+    compile::{self, Chunk, Op},
+    interpreter::{
+        convert_to, field, index, operate, slice, unmatched, update, Function, InterpretError,
+        Interpreter, Stmt, Value,
+    },
+    parse::Expr,
+    strings,
+    token::Token,
+};
+
+// Bytecode for the body of a function, compiled the first time the vm calls it
+#[derive(Debug, Clone, Default)]
+pub struct Code(Rc<OnceCell<Rc<Chunk>>>);
+
+// Compiled from the body, so two functions with the same body are still equal
+impl PartialEq for Code {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Code {
+    fn chunk(&self, body: &Expr) -> Rc<Chunk> {
+        self.0
+            .get_or_init(|| Rc::new(compile::function(body)))
+            .clone()
+    }
+}
+
+struct Frame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+    // Where the values of the call start on the stack
+    base: usize,
+    // Number of saved states when the call started, the last of them being the caller's
+    saved: usize,
+    // Number of times the call was replaced by a tail call
+    tail_calls: usize,
+}
+
+struct Vm {
+    // The scope, settings and call depth of the code running
+    state: Interpreter,
+    // States to go back to at the end of a scope or call
+    saved: Vec<Interpreter>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+// Runs a statement, keeping what it defines
+pub fn run(interpreter: &mut Interpreter, stmt: &Stmt) -> Result<Value, InterpretError> {
+    let chunk = Rc::new(compile::statement(stmt));
+    // Taken rather than cloned so that defining variables doesn't copy the scope
+    let state = mem::replace(interpreter, Interpreter::with_env(HashMap::new()));
+    let mut vm = Vm::new(state, chunk);
+    let res = vm.execute();
+    *interpreter = vm.outermost();
+    res
+}
+
+pub fn eval(interpreter: &Interpreter, expr: &Expr) -> Result<Value, InterpretError> {
+    Vm::new(interpreter.clone(), Rc::new(compile::expression(expr))).execute()
+}
+
+// Calls a function from outside the vm, eg. from map
+pub fn call(
+    function: &Function,
+    args: Vec<Value>,
+    caller: &Interpreter,
+) -> Result<Value, InterpretError> {
+    let mut vm = Vm {
+        state: caller.clone(),
+        saved: vec![],
+        stack: vec![],
+        frames: vec![],
+    };
+    vm.call(function, args)?;
+    vm.execute()
+}
+
+impl Vm {
+    fn new(state: Interpreter, chunk: Rc<Chunk>) -> Self {
+        Self {
+            state,
+            saved: vec![],
+            stack: vec![],
+            frames: vec![Frame {
+                chunk,
+                ip: 0,
+                base: 0,
+                saved: 0,
+                tail_calls: 0,
+            }],
+        }
+    }
+
+    // The state the vm started with, even if it stopped inside a scope or call
+    fn outermost(self) -> Interpreter {
+        self.saved.into_iter().next().unwrap_or(self.state)
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Stack underflow")
+    }
+
+    fn top(&self) -> &Value {
+        self.stack.last().expect("Stack underflow")
+    }
+
+    fn pop_n(&mut self, n: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - n)
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), InterpretError> {
+        let value = self.state.normalize(value)?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn fields(&mut self, names: &[String]) -> Vec<(String, Value)> {
+        let values = self.pop_n(names.len());
+        names.iter().cloned().zip(values).collect()
+    }
+
+    fn call(&mut self, function: &Function, args: Vec<Value>) -> Result<(), InterpretError> {
+        self.state.check_depth()?;
+        let callee = function.enter(args, &self.state)?;
+        self.saved.push(mem::replace(&mut self.state, callee));
+        self.frames.push(Frame {
+            chunk: function.code().chunk(function.body()),
+            ip: 0,
+            base: self.stack.len(),
+            saved: self.saved.len(),
+            tail_calls: 0,
+        });
+        Ok(())
+    }
+
+    fn execute(&mut self) -> Result<Value, InterpretError> {
+        let frame = self.frames.last().expect("Nothing to run");
+        let mut chunk = frame.chunk.clone();
+        let mut ip = frame.ip;
+        loop {
+            let op = &chunk.code[ip];
+            ip += 1;
+            match op {
+                Op::Const(index) => self.push(chunk.constants[*index].clone())?,
+                Op::Float(float) => {
+                    let value = self.state.float(*float)?;
+                    self.push(value)?;
+                }
+                Op::Load(name) => {
+                    let value = self.state.lookup(&chunk.names[*name])?;
+                    self.push(value)?;
+                }
+                Op::Define(name) => {
+                    let value = self.top().clone();
+                    self.state.define(chunk.names[*name].clone(), value);
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.push(operate(left, operator, right)?)?;
+                }
+                Op::Unary(operator) => {
+                    let value = self.pop();
+                    let value = match operator {
+                        Token::Not => value.not()?,
+                        Token::Minus => value.neg()?,
+                        _ => unreachable!(),
+                    };
+                    self.push(value)?;
+                }
+                Op::Quantity(unit) => {
+                    let value = self.pop().to_float()?;
+                    let unit = self.state.unit(&chunk.units[*unit])?;
+                    self.push(Value::quantity(value, unit))?;
+                }
+                Op::Convert(unit) => {
+                    let target = self.state.unit(&chunk.units[*unit])?;
+                    let value = self.pop();
+                    self.push(convert_to(value, target)?)?;
+                }
+                Op::List(len) => {
+                    let elems = self.pop_n(*len);
+                    self.stack.push(Value::List(elems));
+                }
+                Op::Tuple(len) => {
+                    let elems = self.pop_n(*len);
+                    self.stack.push(Value::Tuple(elems));
+                }
+                Op::Record(fields) => {
+                    let fields = self.fields(&chunk.fields[*fields]);
+                    self.stack.push(Value::Record(fields.into_iter().collect()));
+                }
+                Op::Update(fields) => {
+                    let fields = self.fields(&chunk.fields[*fields]);
+                    let record = self.pop();
+                    self.stack.push(update(record, fields)?);
+                }
+                Op::Field(name) => {
+                    let record = self.pop();
+                    self.stack.push(field(record, &chunk.names[*name])?);
+                }
+                Op::Interpolate(pattern, len) => {
+                    let values = self.pop_n(*len);
+                    let string = strings::format(&chunk.names[*pattern], &values)?;
+                    self.stack.push(Value::String(string));
+                }
+                Op::Match(pattern) => {
+                    let bindings = self.state.matches(&chunk.patterns[*pattern], self.top())?;
+                    self.stack.push(Value::Bool(bindings.is_some()));
+                    for (name, value) in bindings.into_iter().flatten() {
+                        self.state.define(name, value);
+                    }
+                }
+                Op::Unmatched => return Err(unmatched(self.top())),
+                Op::Unpack(pattern) => {
+                    let bindings = self.state.unpack(&chunk.patterns[*pattern], self.top())?;
+                    for (name, value) in bindings {
+                        self.state.define(name, value);
+                    }
+                }
+                Op::Index => {
+                    let i = self.pop();
+                    let value = self.pop();
+                    self.stack.push(index(value, &i)?);
+                }
+                Op::Slice(given) => {
+                    let mut bounds = [None, None, None];
+                    for (bound, _) in bounds
+                        .iter_mut()
+                        .zip(given)
+                        .filter(|(_, given)| **given)
+                        .rev()
+                    {
+                        *bound = Some(self.pop());
+                    }
+                    let value = self.pop();
+                    self.stack.push(slice(value, bounds)?);
+                }
+                Op::Closure(function) => {
+                    let (params, body) = &chunk.functions[*function];
+                    let function = self.state.closure(params, body);
+                    self.stack.push(Value::Fn(function));
+                }
+                // Built-in results aren't normalized, same as in the interpreter
+                Op::Builtin(func, args) => {
+                    let args = self.pop_n(*args);
+                    let value = self.state.call_builtin(func, args)?;
+                    self.stack.push(value);
+                }
+                Op::Callee(args) => {
+                    Function::callee(self.top().clone(), *args)?;
+                }
+                Op::Call(args) => {
+                    let args = self.pop_n(*args);
+                    let function = Function::callee(self.pop(), args.len())?;
+                    self.frames.last_mut().expect("No caller").ip = ip;
+                    self.call(&function, args)?;
+                    chunk = function.code().chunk(function.body());
+                    ip = 0;
+                }
+                Op::TailCall(args) => {
+                    let args = self.pop_n(*args);
+                    let function = Function::callee(self.pop(), args.len())?;
+                    let frame = self.frames.last_mut().expect("No caller");
+                    frame.tail_calls += 1;
+                    self.state.check_tail_calls(frame.tail_calls)?;
+                    // Entered from the caller of the function being replaced, so the depth
+                    // stays the same
+                    let callee = function.enter(args, &self.saved[frame.saved - 1])?;
+                    self.saved.truncate(frame.saved);
+                    self.stack.truncate(frame.base);
+                    self.state = callee;
+                    chunk = function.code().chunk(function.body());
+                    frame.chunk = chunk.clone();
+                    ip = 0;
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("No frame to return from");
+                    self.stack.truncate(frame.base);
+                    if frame.saved > 0 {
+                        self.saved.truncate(frame.saved);
+                        self.state = self.saved.pop().expect("No caller state");
+                    }
+                    let Some(caller) = self.frames.last() else {
+                        return Ok(value);
+                    };
+                    chunk = caller.chunk.clone();
+                    ip = caller.ip;
+                    self.stack.push(value);
+                }
+                Op::Jump(target) => ip = *target,
+                Op::JumpUnless(target) => {
+                    if !self.pop().truthy() {
+                        ip = *target;
+                    }
+                }
+                Op::PushScope => {
+                    let scope = self.state.child();
+                    self.saved.push(mem::replace(&mut self.state, scope));
+                }
+                Op::PopScope => self.state = self.saved.pop().expect("No scope to leave"),
+                Op::Undef(names) => {
+                    self.state.undef(&chunk.undefs[*names]);
+                    self.stack.push(Value::Unit);
+                }
+                Op::Config(name) => {
+                    let value = self.top().clone();
+                    self.state.configure(&chunk.names[*name], &value)?;
+                }
+                Op::Fns(group) => {
+                    let value = self.state.define_fns(chunk.groups[*group].clone());
+                    self.stack.push(value);
+                }
+                Op::Simplify(expr) => {
+                    let value = self.state.simplify(chunk.exprs[*expr].clone());
+                    self.stack.push(value);
+                }
+                Op::Unit => self.stack.push(Value::Unit),
+                Op::Normalize => {
+                    let value = self.pop();
+                    self.push(value)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse::Parser, Tokenizer};
+
+    fn vm() -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .configure("backend", &Value::String("vm".to_string()))
+            .unwrap();
+        interpreter
+    }
+
+    fn input(interpreter: &mut Interpreter, input: &str) -> Result<Value, InterpretError> {
+        let stmt = Parser::new(Tokenizer::new(input)).unwrap().parse().unwrap();
+        run(interpreter, &stmt)
+    }
+
+    #[test]
+    fn definitions_survive_errors() {
+        let mut interpreter = vm();
+        assert!(input(&mut interpreter, "let a = 2; { let b = 3; c }").is_err());
+        assert_eq!(input(&mut interpreter, "a"), Ok(Value::Int(2)));
+        assert!(input(&mut interpreter, "b").is_err());
+        assert_eq!(interpreter.env().get("ans"), Some(&Value::Int(2)));
+    }
+
+    #[test]
+    fn callbacks_share_compiled_code() {
+        let mut interpreter = vm();
+        input(&mut interpreter, "let f = |x| x * 2").unwrap();
+        assert_eq!(
+            input(&mut interpreter, "map([1, 2], f)"),
+            Ok(Value::List(vec![Value::Int(2), Value::Int(4)]))
+        );
+        let Some(Value::Fn(f)) = interpreter.env().get("f") else {
+            panic!("f isn't a function");
+        };
+        assert!(f.code().0.get().is_some());
+    }
+}